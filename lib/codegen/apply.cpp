@@ -0,0 +1,34 @@
+#include "apply.h"
+
+Apply::Apply(Location location, unique_ptr<Expr> function, vector<unique_ptr<Expr>> args, ValueType returnType)
+    : Expr(ApplyExpr, location), function(std::move(function)), args(std::move(args)), returnType(returnType){};
+
+const unique_ptr<Expr> &Apply::getFunction()
+{
+    return function;
+}
+
+const vector<unique_ptr<Expr>> &Apply::getArgs()
+{
+    return args;
+}
+
+const ValueType &Apply::getReturnType()
+{
+    return returnType;
+}
+
+void Apply::setFunction(unique_ptr<Expr> applyFunction)
+{
+    function = std::move(applyFunction);
+}
+
+void Apply::setArgs(vector<unique_ptr<Expr>> applyArgs)
+{
+    args = std::move(applyArgs);
+}
+
+void Apply::setReturnType(ValueType applyReturnType)
+{
+    returnType = applyReturnType;
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+#ifndef __APPLY_H
+#define __APPLY_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class Apply : public Expr
+    {
+        unique_ptr<Expr> function;
+        vector<unique_ptr<Expr>> args;
+        ValueType returnType;
+
+    public:
+        Apply(Location location, unique_ptr<Expr> function, vector<unique_ptr<Expr>> args, ValueType returnType);
+
+        const unique_ptr<Expr> &getFunction();
+        const vector<unique_ptr<Expr>> &getArgs();
+        const ValueType &getReturnType();
+
+        void setFunction(unique_ptr<Expr> applyFunction);
+        void setArgs(vector<unique_ptr<Expr>> applyArgs);
+        void setReturnType(ValueType applyReturnType);
+    };
+}
+
+#endif
\ No newline at end of file
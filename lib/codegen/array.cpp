@@ -0,0 +1,24 @@
+#include "array.h"
+
+ArrayLiteral::ArrayLiteral(Location location, vector<unique_ptr<Expr>> elements, ValueType elementType)
+    : Expr(ArrayLit, location), elements(std::move(elements)), elementType(elementType){};
+
+const vector<unique_ptr<Expr>> &ArrayLiteral::getElements()
+{
+    return elements;
+}
+
+const ValueType &ArrayLiteral::getElementType()
+{
+    return elementType;
+}
+
+void ArrayLiteral::setElements(vector<unique_ptr<Expr>> arrElements)
+{
+    elements = std::move(arrElements);
+}
+
+void ArrayLiteral::setElementType(ValueType arrElementType)
+{
+    elementType = arrElementType;
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+#ifndef __ARRAY_LITERAL_H
+#define __ARRAY_LITERAL_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class ArrayLiteral : public Expr
+    {
+        vector<unique_ptr<Expr>> elements;
+        ValueType elementType;
+
+    public:
+        ArrayLiteral(Location location, vector<unique_ptr<Expr>> elements, ValueType elementType);
+
+        const vector<unique_ptr<Expr>> &getElements();
+        const ValueType &getElementType();
+
+        void setElements(vector<unique_ptr<Expr>> arrElements);
+        void setElementType(ValueType arrElementType);
+    };
+}
+
+#endif
\ No newline at end of file
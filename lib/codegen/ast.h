@@ -18,10 +18,21 @@ namespace ast
         ChainExpr,
         FnCall,
         FnDef,
+        ExternDecl,
         Num,
         Ref,
         Str,
-        WhenExpr
+        WhenExpr,
+        ArrayLit,
+        IndexExpr,
+        LengthExpr,
+        ForExpr,
+        RangeExpr,
+        LetExpr,
+        UnaryOp,
+        BoolLit,
+        ClosureExpr,
+        ApplyExpr
     };
 
     enum NumberType_en
@@ -58,6 +69,57 @@ namespace ast
         NumberType_un value;
     };
 
+    // mirrors `FFISafeTypeKind` on the rust side
+    enum class ValueKind
+    {
+        Int,
+        Float,
+        String,
+        Bool,
+        Function
+    };
+
+    // `depth` levels of arrays around `kind`, so [[Int]] is {Int, 2}
+    struct ValueType
+    {
+        ValueKind kind;
+        size_t depth;
+    };
+
+    // mirrors `ForKind` on the rust side
+    enum class LoopKind
+    {
+        Collect,
+        Sum
+    };
+
+    // mirrors `Operator` on the rust side, in the same order
+    enum class OpKind
+    {
+        Power,
+        Multiply,
+        Divide,
+        Remainder,
+        Add,
+        Subtract,
+        Less,
+        Greater,
+        LessEqual,
+        GreaterEqual,
+        Equal,
+        NotEqual,
+        And,
+        Or
+    };
+
+    // mirrors `UnaryOperator` on the rust side
+    enum class UnaryKind
+    {
+        Negate,
+        Plus,
+        Not
+    };
+
     class Expr
     {
         Structure structure;
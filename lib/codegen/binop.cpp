@@ -1,9 +1,9 @@
 #include "binop.h"
 
-BinaryOperation::BinaryOperation(Location location, uint8_t op, unique_ptr<Expr> left, unique_ptr<Expr> right)
+BinaryOperation::BinaryOperation(Location location, OpKind op, unique_ptr<Expr> left, unique_ptr<Expr> right)
     : Expr(BinaryOp, location), op(op), left(std::move(left)), right(std::move(right)){};
 
-const uint8_t &BinaryOperation::getOp()
+const OpKind &BinaryOperation::getOp()
 {
     return op;
 }
@@ -18,9 +18,9 @@ const unique_ptr<Expr> &BinaryOperation::getRight()
     return right;
 }
 
-void BinaryOperation::setOp(uint8_t opInt)
+void BinaryOperation::setOp(OpKind opKind)
 {
-    op = opInt;
+    op = opKind;
 }
 
 void BinaryOperation::setLeft(unique_ptr<Expr> leftExpr)
@@ -7,18 +7,18 @@ namespace ast
 {
     class BinaryOperation : public Expr
     {
-        uint8_t op;
+        OpKind op;
         unique_ptr<Expr> left;
         unique_ptr<Expr> right;
 
     public:
-        BinaryOperation(Location location, uint8_t op, unique_ptr<Expr> left, unique_ptr<Expr> right);
+        BinaryOperation(Location location, OpKind op, unique_ptr<Expr> left, unique_ptr<Expr> right);
 
-        const uint8_t &getOp();
+        const OpKind &getOp();
         const unique_ptr<Expr> &getLeft();
         const unique_ptr<Expr> &getRight();
 
-        void setOp(uint8_t opInt);
+        void setOp(OpKind opKind);
         void setLeft(unique_ptr<Expr> leftExpr);
         void setRight(unique_ptr<Expr> rightExpr);
     };
@@ -0,0 +1,14 @@
+#include "bool.h"
+
+BoolLiteral::BoolLiteral(Location location, bool value)
+    : Expr(BoolLit, location), value(value){};
+
+const bool &BoolLiteral::getValue()
+{
+    return value;
+}
+
+void BoolLiteral::setValue(bool val)
+{
+    value = val;
+}
\ No newline at end of file
@@ -0,0 +1,22 @@
+#ifndef __BOOL_LITERAL_H
+#define __BOOL_LITERAL_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class BoolLiteral : public Expr
+    {
+        bool value;
+
+    public:
+        BoolLiteral(Location location, bool value);
+
+        const bool &getValue();
+
+        void setValue(bool val);
+    };
+
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,24 @@
+#include "closure.h"
+
+Closure::Closure(Location location, string name, vector<string> captures)
+    : Expr(ClosureExpr, location), name(name), captures(captures){};
+
+const string &Closure::getName()
+{
+    return name;
+}
+
+const vector<string> &Closure::getCaptures()
+{
+    return captures;
+}
+
+void Closure::setName(string fnName)
+{
+    name = fnName;
+}
+
+void Closure::setCaptures(vector<string> closureCaptures)
+{
+    captures = closureCaptures;
+}
\ No newline at end of file
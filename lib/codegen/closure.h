@@ -0,0 +1,24 @@
+#ifndef __CLOSURE_H
+#define __CLOSURE_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class Closure : public Expr
+    {
+        string name;
+        vector<string> captures;
+
+    public:
+        Closure(Location location, string name, vector<string> captures);
+
+        const string &getName();
+        const vector<string> &getCaptures();
+
+        void setName(string fnName);
+        void setCaptures(vector<string> closureCaptures);
+    };
+}
+
+#endif
\ No newline at end of file
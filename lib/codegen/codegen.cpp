@@ -35,7 +35,7 @@ void CodeGen::printError()
     error->print();
 }
 
-int CodeGen::runPass(string outFile)
+int CodeGen::runPass(string outFile, OptimizationLevel level, OutputKind kind)
 {
     std::error_code ec;
     raw_fd_ostream dest(outFile, ec, sys::fs::OF_None);
@@ -58,23 +58,96 @@ int CodeGen::runPass(string outFile)
     PB.registerLoopAnalyses(LAM);
     PB.crossRegisterProxies(LAM, FAM, CGAM, MAM);
 
-    ModulePassManager MPM = PB.buildPerModuleDefaultPipeline(OptimizationLevel::O2);
+    // the default pipeline won't take O0, it has its own
+    ModulePassManager MPM = level == OptimizationLevel::O0 ? PB.buildO0DefaultPipeline(level)
+                                                           : PB.buildPerModuleDefaultPipeline(level);
     MPM.run(*llvmModule, MAM);
 
-    WriteBitcodeToFile(*llvmModule, dest);
+    switch (kind)
+    {
+    case OutputKind::LLVMIR:
+        llvmModule->print(dest, nullptr);
+        break;
+    case OutputKind::Bitcode:
+        WriteBitcodeToFile(*llvmModule, dest);
+        break;
+    case OutputKind::Object:
+    {
+        legacy::PassManager pass;
+        if (targetMachine->addPassesToEmitFile(pass, dest, nullptr, CGFT_ObjectFile))
+        {
+            errs() << "the target can't emit object files";
+            return 1;
+        }
+        pass.run(*llvmModule);
+        break;
+    }
+    }
     dest.flush();
 
-    llvmModule->print(dbgs(), nullptr);
-
     return 0;
 }
 
+// `--entry` picks which function gets exported as `entry`
+GlobalValue *CodeGen::exportEntry(string name)
+{
+    Function *fn = loadFunction(name);
+    if (!fn)
+    {
+        addToError("while exporting the entry point", internal_err);
+        return nullptr;
+    }
+    if (name == "entry")
+        return fn;
+    return GlobalAlias::create("entry", fn);
+}
+
 // taken from llvm examples (like most things)
-AllocaInst *CodeGen::createEntryBlockAlloca(Function *function, StringRef varName)
+AllocaInst *CodeGen::createEntryBlockAlloca(Function *function, StringRef varName, Type *type)
 {
     IRBuilder<> TmpB(&function->getEntryBlock(),
                      function->getEntryBlock().begin());
-    return TmpB.CreateAlloca(Type::getDoubleTy(*context), nullptr, varName);
+    return TmpB.CreateAlloca(type, nullptr, varName);
+}
+
+Type *CodeGen::getType(ValueType type)
+{
+    if (type.depth > 0)
+        return getArrayType(getType({type.kind, type.depth - 1}));
+
+    switch (type.kind)
+    {
+    case ValueKind::Int:
+        return Type::getInt64Ty(*context);
+    case ValueKind::Bool:
+        return Type::getInt1Ty(*context);
+    case ValueKind::Function:
+        return getClosureType();
+    // strings are still a todo, see genStringLiteral
+    default:
+        return Type::getDoubleTy(*context);
+    }
+}
+
+// arrays are passed around as { i64 length, element *data }
+Type *CodeGen::getArrayType(Type *elementType)
+{
+    for (auto &[arrayType, element] : arrayElements)
+        if (element == elementType)
+            return arrayType;
+
+    StructType *arrayType = StructType::create(
+        *context, {Type::getInt64Ty(*context), PointerType::getUnqual(elementType)}, "array");
+    arrayElements[arrayType] = elementType;
+    return arrayType;
+}
+
+// closures are passed around as { code, env }, where the code takes the env before
+// its arguments (see getTrampoline) and the env is null if nothing was captured
+Type *CodeGen::getClosureType()
+{
+    PointerType *ptr = PointerType::getUnqual(*context);
+    return StructType::get(*context, {ptr, ptr});
 }
 
 Function *CodeGen::loadFunction(string name)
@@ -92,18 +165,41 @@ Value *CodeGen::genericGen(const unique_ptr<Expr> &expr)
     {
     case BinaryOp:
         return genBinaryOperation(derived(BinaryOperation) expr);
+    case UnaryOp:
+        return genUnaryOperation(derived(UnaryOperation) expr);
     case ChainExpr:
         return genChainExpression(derived(ChainExpression) expr);
     case FnCall:
         return genFunctionCall(derived(FunctionCall) expr);
     case FnDef:
         return genFunctionDefinition(derived(FunctionDefinition) expr);
+    case ExternDecl:
+        return genExternDeclaration(derived(ExternDeclaration) expr);
     case Num:
         return genNumberLiteral(derived(NumberLiteral) expr);
     case Ref:
         return genVariableRef(derived(VariableRef) expr);
     case Str:
         return genStringLiteral(derived(ast::StringLiteral) expr);
+    case BoolLit:
+        return genBoolLiteral(derived(BoolLiteral) expr);
+    case ArrayLit:
+        return genArrayLiteral(derived(ArrayLiteral) expr);
+    case IndexExpr:
+        return genIndexExpression(derived(IndexExpression) expr);
+    case LengthExpr:
+        return genArrayLength(derived(ArrayLength) expr);
+    case ForExpr:
+        return genForExpression(derived(ForExpression) expr);
+    case LetExpr:
+        return genLetExpression(derived(LetExpression) expr);
+    case ClosureExpr:
+        return genClosure(derived(Closure) expr);
+    case ApplyExpr:
+        return genApply(derived(Apply) expr);
+    case RangeExpr:
+        addToError("ranges can only be looped over", expr->getLocation());
+        break;
     case WhenExpr:
         addToError("dangling 'when' not allowed", expr->getLocation());
         break;
@@ -114,12 +210,30 @@ Value *CodeGen::genericGen(const unique_ptr<Expr> &expr)
     return nullptr;
 }
 
-Function *CodeGen::genFunctionDefinition(const unique_ptr<FunctionDefinition> &def)
+// every definition is declared before any body is generated, so a body can use
+// definitions further down, like a lambda calling the function it was lifted out of
+void CodeGen::declare(const unique_ptr<Expr> &expr)
 {
+    if (expr->getStructure() == FnDef)
+        declareFunction(derived(FunctionDefinition) expr);
+}
+
+Function *CodeGen::declareFunction(const unique_ptr<FunctionDefinition> &def)
+{
+    if (Function *existing = llvmModule->getFunction(def->getName()))
+        return existing;
+
     // define argument types and return type
-    vector<Type *> argT(def->getArgs().size(), Type::getDoubleTy(*context));
-    FunctionType *fnType = FunctionType::get(Type::getDoubleTy(*context), argT, false);
-    Function *fn = Function::Create(fnType, Function::ExternalLinkage, def->getName(), llvmModule.get());
+    vector<Type *> argT;
+    for (auto argType : def->getArgTypes())
+        argT.push_back(getType(argType));
+    FunctionType *fnType = FunctionType::get(getType(def->getReturnType()), argT, false);
+    return Function::Create(fnType, Function::ExternalLinkage, def->getName(), llvmModule.get());
+}
+
+Function *CodeGen::genFunctionDefinition(const unique_ptr<FunctionDefinition> &def)
+{
+    Function *fn = declareFunction(def);
     BasicBlock *block = BasicBlock::Create(*context, "entry", fn);
     builder->SetInsertPoint(block);
     namedValues.clear();
@@ -130,7 +244,7 @@ Function *CodeGen::genFunctionDefinition(const unique_ptr<FunctionDefinition> &d
     {
         string name = def->getArgs()[i++];
         arg.setName(name);
-        AllocaInst *alloca = createEntryBlockAlloca(fn, name);
+        AllocaInst *alloca = createEntryBlockAlloca(fn, name, arg.getType());
         builder->CreateStore(&arg, alloca);
         namedValues[name] = alloca;
     }
@@ -142,11 +256,27 @@ Function *CodeGen::genFunctionDefinition(const unique_ptr<FunctionDefinition> &d
         return fn;
     }
 
-    fn->eraseFromParent();
+    // other bodies might already call it, so only the body goes
+    fn->deleteBody();
     addToError("while parsing function definition", def->getLocation());
     return nullptr;
 }
 
+Function *CodeGen::genExternDeclaration(const unique_ptr<ExternDeclaration> &decl)
+{
+    // just the signature, the symbol itself gets resolved when the output is linked
+    vector<Type *> argT;
+    for (auto argType : decl->getArgTypes())
+        argT.push_back(getType(argType));
+    FunctionType *fnType = FunctionType::get(getType(decl->getReturnType()), argT, false);
+    Function *fn = Function::Create(fnType, Function::ExternalLinkage, decl->getName(), llvmModule.get());
+
+    unsigned int i = 0;
+    for (auto &arg : fn->args())
+        arg.setName(decl->getArgs()[i++]);
+    return fn;
+}
+
 Value *CodeGen::genNumberLiteral(const unique_ptr<NumberLiteral> &num)
 {
     auto data = num->getValue();
@@ -170,6 +300,11 @@ Value *CodeGen::genStringLiteral(const unique_ptr<ast::StringLiteral> &string)
     return ConstantFP::get(*context, APFloat(0.0));
 }
 
+Value *CodeGen::genBoolLiteral(const unique_ptr<BoolLiteral> &boolean)
+{
+    return ConstantInt::getBool(*context, boolean->getValue());
+}
+
 Value *CodeGen::getPredFCmp(const unique_ptr<WhenExpression> &when)
 {
     Value *predicate = genericGen(when->getPredicate());
@@ -178,7 +313,8 @@ Value *CodeGen::getPredFCmp(const unique_ptr<WhenExpression> &when)
         addToError("while parsing predicate!\n", when->getLocation());
         return nullptr;
     }
-    return builder->CreateFCmpONE(predicate, ConstantFP::get(*context, APFloat(0.0)), "ifcond");
+    // typeck only lets Bools through, so it's already an i1
+    return predicate;
 }
 
 // todo
@@ -251,7 +387,7 @@ Value *CodeGen::genChainExpression(const unique_ptr<ChainExpression> &chain)
 
     parent->insert(parent->end(), merge);
     builder->SetInsertPoint(merge);
-    PHINode *phi = builder->CreatePHI(Type::getDoubleTy(*context), 2, "iftmp");
+    PHINode *phi = builder->CreatePHI(results[0]->getType(), blocks.size(), "iftmp");
 
     int i = 0;
     for (auto block : blocks)
@@ -265,6 +401,10 @@ Value *CodeGen::genChainExpression(const unique_ptr<ChainExpression> &chain)
 // todo
 Value *CodeGen::genBinaryOperation(const unique_ptr<BinaryOperation> &bin)
 {
+    // these can't generate the right side up front
+    if (bin->getOp() == OpKind::And || bin->getOp() == OpKind::Or)
+        return genLogicalOperation(bin);
+
     Value *left = genericGen(bin->getLeft());
     Value *right = genericGen(bin->getRight());
 
@@ -280,45 +420,265 @@ Value *CodeGen::genBinaryOperation(const unique_ptr<BinaryOperation> &bin)
         return nullptr;
     }
 
+    if (left->getType() != right->getType())
+    {
+        addToError("mismatched operand types", bin->getLocation());
+        return nullptr;
+    }
+
+    if (bin->getOp() == OpKind::Power)
+        return genPower(left, right, bin->getLocation());
+
+    if (left->getType()->isIntegerTy())
+        return genIntBinaryOperation(bin->getOp(), left, right, bin->getLocation());
+
     switch (bin->getOp())
     {
-    case 1:
+    case OpKind::Multiply:
         return builder->CreateFMul(left, right, "multmp");
-    case 2:
+    case OpKind::Divide:
         return builder->CreateFDiv(left, right, "divtmp");
-    case 3:
+    case OpKind::Remainder:
         return builder->CreateFRem(left, right, "remtmp");
-    case 4:
+    case OpKind::Add:
         return builder->CreateFAdd(left, right, "addtmp");
-    case 5:
+    case OpKind::Subtract:
         return builder->CreateFSub(left, right, "subtmp");
 
-    // boolean operators
-    case 6:
-        left = builder->CreateFCmpULT(left, right, "ulttmp");
-        break;
-    case 7:
-        left = builder->CreateFCmpUGT(left, right, "ugttmp");
-        break;
-    case 10:
-        left = builder->CreateFCmpULE(left, right, "uletmp");
-        break;
-    case 11:
-        left = builder->CreateFCmpUGE(left, right, "ugetmp");
-        break;
-    case 12:
-        left = builder->CreateFCmpUEQ(left, right, "ueqtmp");
-        break;
-    case 13:
-        left = builder->CreateFCmpUNE(left, right, "unetmp");
-        break;
+    // comparisons give an i1, which is what a Bool is
+    case OpKind::Less:
+        return builder->CreateFCmpULT(left, right, "ulttmp");
+    case OpKind::Greater:
+        return builder->CreateFCmpUGT(left, right, "ugttmp");
+    case OpKind::LessEqual:
+        return builder->CreateFCmpULE(left, right, "uletmp");
+    case OpKind::GreaterEqual:
+        return builder->CreateFCmpUGE(left, right, "ugetmp");
+    case OpKind::Equal:
+        return builder->CreateFCmpUEQ(left, right, "ueqtmp");
+    case OpKind::NotEqual:
+        return builder->CreateFCmpUNE(left, right, "unetmp");
     default:
         addToError("unknown operator", bin->getLocation());
         return nullptr;
     }
+}
+
+Value *CodeGen::genIntBinaryOperation(OpKind op, Value *left, Value *right, Location location)
+{
+    switch (op)
+    {
+    case OpKind::Multiply:
+        return builder->CreateMul(left, right, "multmp");
+    case OpKind::Divide:
+        genDivisorCheck(left, right);
+        return builder->CreateSDiv(left, right, "divtmp");
+    case OpKind::Remainder:
+        genDivisorCheck(left, right);
+        return builder->CreateSRem(left, right, "remtmp");
+    case OpKind::Add:
+        return builder->CreateAdd(left, right, "addtmp");
+    case OpKind::Subtract:
+        return builder->CreateSub(left, right, "subtmp");
+
+    // comparisons give an i1, which is what a Bool is
+    case OpKind::Less:
+        return builder->CreateICmpSLT(left, right, "slttmp");
+    case OpKind::Greater:
+        return builder->CreateICmpSGT(left, right, "sgttmp");
+    case OpKind::LessEqual:
+        return builder->CreateICmpSLE(left, right, "sletmp");
+    case OpKind::GreaterEqual:
+        return builder->CreateICmpSGE(left, right, "sgetmp");
+    case OpKind::Equal:
+        return builder->CreateICmpEQ(left, right, "eqtmp");
+    case OpKind::NotEqual:
+        return builder->CreateICmpNE(left, right, "netmp");
+    default:
+        addToError("unknown operator", location);
+        return nullptr;
+    }
+}
+
+// the right side gets its own block that's skipped when the left side already decides it
+Value *CodeGen::genLogicalOperation(const unique_ptr<BinaryOperation> &bin)
+{
+    bool isAnd = bin->getOp() == OpKind::And;
+    Value *left = genericGen(bin->getLeft());
+    if (!left)
+    {
+        addToError("while parsing left side of binary operation", bin->getLeft()->getLocation());
+        return nullptr;
+    }
+
+    BasicBlock *leftEnd = builder->GetInsertBlock();
+    Function *parent = leftEnd->getParent();
+    BasicBlock *rightStart = BasicBlock::Create(*context, isAnd ? "andright" : "orright", parent);
+    BasicBlock *merge = BasicBlock::Create(*context, isAnd ? "andjoin" : "orjoin");
+
+    // && only needs the right side when the left is true, || when it's false
+    if (isAnd)
+        builder->CreateCondBr(left, rightStart, merge);
+    else
+        builder->CreateCondBr(left, merge, rightStart);
+
+    builder->SetInsertPoint(rightStart);
+    Value *right = genericGen(bin->getRight());
+    if (!right)
+    {
+        addToError("while parsing right side of binary operation", bin->getRight()->getLocation());
+        return nullptr;
+    }
+    builder->CreateBr(merge);
+    BasicBlock *rightEnd = builder->GetInsertBlock();
+
+    parent->insert(parent->end(), merge);
+    builder->SetInsertPoint(merge);
+    PHINode *phi = builder->CreatePHI(Type::getInt1Ty(*context), 2, "logictmp");
+    phi->addIncoming(ConstantInt::getBool(*context, !isAnd), leftEnd);
+    phi->addIncoming(right, rightEnd);
+    return phi;
+}
+
+Value *CodeGen::genPower(Value *base, Value *exponent, Location location)
+{
+    if (!base->getType()->isIntegerTy())
+    {
+        // whole number exponents multiply out, anything else is a call to `pow`
+        if (auto *constant = dyn_cast<ConstantFP>(exponent))
+        {
+            double n = constant->getValueAPF().convertToDouble();
+            if (n == trunc(n) && fabs(n) <= 1024)
+            {
+                Value *power = genUnrolledPower(base, (uint64_t)fabs(n));
+                if (n >= 0)
+                    return power;
+                return builder->CreateFDiv(ConstantFP::get(base->getType(), 1.0), power, "powtmp");
+            }
+        }
+        return builder->CreateBinaryIntrinsic(Intrinsic::pow, base, exponent, nullptr, "powtmp");
+    }
+
+    if (auto *constant = dyn_cast<ConstantInt>(exponent))
+    {
+        if (constant->isNegative())
+        {
+            addToError("Ints can't be raised to a negative power", location);
+            return nullptr;
+        }
+        return genUnrolledPower(base, constant->getZExtValue());
+    }
+
+    // square and multiply at runtime. a negative exponent is an error in the
+    // evaluator, so here it traps instead of quietly giving back some number
+    Type *type = base->getType();
+    Value *zero = ConstantInt::get(type, 0);
+    Value *one = ConstantInt::get(type, 1);
+    genRuntimeCheck(builder->CreateICmpSGE(exponent, zero, "powsign"), "pow");
+    BasicBlock *entry = builder->GetInsertBlock();
+    Function *parent = entry->getParent();
+    BasicBlock *loop = BasicBlock::Create(*context, "powloop", parent);
+    BasicBlock *after = BasicBlock::Create(*context, "powjoin", parent);
+    builder->CreateCondBr(builder->CreateICmpSGT(exponent, zero, "powcond"), loop, after);
+
+    builder->SetInsertPoint(loop);
+    PHINode *result = builder->CreatePHI(type, 2, "powresult");
+    PHINode *square = builder->CreatePHI(type, 2, "powsquare");
+    PHINode *remaining = builder->CreatePHI(type, 2, "powremaining");
+    Value *odd = builder->CreateICmpNE(builder->CreateAnd(remaining, one), zero, "powodd");
+    Value *next = builder->CreateSelect(odd, builder->CreateMul(result, square, "powmul"), result, "powstep");
+    Value *squared = builder->CreateMul(square, square, "powsquared");
+    Value *halved = builder->CreateLShr(remaining, one, "powhalved");
+    result->addIncoming(one, entry);
+    result->addIncoming(next, loop);
+    square->addIncoming(base, entry);
+    square->addIncoming(squared, loop);
+    remaining->addIncoming(exponent, entry);
+    remaining->addIncoming(halved, loop);
+    builder->CreateCondBr(builder->CreateICmpNE(halved, zero, "powcond"), loop, after);
+
+    builder->SetInsertPoint(after);
+    PHINode *power = builder->CreatePHI(type, 2, "powtmp");
+    power->addIncoming(one, entry);
+    power->addIncoming(next, loop);
+    return power;
+}
+
+// dividing by zero, or the smallest Int by -1, is undefined behaviour in llvm and an
+// error in the evaluator, so both trap before `sdiv`/`srem` gets to them
+void CodeGen::genDivisorCheck(Value *dividend, Value *divisor)
+{
+    auto *type = cast<IntegerType>(divisor->getType());
+    Value *zero = builder->CreateICmpEQ(divisor, ConstantInt::get(type, 0), "divzero");
+    Value *min = builder->CreateICmpEQ(
+        dividend, ConstantInt::get(type, APInt::getSignedMinValue(type->getBitWidth())), "divmin");
+    Value *minusOne = builder->CreateICmpEQ(divisor, ConstantInt::getSigned(type, -1), "divminusone");
+    Value *overflow = builder->CreateAnd(min, minusOne, "divoverflow");
+    genRuntimeCheck(builder->CreateNot(builder->CreateOr(zero, overflow), "divok"), "div");
+}
+
+// carries on in a fresh block if `ok` holds and traps otherwise, for what the
+// evaluator reports as an error while running
+void CodeGen::genRuntimeCheck(Value *ok, const string &name)
+{
+    Function *parent = builder->GetInsertBlock()->getParent();
+    BasicBlock *fail = BasicBlock::Create(*context, name + "fail", parent);
+    BasicBlock *pass = BasicBlock::Create(*context, name + "ok", parent);
+    builder->CreateCondBr(ok, pass, fail);
+
+    builder->SetInsertPoint(fail);
+    builder->CreateCall(Intrinsic::getDeclaration(llvmModule.get(), Intrinsic::trap));
+    builder->CreateUnreachable();
+
+    builder->SetInsertPoint(pass);
+}
+
+// a known exponent as a chain of multiplications, `x ^ 5` is `x * (x * x) * (x * x)`
+Value *CodeGen::genUnrolledPower(Value *base, uint64_t exponent)
+{
+    bool isInt = base->getType()->isIntegerTy();
+    auto multiply = [&](Value *a, Value *b)
+    { return isInt ? builder->CreateMul(a, b, "powtmp") : builder->CreateFMul(a, b, "powtmp"); };
+
+    Value *result = nullptr;
+    Value *square = base;
+    for (uint64_t n = exponent; n > 0; n >>= 1)
+    {
+        if (n & 1)
+            result = result ? multiply(result, square) : square;
+        if (n > 1)
+            square = multiply(square, square);
+    }
+
+    if (result)
+        return result;
+    if (isInt)
+        return ConstantInt::get(base->getType(), 1);
+    return ConstantFP::get(base->getType(), 1.0);
+}
+
+Value *CodeGen::genUnaryOperation(const unique_ptr<UnaryOperation> &un)
+{
+    Value *operand = genericGen(un->getOperand());
+    if (!operand)
+    {
+        addToError("while parsing operand of unary operation", un->getOperand()->getLocation());
+        return nullptr;
+    }
 
-    // convert int to float
-    return builder->CreateUIToFP(left, Type::getDoubleTy(*context), "booltmp");
+    bool isInt = operand->getType()->isIntegerTy();
+    switch (un->getOp())
+    {
+    case UnaryKind::Plus:
+        return operand;
+    case UnaryKind::Negate:
+        return isInt ? builder->CreateNeg(operand, "negtmp") : builder->CreateFNeg(operand, "negtmp");
+    case UnaryKind::Not:
+        return builder->CreateNot(operand, "nottmp");
+    default:
+        addToError("unknown operator", un->getLocation());
+        return nullptr;
+    }
 }
 
 // todo
@@ -356,7 +716,7 @@ Value *CodeGen::genFunctionCall(const unique_ptr<FunctionCall> &call)
 Value *CodeGen::genVariableRef(const unique_ptr<VariableRef> &ref)
 {
     string name = ref->getName();
-    Value *var = namedValues[name];
+    AllocaInst *var = namedValues[name];
     if (!var)
     {
         addToError("unknown variable", ref->getLocation());
@@ -364,5 +724,325 @@ Value *CodeGen::genVariableRef(const unique_ptr<VariableRef> &ref)
     }
 
     // Load the value.
-    return builder->CreateLoad(Type::getDoubleTy(*context), var, name);
+    return builder->CreateLoad(var->getAllocatedType(), var, name);
+}
+
+// the data is malloc'd and never freed, there's nothing to track ownership yet
+Value *CodeGen::genArrayAlloc(Type *elementType, Value *length)
+{
+    Type *i64 = Type::getInt64Ty(*context);
+    uint64_t elementSize = llvmModule->getDataLayout().getTypeAllocSize(elementType);
+
+    FunctionCallee malloc = llvmModule->getOrInsertFunction(
+        "malloc", FunctionType::get(PointerType::getUnqual(Type::getInt8Ty(*context)), {i64}, false));
+    Value *size = builder->CreateMul(length, ConstantInt::get(i64, elementSize), "arrsize");
+    Value *raw = builder->CreateCall(malloc, {size}, "arrmem");
+    return builder->CreateBitCast(raw, PointerType::getUnqual(elementType), "arrdata");
+}
+
+Value *CodeGen::genArrayLiteral(const unique_ptr<ArrayLiteral> &arr)
+{
+    Type *elementType = getType(arr->getElementType());
+    Type *arrayType = getArrayType(elementType);
+    Type *i64 = Type::getInt64Ty(*context);
+    uint64_t length = arr->getElements().size();
+
+    Value *data = genArrayAlloc(elementType, ConstantInt::get(i64, length));
+
+    for (uint64_t i = 0; i < length; i++)
+    {
+        Value *element = genericGen(arr->getElements()[i]);
+        if (!element)
+        {
+            addToError("while parsing array element", arr->getElements()[i]->getLocation());
+            return nullptr;
+        }
+        if (element->getType() != elementType)
+        {
+            addToError("mismatched array element type", arr->getElements()[i]->getLocation());
+            return nullptr;
+        }
+        Value *slot = builder->CreateGEP(elementType, data, ConstantInt::get(i64, i), "arrslot");
+        builder->CreateStore(element, slot);
+    }
+
+    Value *array = UndefValue::get(arrayType);
+    array = builder->CreateInsertValue(array, ConstantInt::get(i64, length), {0}, "arrlen");
+    return builder->CreateInsertValue(array, data, {1}, "arrtmp");
+}
+
+// no bounds checking here, the evaluator is the one that catches those
+Value *CodeGen::genIndexExpression(const unique_ptr<IndexExpression> &idx)
+{
+    Value *array = genericGen(idx->getArray());
+    Value *index = genericGen(idx->getIndex());
+    if (!array || !index)
+    {
+        addToError("while parsing index expression", idx->getLocation());
+        return nullptr;
+    }
+
+    auto element = arrayElements.find(array->getType());
+    if (element == arrayElements.end() || !index->getType()->isIntegerTy())
+    {
+        addToError("only arrays can be indexed, and only with integers", idx->getLocation());
+        return nullptr;
+    }
+
+    // unsigned, so a negative index is out of bounds too
+    Value *length = builder->CreateExtractValue(array, {0}, "arrlen");
+    genRuntimeCheck(builder->CreateICmpULT(index, length, "idxinbounds"), "idx");
+    Value *data = builder->CreateExtractValue(array, {1}, "arrdata");
+    Value *slot = builder->CreateGEP(element->second, data, index, "arrslot");
+    return builder->CreateLoad(element->second, slot, "idxtmp");
+}
+
+Value *CodeGen::genArrayLength(const unique_ptr<ArrayLength> &len)
+{
+    Value *array = genericGen(len->getArray());
+    if (!array || !arrayElements.count(array->getType()))
+    {
+        addToError("len only works on arrays", len->getLocation());
+        return nullptr;
+    }
+    return builder->CreateExtractValue(array, {0}, "lentmp");
+}
+
+// ranges count up from their start without ever being allocated, arrays walk their elements
+Value *CodeGen::genForExpression(const unique_ptr<ForExpression> &loop)
+{
+    Type *i64 = Type::getInt64Ty(*context);
+    Type *bodyType = getType(loop->getBodyType());
+    Type *elementType = i64;
+    Value *start = ConstantInt::get(i64, 0);
+    Value *count;
+    Value *data = nullptr;
+
+    const unique_ptr<Expr> &iterable = loop->getIterable();
+    if (iterable->getStructure() == RangeExpr)
+    {
+        auto &range = derived(RangeExpression) iterable;
+        start = genericGen(range->getStart());
+        Value *end = genericGen(range->getEnd());
+        if (!start || !end)
+        {
+            addToError("while parsing range", range->getLocation());
+            return nullptr;
+        }
+        // backwards ranges are just empty
+        Value *difference = builder->CreateSub(end, start, "rangelen");
+        Value *backwards = builder->CreateICmpSLT(difference, ConstantInt::get(i64, 0), "backwards");
+        count = builder->CreateSelect(backwards, ConstantInt::get(i64, 0), difference, "count");
+    }
+    else
+    {
+        Value *array = genericGen(iterable);
+        auto element = array ? arrayElements.find(array->getType()) : arrayElements.end();
+        if (element == arrayElements.end())
+        {
+            addToError("for loops only work over arrays and ranges", iterable->getLocation());
+            return nullptr;
+        }
+        elementType = element->second;
+        count = builder->CreateExtractValue(array, {0}, "count");
+        data = builder->CreateExtractValue(array, {1}, "arrdata");
+    }
+
+    Function *parent = builder->GetInsertBlock()->getParent();
+    AllocaInst *counter = createEntryBlockAlloca(parent, "i", i64);
+    AllocaInst *variable = createEntryBlockAlloca(parent, loop->getVariable(), elementType);
+    AllocaInst *total = nullptr;
+    Value *results = nullptr;
+
+    builder->CreateStore(ConstantInt::get(i64, 0), counter);
+    if (loop->getKind() == LoopKind::Sum)
+    {
+        total = createEntryBlockAlloca(parent, "total", bodyType);
+        Value *zero = bodyType->isIntegerTy() ? (Value *)ConstantInt::get(bodyType, 0)
+                                              : ConstantFP::get(bodyType, 0.0);
+        builder->CreateStore(zero, total);
+    }
+    else
+        results = genArrayAlloc(bodyType, count);
+
+    // the loop variable shadows anything with the same name until the loop is done
+    AllocaInst *shadowed = namedValues[loop->getVariable()];
+    namedValues[loop->getVariable()] = variable;
+
+    BasicBlock *condition = BasicBlock::Create(*context, "loopcond", parent);
+    BasicBlock *body = BasicBlock::Create(*context, "loopbody", parent);
+    BasicBlock *end = BasicBlock::Create(*context, "loopend", parent);
+
+    builder->CreateBr(condition);
+    builder->SetInsertPoint(condition);
+    Value *index = builder->CreateLoad(i64, counter, "i");
+    builder->CreateCondBr(builder->CreateICmpSLT(index, count, "loopcmp"), body, end);
+
+    builder->SetInsertPoint(body);
+    Value *element = data ? builder->CreateLoad(elementType, builder->CreateGEP(elementType, data, index), "element")
+                          : builder->CreateAdd(start, index, "element");
+    builder->CreateStore(element, variable);
+
+    Value *result = genericGen(loop->getBody());
+    if (!result || result->getType() != bodyType)
+    {
+        addToError("while parsing loop body", loop->getBody()->getLocation());
+        return nullptr;
+    }
+
+    if (total)
+    {
+        Value *sum = builder->CreateLoad(bodyType, total, "total");
+        sum = bodyType->isIntegerTy() ? builder->CreateAdd(sum, result, "sumtmp")
+                                      : builder->CreateFAdd(sum, result, "sumtmp");
+        builder->CreateStore(sum, total);
+    }
+    else
+        builder->CreateStore(result, builder->CreateGEP(bodyType, results, index, "arrslot"));
+
+    builder->CreateStore(builder->CreateAdd(index, ConstantInt::get(i64, 1), "next"), counter);
+    builder->CreateBr(condition);
+    builder->SetInsertPoint(end);
+
+    if (shadowed)
+        namedValues[loop->getVariable()] = shadowed;
+    else
+        namedValues.erase(loop->getVariable());
+
+    if (total)
+        return builder->CreateLoad(bodyType, total, "sum");
+
+    Value *array = UndefValue::get(getArrayType(bodyType));
+    array = builder->CreateInsertValue(array, count, {0}, "arrlen");
+    return builder->CreateInsertValue(array, results, {1}, "arrtmp");
+}
+
+Value *CodeGen::genLetExpression(const unique_ptr<LetExpression> &let)
+{
+    Value *value = genericGen(let->getValue());
+    if (!value)
+    {
+        addToError("while parsing local binding", let->getValue()->getLocation());
+        return nullptr;
+    }
+
+    Function *parent = builder->GetInsertBlock()->getParent();
+    AllocaInst *binding = createEntryBlockAlloca(parent, let->getName(), value->getType());
+    builder->CreateStore(value, binding);
+
+    // the binding shadows anything with the same name, but only inside the body
+    AllocaInst *shadowed = namedValues[let->getName()];
+    namedValues[let->getName()] = binding;
+
+    Value *result = genericGen(let->getBody());
+
+    if (shadowed)
+        namedValues[let->getName()] = shadowed;
+    else
+        namedValues.erase(let->getName());
+    return result;
+}
+
+// the env is malloc'd and never freed, same as array data
+Value *CodeGen::genClosure(const unique_ptr<Closure> &closure)
+{
+    Function *fn = loadFunction(closure->getName());
+    const vector<string> &captures = closure->getCaptures();
+    if (!fn || fn->arg_size() < captures.size())
+    {
+        addToError("while loading function '" + closure->getName() + "'", closure->getLocation());
+        return nullptr;
+    }
+
+    PointerType *ptr = PointerType::getUnqual(*context);
+    Value *env = ConstantPointerNull::get(ptr);
+    if (!captures.empty())
+    {
+        vector<Type *> captureTypes;
+        for (size_t i = 0; i < captures.size(); i++)
+            captureTypes.push_back(fn->getArg(i)->getType());
+        StructType *envType = StructType::get(*context, captureTypes);
+
+        Type *i64 = Type::getInt64Ty(*context);
+        uint64_t envSize = llvmModule->getDataLayout().getTypeAllocSize(envType);
+        FunctionCallee malloc = llvmModule->getOrInsertFunction(
+            "malloc", FunctionType::get(PointerType::getUnqual(Type::getInt8Ty(*context)), {i64}, false));
+        env = builder->CreateCall(malloc, {ConstantInt::get(i64, envSize)}, "envmem");
+
+        for (size_t i = 0; i < captures.size(); i++)
+        {
+            AllocaInst *var = namedValues[captures[i]];
+            if (!var)
+            {
+                addToError("unknown captured variable '" + captures[i] + "'", closure->getLocation());
+                return nullptr;
+            }
+            Value *value = builder->CreateLoad(var->getAllocatedType(), var, captures[i]);
+            builder->CreateStore(value, builder->CreateStructGEP(envType, env, i, "envslot"));
+        }
+    }
+
+    Value *result = UndefValue::get(getClosureType());
+    result = builder->CreateInsertValue(result, getTrampoline(fn, captures.size()), {0}, "closurecode");
+    return builder->CreateInsertValue(result, env, {1}, "closuretmp");
+}
+
+// `f.env(env, args...)` takes the captures back out of the env and calls `f` with them
+// in front of the arguments, so every closure gets called the same way
+Function *CodeGen::getTrampoline(Function *fn, size_t captureCount)
+{
+    string name = fn->getName().str() + ".env";
+    if (Function *existing = llvmModule->getFunction(name))
+        return existing;
+
+    FunctionType *fnType = fn->getFunctionType();
+    vector<Type *> captureTypes(fnType->param_begin(), fnType->param_begin() + captureCount);
+    vector<Type *> argT = {PointerType::getUnqual(*context)};
+    argT.insert(argT.end(), fnType->param_begin() + captureCount, fnType->param_end());
+    Function *trampoline = Function::Create(FunctionType::get(fnType->getReturnType(), argT, false),
+                                            Function::InternalLinkage, name, llvmModule.get());
+
+    // a builder of its own, since this is usually made halfway through another function
+    IRBuilder<> body(BasicBlock::Create(*context, "entry", trampoline));
+    StructType *envType = StructType::get(*context, captureTypes);
+    Argument *env = trampoline->getArg(0);
+    env->setName("env");
+
+    vector<Value *> argv;
+    for (unsigned i = 0; i < captureCount; i++)
+        argv.push_back(body.CreateLoad(captureTypes[i], body.CreateStructGEP(envType, env, i), "capture"));
+    for (unsigned i = 1; i < trampoline->arg_size(); i++)
+        argv.push_back(trampoline->getArg(i));
+    body.CreateRet(body.CreateCall(fn, argv, "calltmp"));
+
+    verifyFunction(*trampoline);
+    return trampoline;
+}
+
+Value *CodeGen::genApply(const unique_ptr<Apply> &apply)
+{
+    Value *closure = genericGen(apply->getFunction());
+    if (!closure || closure->getType() != getClosureType())
+    {
+        addToError("only functions can be called", apply->getLocation());
+        return nullptr;
+    }
+
+    vector<Type *> argT = {PointerType::getUnqual(*context)};
+    vector<Value *> argv = {builder->CreateExtractValue(closure, {1}, "env")};
+    for (auto &arg : apply->getArgs())
+    {
+        Value *value = genericGen(arg);
+        if (!value)
+        {
+            addToError("broken arguments", apply->getLocation());
+            return nullptr;
+        }
+        argT.push_back(value->getType());
+        argv.push_back(value);
+    }
+
+    FunctionType *fnType = FunctionType::get(getType(apply->getReturnType()), argT, false);
+    Value *code = builder->CreateExtractValue(closure, {0}, "code");
+    return builder->CreateCall(fnType, code, argv, "calltmp");
 }
\ No newline at end of file
@@ -33,12 +33,23 @@
 
 #include "number.h"
 #include "string.h"
+#include "bool.h"
 #include "fndef.h"
+#include "extern.h"
 #include "chain.h"
 #include "binop.h"
+#include "unary.h"
 #include "when.h"
 #include "fncall.h"
 #include "ref.h"
+#include "array.h"
+#include "index.h"
+#include "length.h"
+#include "for.h"
+#include "range.h"
+#include "let.h"
+#include "closure.h"
+#include "apply.h"
 
 using namespace llvm;
 using namespace std;
@@ -46,6 +57,14 @@ using namespace ast;
 
 #define derived(U) (const unique_ptr<U> &)
 
+// what `runPass` writes out, the rust side handles every `--emit` before this
+enum class OutputKind
+{
+    LLVMIR,
+    Bitcode,
+    Object
+};
+
 class CodeGen
 {
     unique_ptr<LLVMContext> context;
@@ -56,16 +75,40 @@ class CodeGen
     vector<unique_ptr<FunctionDefinition>> functionDefs;
     string targetTriple;
     TargetMachine *targetMachine;
-    AllocaInst *createEntryBlockAlloca(Function *function, StringRef varName);
+    AllocaInst *createEntryBlockAlloca(Function *function, StringRef varName, Type *type);
+    // element type of each array struct, opaque pointers don't keep it around
+    map<Type *, Type *> arrayElements;
+    Type *getType(ValueType type);
+    Type *getArrayType(Type *elementType);
     Value *getPredFCmp(const unique_ptr<WhenExpression> &when);
+    Value *genIntBinaryOperation(OpKind op, Value *left, Value *right, Location location);
+    Value *genLogicalOperation(const unique_ptr<BinaryOperation> &bin);
+    Value *genPower(Value *base, Value *exponent, Location location);
+    Value *genUnrolledPower(Value *base, uint64_t exponent);
+    void genDivisorCheck(Value *dividend, Value *divisor);
+    void genRuntimeCheck(Value *ok, const string &name);
     
+    Function *declareFunction(const unique_ptr<FunctionDefinition> &def);
     Function *genFunctionDefinition(const unique_ptr<FunctionDefinition> &func);
+    Function *genExternDeclaration(const unique_ptr<ExternDeclaration> &decl);
     Value *genNumberLiteral(const unique_ptr<NumberLiteral> &num);
     Value *genStringLiteral(const unique_ptr<ast::StringLiteral> &string);
+    Value *genBoolLiteral(const unique_ptr<BoolLiteral> &boolean);
     Value *genChainExpression(const unique_ptr<ChainExpression> &chain);
     Value *genBinaryOperation(const unique_ptr<BinaryOperation> &bin);
+    Value *genUnaryOperation(const unique_ptr<UnaryOperation> &un);
     Value *genFunctionCall(const unique_ptr<FunctionCall> &call);
     Value *genVariableRef(const unique_ptr<VariableRef> &ref);
+    Value *genArrayLiteral(const unique_ptr<ArrayLiteral> &arr);
+    Value *genIndexExpression(const unique_ptr<IndexExpression> &idx);
+    Value *genArrayLength(const unique_ptr<ArrayLength> &len);
+    Value *genForExpression(const unique_ptr<ForExpression> &loop);
+    Value *genLetExpression(const unique_ptr<LetExpression> &let);
+    Value *genArrayAlloc(Type *elementType, Value *length);
+    Value *genClosure(const unique_ptr<Closure> &closure);
+    Value *genApply(const unique_ptr<Apply> &apply);
+    Function *getTrampoline(Function *fn, size_t captureCount);
+    Type *getClosureType();
 
     CodeGenError *error;
     void addToError(string message, Location location);
@@ -76,8 +119,10 @@ public:
 
     const unique_ptr<LLVMContext> &getContext();
 
-    int runPass(string outFile);
+    int runPass(string outFile, OptimizationLevel level, OutputKind kind);
+    GlobalValue *exportEntry(string name);
     Function *loadFunction(string name);
+    void declare(const unique_ptr<Expr> &expr);
     Value *genericGen(const unique_ptr<Expr> &expr);
     void printError();
 };
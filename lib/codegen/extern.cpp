@@ -0,0 +1,45 @@
+#include "extern.h"
+
+ExternDeclaration::ExternDeclaration(Location location, string name, vector<string> args,
+                                     vector<ValueType> argTypes, ValueType returnType)
+    : Expr(ExternDecl, location), name(name), args(args), argTypes(argTypes), returnType(returnType){};
+
+const string &ExternDeclaration::getName()
+{
+    return name;
+}
+
+const vector<string> &ExternDeclaration::getArgs()
+{
+    return args;
+}
+
+const vector<ValueType> &ExternDeclaration::getArgTypes()
+{
+    return argTypes;
+}
+
+const ValueType &ExternDeclaration::getReturnType()
+{
+    return returnType;
+}
+
+void ExternDeclaration::setName(string externName)
+{
+    name = externName;
+}
+
+void ExternDeclaration::setArgs(vector<string> externArgs)
+{
+    args = externArgs;
+}
+
+void ExternDeclaration::setArgTypes(vector<ValueType> externArgTypes)
+{
+    argTypes = externArgTypes;
+}
+
+void ExternDeclaration::setReturnType(ValueType externReturnType)
+{
+    returnType = externReturnType;
+}
\ No newline at end of file
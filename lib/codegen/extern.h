@@ -0,0 +1,31 @@
+#ifndef __EXTERN_DECLARATION_H
+#define __EXTERN_DECLARATION_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class ExternDeclaration : public Expr
+    {
+        string name;
+        vector<string> args;
+        vector<ValueType> argTypes;
+        ValueType returnType;
+
+    public:
+        ExternDeclaration(Location location, string name, vector<string> args, vector<ValueType> argTypes,
+                          ValueType returnType);
+
+        const string &getName();
+        const vector<string> &getArgs();
+        const vector<ValueType> &getArgTypes();
+        const ValueType &getReturnType();
+
+        void setName(string externName);
+        void setArgs(vector<string> externArgs);
+        void setArgTypes(vector<ValueType> externArgTypes);
+        void setReturnType(ValueType externReturnType);
+    };
+}
+
+#endif
\ No newline at end of file
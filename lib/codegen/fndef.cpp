@@ -1,7 +1,9 @@
 #include "fndef.h"
 
-FunctionDefinition::FunctionDefinition(Location location, string name, vector<string> args, unique_ptr<Expr> body)
-    : Expr(FnDef, location), name(name), args(args), body(std::move(body)){};
+FunctionDefinition::FunctionDefinition(Location location, string name, vector<string> args, vector<ValueType> argTypes,
+                                       ValueType returnType, unique_ptr<Expr> body)
+    : Expr(FnDef, location), name(name), args(args), argTypes(argTypes), returnType(returnType),
+      body(std::move(body)){};
 
 const string &FunctionDefinition::getName()
 {
@@ -13,6 +15,16 @@ const vector<string> &FunctionDefinition::getArgs()
     return args;
 }
 
+const vector<ValueType> &FunctionDefinition::getArgTypes()
+{
+    return argTypes;
+}
+
+const ValueType &FunctionDefinition::getReturnType()
+{
+    return returnType;
+}
+
 const unique_ptr<Expr> &FunctionDefinition::getBody()
 {
     return body;
@@ -28,6 +40,16 @@ void FunctionDefinition::setArgs(vector<string> fnArgs)
     args = fnArgs;
 }
 
+void FunctionDefinition::setArgTypes(vector<ValueType> fnArgTypes)
+{
+    argTypes = fnArgTypes;
+}
+
+void FunctionDefinition::setReturnType(ValueType fnReturnType)
+{
+    returnType = fnReturnType;
+}
+
 void FunctionDefinition::setBody(unique_ptr<Expr> fnBody)
 {
     body = std::move(fnBody);
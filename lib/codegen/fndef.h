@@ -9,17 +9,24 @@ namespace ast
     {
         string name;
         vector<string> args;
+        vector<ValueType> argTypes;
+        ValueType returnType;
         unique_ptr<Expr> body;
 
     public:
-        FunctionDefinition(Location location, string name, vector<string> args, unique_ptr<Expr> fnBody);
+        FunctionDefinition(Location location, string name, vector<string> args, vector<ValueType> argTypes,
+                           ValueType returnType, unique_ptr<Expr> fnBody);
 
         const string &getName();
         const vector<string> &getArgs();
+        const vector<ValueType> &getArgTypes();
+        const ValueType &getReturnType();
         const unique_ptr<Expr> &getBody();
 
         void setName(string fnName);
         void setArgs(vector<string> fnArgs);
+        void setArgTypes(vector<ValueType> fnArgTypes);
+        void setReturnType(ValueType fnReturnType);
         void setBody(unique_ptr<Expr> fnBody);
     };
 }
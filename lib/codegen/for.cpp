@@ -0,0 +1,56 @@
+#include "for.h"
+
+ForExpression::ForExpression(Location location, LoopKind kind, string variable, unique_ptr<Expr> iterable,
+                             unique_ptr<Expr> body, ValueType bodyType)
+    : Expr(ForExpr, location), kind(kind), variable(variable), iterable(std::move(iterable)),
+      body(std::move(body)), bodyType(bodyType){};
+
+const LoopKind &ForExpression::getKind()
+{
+    return kind;
+}
+
+const string &ForExpression::getVariable()
+{
+    return variable;
+}
+
+const unique_ptr<Expr> &ForExpression::getIterable()
+{
+    return iterable;
+}
+
+const unique_ptr<Expr> &ForExpression::getBody()
+{
+    return body;
+}
+
+const ValueType &ForExpression::getBodyType()
+{
+    return bodyType;
+}
+
+void ForExpression::setKind(LoopKind forKind)
+{
+    kind = forKind;
+}
+
+void ForExpression::setVariable(string forVariable)
+{
+    variable = forVariable;
+}
+
+void ForExpression::setIterable(unique_ptr<Expr> forIterable)
+{
+    iterable = std::move(forIterable);
+}
+
+void ForExpression::setBody(unique_ptr<Expr> forBody)
+{
+    body = std::move(forBody);
+}
+
+void ForExpression::setBodyType(ValueType forBodyType)
+{
+    bodyType = forBodyType;
+}
\ No newline at end of file
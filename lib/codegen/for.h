@@ -0,0 +1,34 @@
+#ifndef __FOR_EXPRESSION_H
+#define __FOR_EXPRESSION_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class ForExpression : public Expr
+    {
+        LoopKind kind;
+        string variable;
+        unique_ptr<Expr> iterable;
+        unique_ptr<Expr> body;
+        ValueType bodyType;
+
+    public:
+        ForExpression(Location location, LoopKind kind, string variable, unique_ptr<Expr> iterable,
+                      unique_ptr<Expr> body, ValueType bodyType);
+
+        const LoopKind &getKind();
+        const string &getVariable();
+        const unique_ptr<Expr> &getIterable();
+        const unique_ptr<Expr> &getBody();
+        const ValueType &getBodyType();
+
+        void setKind(LoopKind forKind);
+        void setVariable(string forVariable);
+        void setIterable(unique_ptr<Expr> forIterable);
+        void setBody(unique_ptr<Expr> forBody);
+        void setBodyType(ValueType forBodyType);
+    };
+}
+
+#endif
\ No newline at end of file
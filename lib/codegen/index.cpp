@@ -0,0 +1,24 @@
+#include "index.h"
+
+IndexExpression::IndexExpression(Location location, unique_ptr<Expr> array, unique_ptr<Expr> index)
+    : Expr(IndexExpr, location), array(std::move(array)), index(std::move(index)){};
+
+const unique_ptr<Expr> &IndexExpression::getArray()
+{
+    return array;
+}
+
+const unique_ptr<Expr> &IndexExpression::getIndex()
+{
+    return index;
+}
+
+void IndexExpression::setArray(unique_ptr<Expr> idxArray)
+{
+    array = std::move(idxArray);
+}
+
+void IndexExpression::setIndex(unique_ptr<Expr> idxIndex)
+{
+    index = std::move(idxIndex);
+}
\ No newline at end of file
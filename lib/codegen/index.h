@@ -0,0 +1,24 @@
+#ifndef __INDEX_EXPRESSION_H
+#define __INDEX_EXPRESSION_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class IndexExpression : public Expr
+    {
+        unique_ptr<Expr> array;
+        unique_ptr<Expr> index;
+
+    public:
+        IndexExpression(Location location, unique_ptr<Expr> array, unique_ptr<Expr> index);
+
+        const unique_ptr<Expr> &getArray();
+        const unique_ptr<Expr> &getIndex();
+
+        void setArray(unique_ptr<Expr> idxArray);
+        void setIndex(unique_ptr<Expr> idxIndex);
+    };
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,14 @@
+#include "length.h"
+
+ArrayLength::ArrayLength(Location location, unique_ptr<Expr> array)
+    : Expr(LengthExpr, location), array(std::move(array)){};
+
+const unique_ptr<Expr> &ArrayLength::getArray()
+{
+    return array;
+}
+
+void ArrayLength::setArray(unique_ptr<Expr> lenArray)
+{
+    array = std::move(lenArray);
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#ifndef __ARRAY_LENGTH_H
+#define __ARRAY_LENGTH_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class ArrayLength : public Expr
+    {
+        unique_ptr<Expr> array;
+
+    public:
+        ArrayLength(Location location, unique_ptr<Expr> array);
+
+        const unique_ptr<Expr> &getArray();
+
+        void setArray(unique_ptr<Expr> lenArray);
+    };
+}
+
+#endif
\ No newline at end of file
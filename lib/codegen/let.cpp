@@ -0,0 +1,34 @@
+#include "let.h"
+
+LetExpression::LetExpression(Location location, string name, unique_ptr<Expr> value, unique_ptr<Expr> body)
+    : Expr(LetExpr, location), name(name), value(std::move(value)), body(std::move(body)){};
+
+const string &LetExpression::getName()
+{
+    return name;
+}
+
+const unique_ptr<Expr> &LetExpression::getValue()
+{
+    return value;
+}
+
+const unique_ptr<Expr> &LetExpression::getBody()
+{
+    return body;
+}
+
+void LetExpression::setName(string letName)
+{
+    name = letName;
+}
+
+void LetExpression::setValue(unique_ptr<Expr> letValue)
+{
+    value = std::move(letValue);
+}
+
+void LetExpression::setBody(unique_ptr<Expr> letBody)
+{
+    body = std::move(letBody);
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+#ifndef __LET_EXPRESSION_H
+#define __LET_EXPRESSION_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class LetExpression : public Expr
+    {
+        string name;
+        unique_ptr<Expr> value;
+        unique_ptr<Expr> body;
+
+    public:
+        LetExpression(Location location, string name, unique_ptr<Expr> value, unique_ptr<Expr> body);
+
+        const string &getName();
+        const unique_ptr<Expr> &getValue();
+        const unique_ptr<Expr> &getBody();
+
+        void setName(string letName);
+        void setValue(unique_ptr<Expr> letValue);
+        void setBody(unique_ptr<Expr> letBody);
+    };
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,24 @@
+#include "range.h"
+
+RangeExpression::RangeExpression(Location location, unique_ptr<Expr> start, unique_ptr<Expr> end)
+    : Expr(RangeExpr, location), start(std::move(start)), end(std::move(end)){};
+
+const unique_ptr<Expr> &RangeExpression::getStart()
+{
+    return start;
+}
+
+const unique_ptr<Expr> &RangeExpression::getEnd()
+{
+    return end;
+}
+
+void RangeExpression::setStart(unique_ptr<Expr> rangeStart)
+{
+    start = std::move(rangeStart);
+}
+
+void RangeExpression::setEnd(unique_ptr<Expr> rangeEnd)
+{
+    end = std::move(rangeEnd);
+}
\ No newline at end of file
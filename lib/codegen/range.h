@@ -0,0 +1,24 @@
+#ifndef __RANGE_EXPRESSION_H
+#define __RANGE_EXPRESSION_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class RangeExpression : public Expr
+    {
+        unique_ptr<Expr> start;
+        unique_ptr<Expr> end;
+
+    public:
+        RangeExpression(Location location, unique_ptr<Expr> start, unique_ptr<Expr> end);
+
+        const unique_ptr<Expr> &getStart();
+        const unique_ptr<Expr> &getEnd();
+
+        void setStart(unique_ptr<Expr> rangeStart);
+        void setEnd(unique_ptr<Expr> rangeEnd);
+    };
+}
+
+#endif
\ No newline at end of file
@@ -0,0 +1,24 @@
+#include "unary.h"
+
+UnaryOperation::UnaryOperation(Location location, UnaryKind op, unique_ptr<Expr> operand)
+    : Expr(UnaryOp, location), op(op), operand(std::move(operand)){};
+
+const UnaryKind &UnaryOperation::getOp()
+{
+    return op;
+}
+
+const unique_ptr<Expr> &UnaryOperation::getOperand()
+{
+    return operand;
+}
+
+void UnaryOperation::setOp(UnaryKind opKind)
+{
+    op = opKind;
+}
+
+void UnaryOperation::setOperand(unique_ptr<Expr> operandExpr)
+{
+    operand = std::move(operandExpr);
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+#ifndef __UNARY_OPERATION_H
+#define __UNARY_OPERATION_H
+
+#include "ast.h"
+
+namespace ast
+{
+    class UnaryOperation : public Expr
+    {
+        UnaryKind op;
+        unique_ptr<Expr> operand;
+
+    public:
+        UnaryOperation(Location location, UnaryKind op, unique_ptr<Expr> operand);
+
+        const UnaryKind &getOp();
+        const unique_ptr<Expr> &getOperand();
+
+        void setOp(UnaryKind opKind);
+        void setOperand(unique_ptr<Expr> operandExpr);
+    };
+}
+
+#endif
\ No newline at end of file
@@ -1,31 +1,100 @@
 #include "compiler.h"
 
+// only the ones codegen deals with, the rest never make it past the rust side
+static OutputKind outputKind(Emit emit)
+{
+    switch (emit)
+    {
+    case Emit::LlvmIr:
+        return OutputKind::LLVMIR;
+    case Emit::Obj:
+        return OutputKind::Object;
+    default:
+        return OutputKind::Bitcode;
+    }
+}
+
+static OptimizationLevel optimizationLevel(uint8_t level)
+{
+    switch (level)
+    {
+    case 0:
+        return OptimizationLevel::O0;
+    case 1:
+        return OptimizationLevel::O1;
+    case 3:
+        return OptimizationLevel::O3;
+    default:
+        return OptimizationLevel::O2;
+    }
+}
+
+static CodeGenOpt::Level codeGenLevel(uint8_t level)
+{
+    switch (level)
+    {
+    case 0:
+        return CodeGenOpt::None;
+    case 1:
+        return CodeGenOpt::Less;
+    case 3:
+        return CodeGenOpt::Aggressive;
+    default:
+        return CodeGenOpt::Default;
+    }
+}
+
 // all taken from llvm examples
-int compile(int argc, char *argv[])
+static int generate(FFISafeExprVec tokens)
 {
-    auto targetTriple = sys::getDefaultTargetTriple();
-    InitializeNativeTarget();
-    InitializeNativeTargetAsmParser();
-    InitializeNativeTargetAsmPrinter();
+    FFISafeOptions &options = tokens.options;
+    if (tokens.finished)
+    {
+        drop_options(options);
+        return tokens.errors > 0 ? 1 : 0;
+    }
+
+    auto targetTriple = options.target ? string(options.target) : sys::getDefaultTargetTriple();
+    if (options.target)
+    {
+        // any target llvm was built with, not just the host
+        InitializeAllTargetInfos();
+        InitializeAllTargets();
+        InitializeAllTargetMCs();
+        InitializeAllAsmParsers();
+        InitializeAllAsmPrinters();
+    }
+    else
+    {
+        InitializeNativeTarget();
+        InitializeNativeTargetAsmParser();
+        InitializeNativeTargetAsmPrinter();
+    }
     string error;
     auto target = TargetRegistry::lookupTarget(targetTriple, error);
 
     if (!target)
     {
         errs() << error;
+        drop_all(tokens.ptr, tokens.len);
+        drop_options(options);
         return 1;
     }
-    auto cpu = "generic";
     auto features = "";
 
     TargetOptions opt;
     auto rm = optional<Reloc::Model>();
-    auto targetMachine = target->createTargetMachine(targetTriple, cpu, features, opt, rm);
+    auto cm = optional<CodeModel::Model>();
+    auto targetMachine = target->createTargetMachine(targetTriple, options.cpu, features, opt, rm, cm,
+                                                     codeGenLevel(options.opt_level));
     CodeGen generator(targetTriple, targetMachine);
-    FFISafeExprVec tokens = recieve_tokens(argv, argc);
+
     auto tree = reGenerateAST(tokens);
     bool cont = true;
 
+    for (auto &branch : tree)
+        generator.declare(branch);
+
     for (auto &branch : tree)
     {
         auto value = generator.genericGen(branch);
@@ -37,14 +106,30 @@ int compile(int argc, char *argv[])
         }
     }
 
-    if (cont && generator.runPass(tokens.out))
+    if (cont && options.entry && !generator.exportEntry(options.entry))
+    {
+        generator.printError();
+        cont = false;
+    }
+
+    if (cont && generator.runPass(options.outfile, optimizationLevel(options.opt_level), outputKind(options.emit)))
         cont = false;
 
-    free(tokens.out);
     drop_all(tokens.ptr, tokens.len);
+    drop_options(options);
     delete targetMachine;
 
     if (cont)
         return 0;
     return 1;
+}
+
+int compile(int argc, char *argv[])
+{
+    return generate(recieve_tokens(argv, argc));
+}
+
+int compileSource(const char *name, const char *source, size_t len)
+{
+    return generate(recieve_source(name, source, len));
 }
\ No newline at end of file
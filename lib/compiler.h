@@ -11,5 +11,7 @@ using namespace llvm;
 using namespace llvm::sys;
 
 int compile(int argc, char *argv[]);
+// like `fx <name>` with no flags, but `source` is used instead of reading `name`
+int compileSource(const char *name, const char *source, size_t len);
 
 #endif
\ No newline at end of file
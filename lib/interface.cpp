@@ -5,10 +5,10 @@ Location buildFromPtr(const LocatedFFISafeExpr *ptr)
     return Location(ptr->line, ptr->column, ptr->filename);
 }
 
-vector<unique_ptr<Expr>> translateExpressionVec(const LocatedFFISafeExpr *start, uint8_t len)
+vector<unique_ptr<Expr>> translateExpressionVec(const LocatedFFISafeExpr *start, uintptr_t len)
 {
     vector<unique_ptr<Expr>> vec;
-    for (uint8_t i = 0; i < len; i++)
+    for (uintptr_t i = 0; i < len; i++)
     {
         vec.push_back(translateExpression(start + i));
     }
@@ -26,6 +26,33 @@ vector<string> translateStringVec(const char *const *start, uint32_t len)
     return vec;
 }
 
+ValueType translateType(FFISafeType type)
+{
+    switch (type.kind)
+    {
+    case FFISafeTypeKind::Int:
+        return {ValueKind::Int, type.depth};
+    case FFISafeTypeKind::String:
+        return {ValueKind::String, type.depth};
+    case FFISafeTypeKind::Bool:
+        return {ValueKind::Bool, type.depth};
+    case FFISafeTypeKind::Function:
+        return {ValueKind::Function, type.depth};
+    default:
+        return {ValueKind::Float, type.depth};
+    }
+}
+
+vector<ValueType> translateTypeVec(const FFISafeType *start, uint32_t len)
+{
+    vector<ValueType> vec;
+    for (int i = 0; i < len; i++)
+    {
+        vec.push_back(translateType(*(start + i)));
+    }
+    return vec;
+}
+
 unique_ptr<WhenExpression> translateWhen(const LocatedFFISafeExpr *ptr)
 {
     const FFISafeExpr expr = ptr->expr;
@@ -44,21 +71,43 @@ unique_ptr<Expr> translateExpression(const LocatedFFISafeExpr *ptr)
     {
     case FFISafeExpr::Tag::NumberLiteral:
     {
-        // TODO
         NumberType type;
-        type.type = f64;
-        type.value.f64 = expr.number_literal._2;
+        if (expr.number_literal._0)
+        {
+            type.type = f64;
+            type.value.f64 = expr.number_literal._2;
+        }
+        else
+        {
+            type.type = i64;
+            type.value.i64 = expr.number_literal._1;
+        }
         return make_unique<NumberLiteral>(location, type);
     }
     case FFISafeExpr::Tag::StringLiteral:
     {
         return make_unique<ast::StringLiteral>(location, expr.string_literal._0);
     }
+    case FFISafeExpr::Tag::BoolLiteral:
+    {
+        return make_unique<BoolLiteral>(location, expr.bool_literal._0);
+    }
     case FFISafeExpr::Tag::FunctionDefinition:
     {
         string funcName = expr.function_definition._0;
-        vector<string> args = translateStringVec(expr.function_definition._1, expr.function_definition._2);
-        return make_unique<FunctionDefinition>(location, funcName, args, translateExpression(expr.function_definition._3));
+        vector<string> args = translateStringVec(expr.function_definition._1, expr.function_definition._3);
+        vector<ValueType> argTypes = translateTypeVec(expr.function_definition._2, expr.function_definition._3);
+        return make_unique<FunctionDefinition>(location, funcName, args, argTypes,
+                                               translateType(expr.function_definition._5),
+                                               translateExpression(expr.function_definition._4));
+    }
+    case FFISafeExpr::Tag::ExternDeclaration:
+    {
+        string externName = expr.extern_declaration._0;
+        vector<string> args = translateStringVec(expr.extern_declaration._1, expr.extern_declaration._3);
+        vector<ValueType> argTypes = translateTypeVec(expr.extern_declaration._2, expr.extern_declaration._3);
+        return make_unique<ExternDeclaration>(location, externName, args, argTypes,
+                                              translateType(expr.extern_declaration._4));
     }
     case FFISafeExpr::Tag::ChainExpression:
     {
@@ -74,10 +123,15 @@ unique_ptr<Expr> translateExpression(const LocatedFFISafeExpr *ptr)
     }
     case FFISafeExpr::Tag::BinaryOperation:
     {
-        return make_unique<BinaryOperation>(location, expr.binary_operation._0,
+        return make_unique<BinaryOperation>(location, static_cast<OpKind>(expr.binary_operation._0),
                                             translateExpression(expr.binary_operation._1),
                                             translateExpression(expr.binary_operation._2));
     }
+    case FFISafeExpr::Tag::UnaryOperation:
+    {
+        return make_unique<UnaryOperation>(location, static_cast<UnaryKind>(expr.unary_operation._0),
+                                           translateExpression(expr.unary_operation._1));
+    }
     case FFISafeExpr::Tag::WhenExpression:
     {
         return translateWhen(ptr);
@@ -92,6 +146,50 @@ unique_ptr<Expr> translateExpression(const LocatedFFISafeExpr *ptr)
     {
         return make_unique<VariableRef>(location, expr.variable_ref._0);
     }
+    case FFISafeExpr::Tag::ArrayLiteral:
+    {
+        return make_unique<ArrayLiteral>(
+            location, translateExpressionVec(expr.array_literal._0, expr.array_literal._1),
+            translateType(expr.array_literal._2));
+    }
+    case FFISafeExpr::Tag::Index:
+    {
+        return make_unique<IndexExpression>(location, translateExpression(expr.index._0),
+                                            translateExpression(expr.index._1));
+    }
+    case FFISafeExpr::Tag::Length:
+    {
+        return make_unique<ArrayLength>(location, translateExpression(expr.length._0));
+    }
+    case FFISafeExpr::Tag::For:
+    {
+        LoopKind kind = expr.for_._0 == ForKind::Sum ? LoopKind::Sum : LoopKind::Collect;
+        return make_unique<ForExpression>(location, kind, expr.for_._1,
+                                          translateExpression(expr.for_._2),
+                                          translateExpression(expr.for_._3),
+                                          translateType(expr.for_._4));
+    }
+    case FFISafeExpr::Tag::Range:
+    {
+        return make_unique<RangeExpression>(location, translateExpression(expr.range._0),
+                                            translateExpression(expr.range._1));
+    }
+    case FFISafeExpr::Tag::Let:
+    {
+        return make_unique<LetExpression>(location, expr.let._0, translateExpression(expr.let._1),
+                                          translateExpression(expr.let._2));
+    }
+    case FFISafeExpr::Tag::Apply:
+    {
+        return make_unique<Apply>(location, translateExpression(expr.apply._0),
+                                  translateExpressionVec(expr.apply._1, expr.apply._2),
+                                  translateType(expr.apply._3));
+    }
+    case FFISafeExpr::Tag::Closure:
+    {
+        return make_unique<Closure>(location, expr.closure._0,
+                                    translateStringVec(expr.closure._1, expr.closure._2));
+    }
     default:
         return nullptr;
     }
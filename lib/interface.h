@@ -12,8 +12,10 @@ using namespace ffi;
 
 Location buildFromPtr(const LocatedFFISafeExpr *ptr);
 
-vector<unique_ptr<Expr>> translateExpressionVec(const LocatedFFISafeExpr *start, uint8_t len);
+vector<unique_ptr<Expr>> translateExpressionVec(const LocatedFFISafeExpr *start, uintptr_t len);
 vector<FFISafeExpr *> translateStringVec(const char *start, uint32_t len);
+ValueType translateType(FFISafeType type);
+vector<ValueType> translateTypeVec(const FFISafeType *start, uint32_t len);
 unique_ptr<WhenExpression> translateWhen(const LocatedFFISafeExpr *ptr);
 unique_ptr<Expr> translateExpression(const LocatedFFISafeExpr *ptr);
 
@@ -1,9 +1,9 @@
 #include <stdio.h>
 
-extern double entry();
+extern long long entry();
 
 int main() {
-    double val = entry();
-    printf("%f\n", val);
+    long long val = entry();
+    printf("%lld\n", val);
     return 0;
-}
\ No newline at end of file
+}
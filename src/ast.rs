@@ -1,6 +1,9 @@
-use std::ffi::{c_char, CString};
+use std::{
+    ffi::{c_char, CString},
+    fmt,
+};
 
-use crate::errors::Location;
+use crate::{errors::Location, options::FFISafeOptions};
 
 #[repr(C)]
 #[derive(Debug)]
@@ -20,7 +23,7 @@ impl LocatedFFISafeExpr {
             expr,
         }
     }
-    
+
     pub fn get_expr(&self) -> &FFISafeExpr {
         &self.expr
     }
@@ -42,11 +45,11 @@ impl LocatedExpr {
     }
 
     pub fn get_location(&self) -> Location {
-        self.location
+        self.location.clone()
     }
 
     pub fn get_expr(&self) -> Expr {
-        self.expr
+        self.expr.clone()
     }
 }
 
@@ -54,7 +57,134 @@ impl LocatedExpr {
 pub enum ReturnType {
     Int,
     Float,
-    String
+    String,
+    Bool,
+    // element type
+    Array(Box<ReturnType>),
+    // parameter types, return type
+    Function(Vec<ReturnType>, Box<ReturnType>),
+}
+
+impl fmt::Display for ReturnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReturnType::Int => write!(f, "Int"),
+            ReturnType::Float => write!(f, "Float"),
+            ReturnType::String => write!(f, "String"),
+            ReturnType::Bool => write!(f, "Bool"),
+            ReturnType::Array(element) => write!(f, "[{}]", element),
+            ReturnType::Function(args, ret) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "({}) -> {}", args.join(", "), ret)
+            }
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FFISafeTypeKind {
+    Int,
+    Float,
+    String,
+    Bool,
+    // a closure, codegen only needs to know that much to pass one around
+    Function,
+}
+
+// `ReturnType` flattened for the ffi, `[[Int]]` is `Int` with a depth of 2
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FFISafeType {
+    kind: FFISafeTypeKind,
+    depth: usize,
+}
+
+impl From<ReturnType> for FFISafeType {
+    fn from(return_type: ReturnType) -> FFISafeType {
+        let kind = match return_type {
+            ReturnType::Int => FFISafeTypeKind::Int,
+            ReturnType::Float => FFISafeTypeKind::Float,
+            ReturnType::String => FFISafeTypeKind::String,
+            ReturnType::Bool => FFISafeTypeKind::Bool,
+            ReturnType::Function(..) => FFISafeTypeKind::Function,
+            ReturnType::Array(element) => {
+                let element = FFISafeType::from(*element);
+                return FFISafeType {
+                    depth: element.depth + 1,
+                    ..element
+                };
+            }
+        };
+        FFISafeType { kind, depth: 0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamType {
+    // `n: Int`
+    Plain(ReturnType),
+    // `n: [0,]` or `n: [1, 10]`, inclusive, and either end can be left open
+    Range(ReturnType, Option<f64>, Option<f64>),
+}
+
+impl ParamType {
+    pub fn base(&self) -> ReturnType {
+        match self {
+            ParamType::Plain(base) | ParamType::Range(base, _, _) => base.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bound = |b: &Option<f64>, base: &ReturnType| match (b, base) {
+            (None, _) => String::new(),
+            (Some(b), ReturnType::Int) => format!("{}", *b as isize),
+            (Some(b), _) => format!("{:?}", b),
+        };
+        match self {
+            ParamType::Plain(base) => write!(f, "{}", base),
+            ParamType::Range(base, low, high) => {
+                let high = match high {
+                    Some(_) => format!(" {}", bound(high, base)),
+                    None => String::new(),
+                };
+                write!(f, "[{},{}]", bound(low, base), high)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    // whatever was written after the colon, if anything
+    pub ty: Option<ParamType>,
+}
+
+// `[x * 2 for x in xs]` collects every result into an array,
+// `sum(x * 2 for x in xs)` adds them all up instead
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForKind {
+    Collect,
+    Sum,
+}
+
+// what typeck settled on for a definition, everything here is concrete
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub args: Vec<ReturnType>,
+    pub ret: ReturnType,
+}
+
+// `(Int, [Float]) -> Int`
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let args: Vec<String> = self.args.iter().map(|arg| arg.to_string()).collect();
+        write!(f, "({}) -> {}", args.join(", "), self.ret)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -63,55 +193,216 @@ pub enum Expr {
     NumberLiteral(bool, isize, f64),
     // string literal value
     StringLiteral(String),
-    // function name, argument names, function body, return type
-    FunctionDefinition(String, Vec<String>, Box<LocatedExpr>, ReturnType),
+    // `true` or `false`
+    BoolLiteral(bool),
+    // function name, parameters, function body, declared return type, signature (from typeck)
+    FunctionDefinition(
+        String,
+        Vec<Param>,
+        Box<LocatedExpr>,
+        Option<ReturnType>,
+        Option<Signature>,
+    ),
+    // function name, parameters (all typed), return type
+    ExternDeclaration(String, Vec<Param>, ReturnType),
     // chain links -> when expressions as base cases, and finally a recursive expression
     ChainExpression(Vec<LocatedExpr>),
-    // binary op as unsigned int, left, right
-    BinaryOperation(u8, Box<LocatedExpr>, Box<LocatedExpr>),
+    // operator, left, right
+    BinaryOperation(Operator, Box<LocatedExpr>, Box<LocatedExpr>),
+    // operator, operand
+    UnaryOperation(UnaryOperator, Box<LocatedExpr>),
     // predicate, result
     WhenExpression(Box<LocatedExpr>, Box<LocatedExpr>),
     // function name, argument values
     FunctionCall(String, Vec<LocatedExpr>),
     // variable name
     VariableRef(String),
+    // elements, element type (from typeck)
+    ArrayLiteral(Vec<LocatedExpr>, Option<ReturnType>),
+    // array, index
+    Index(Box<LocatedExpr>, Box<LocatedExpr>),
+    // array, from the `len` builtin
+    Length(Box<LocatedExpr>),
+    // what to do with the results, loop variable, what to loop over, body, body type (from typeck)
+    For(
+        ForKind,
+        String,
+        Box<LocatedExpr>,
+        Box<LocatedExpr>,
+        Option<ReturnType>,
+    ),
+    // start, end (exclusive), only shows up as what a `for` loops over
+    Range(Box<LocatedExpr>, Box<LocatedExpr>),
+    // name, value, body (the only place the name can be used)
+    Let(String, Box<LocatedExpr>, Box<LocatedExpr>),
+    // parameters, body, signature (from typeck), captured variables and their types (from typeck)
+    Lambda(
+        Vec<Param>,
+        Box<LocatedExpr>,
+        Option<Signature>,
+        Vec<(String, ReturnType)>,
+    ),
+    // function value, argument values, return type (from typeck)
+    Apply(Box<LocatedExpr>, Vec<LocatedExpr>, Option<ReturnType>),
+    // lifted function, captured variables that get passed before the arguments;
+    // only closure conversion makes these, out of lambdas and function names
+    Closure(String, Vec<String>),
+}
+
+// what a `BinaryOperation` does; codegen has a copy of this (`OpKind`) in the same order
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Power,
+    Multiply,
+    Divide,
+    Remainder,
+    Add,
+    Subtract,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+impl Operator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Operator::Power => "^",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Remainder => "%",
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Less => "<",
+            Operator::Greater => ">",
+            Operator::LessEqual => "<=",
+            Operator::GreaterEqual => ">=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::And => "&&",
+            Operator::Or => "||",
+        }
+    }
+
+    // higher binds tighter, like in c: `a || b && c == d < e + f * g ^ h`
+    // has every operator grouped around its right side
+    pub fn precedence(self) -> u8 {
+        match self {
+            Operator::Or => 1,
+            Operator::And => 2,
+            Operator::Equal | Operator::NotEqual => 3,
+            Operator::Less | Operator::Greater | Operator::LessEqual | Operator::GreaterEqual => 4,
+            Operator::Add | Operator::Subtract => 5,
+            Operator::Multiply | Operator::Divide | Operator::Remainder => 6,
+            Operator::Power => 7,
+        }
+    }
+
+    // the ones that give a Bool whatever they're comparing
+    pub fn compares(self) -> bool {
+        matches!(
+            self,
+            Operator::Less
+                | Operator::Greater
+                | Operator::LessEqual
+                | Operator::GreaterEqual
+                | Operator::Equal
+                | Operator::NotEqual
+        )
+    }
+
+    // `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`, everything else groups from the left
+    pub fn right_associative(self) -> bool {
+        self == Operator::Power
+    }
+}
+
+// what a prefix `UnaryOperation` does, codegen's copy is `UnaryKind`; these bind
+// tighter than any binary operator except `^`, so `-2 ^ 2` is `-(2 ^ 2)`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Negate,
+    Plus,
+    Not,
 }
 
+impl UnaryOperator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Plus => "+",
+            UnaryOperator::Not => "!",
+        }
+    }
+}
+
+// boxed so the allocation is exactly `len` long, `drop_all` and friends rely on that
 fn map_vec<T: Clone, U>(from: Vec<T>, f: fn(T) -> U) -> (*mut U, usize) {
-    let mut converted: Vec<U> = from.clone().into_iter().map(f).collect();
-    let ptr = converted.as_mut_ptr();
-    std::mem::forget(converted);
-    (ptr, from.len())
+    let converted: Box<[U]> = from.clone().into_iter().map(f).collect();
+    (Box::into_raw(converted) as *mut U, from.len())
 }
 
 pub fn convert_vec(from: Vec<LocatedExpr>) -> (*mut LocatedFFISafeExpr, usize) {
-    map_vec(from, |e| convert_expr(e))
+    map_vec(from, convert_expr)
 }
 
 fn convert_str_vec(from: Vec<String>) -> (*mut *mut c_char, usize) {
-    map_vec(from, |s| convert_str(s))
+    map_vec(from, convert_str)
 }
 
-fn convert_box(from: Box<LocatedExpr>) -> *mut LocatedFFISafeExpr {
-    Box::into_raw(convert_expr(*from).into())
+fn convert_box(from: LocatedExpr) -> *mut LocatedFFISafeExpr {
+    Box::into_raw(Box::new(convert_expr(from)))
 }
 
 pub fn convert_str(from: String) -> *mut c_char {
     let cstr = CString::new(from).unwrap();
-    return cstr.into_raw();
+    cstr.into_raw()
 }
 
 pub fn convert_expr(expr: LocatedExpr) -> LocatedFFISafeExpr {
     let located = match expr.expr {
         Expr::NumberLiteral(is_f, int, float) => FFISafeExpr::NumberLiteral(is_f, int, float),
         Expr::StringLiteral(src) => FFISafeExpr::StringLiteral(convert_str(src)),
-        Expr::FunctionDefinition(name, args, body, _) => {
-            let arg_vec = convert_str_vec(args);
+        Expr::BoolLiteral(value) => FFISafeExpr::BoolLiteral(value),
+        Expr::FunctionDefinition(name, args, body, _, signature) => {
+            let Signature {
+                args: arg_types,
+                ret: return_type,
+            } = signature.expect("definitions have to go through typeck before the ffi");
+            let arg_vec = convert_str_vec(args.into_iter().map(|p| p.name).collect());
+            let type_vec = map_vec(arg_types, FFISafeType::from);
             FFISafeExpr::FunctionDefinition(
                 convert_str(name),
                 arg_vec.0,
+                type_vec.0,
                 arg_vec.1,
-                convert_box(body),
+                convert_box(*body),
+                return_type.into(),
+            )
+        }
+        Expr::ExternDeclaration(name, params, return_type) => {
+            let arg_types = params
+                .iter()
+                .map(|p| {
+                    p.ty.as_ref()
+                        .expect("extern parameters are always typed")
+                        .base()
+                })
+                .collect();
+            let arg_vec = convert_str_vec(params.into_iter().map(|p| p.name).collect());
+            let type_vec = map_vec(arg_types, FFISafeType::from);
+            FFISafeExpr::ExternDeclaration(
+                convert_str(name),
+                arg_vec.0,
+                type_vec.0,
+                arg_vec.1,
+                return_type.into(),
             )
         }
         Expr::ChainExpression(exprs) => {
@@ -119,16 +410,54 @@ pub fn convert_expr(expr: LocatedExpr) -> LocatedFFISafeExpr {
             FFISafeExpr::ChainExpression(expr_vec.0, expr_vec.1)
         }
         Expr::BinaryOperation(op, left, right) => {
-            FFISafeExpr::BinaryOperation(op, convert_box(left), convert_box(right))
+            FFISafeExpr::BinaryOperation(op, convert_box(*left), convert_box(*right))
         }
+        Expr::UnaryOperation(op, operand) => FFISafeExpr::UnaryOperation(op, convert_box(*operand)),
         Expr::WhenExpression(predicate, result) => {
-            FFISafeExpr::WhenExpression(convert_box(predicate), convert_box(result))
+            FFISafeExpr::WhenExpression(convert_box(*predicate), convert_box(*result))
         }
         Expr::FunctionCall(name, args) => {
             let arg_vec = convert_vec(args);
             FFISafeExpr::FunctionCall(convert_str(name), arg_vec.0, arg_vec.1)
         }
         Expr::VariableRef(name) => FFISafeExpr::VariableRef(convert_str(name)),
+        Expr::ArrayLiteral(elements, element_type) => {
+            let element_vec = convert_vec(elements);
+            let element_type =
+                element_type.expect("array literals have to go through typeck before the ffi");
+            FFISafeExpr::ArrayLiteral(element_vec.0, element_vec.1, element_type.into())
+        }
+        Expr::Index(array, index) => FFISafeExpr::Index(convert_box(*array), convert_box(*index)),
+        Expr::Length(array) => FFISafeExpr::Length(convert_box(*array)),
+        Expr::For(kind, variable, iterable, body, body_type) => {
+            let body_type = body_type.expect("loops have to go through typeck before the ffi");
+            FFISafeExpr::For(
+                kind,
+                convert_str(variable),
+                convert_box(*iterable),
+                convert_box(*body),
+                body_type.into(),
+            )
+        }
+        Expr::Range(start, end) => FFISafeExpr::Range(convert_box(*start), convert_box(*end)),
+        Expr::Let(name, value, body) => {
+            FFISafeExpr::Let(convert_str(name), convert_box(*value), convert_box(*body))
+        }
+        Expr::Lambda(..) => panic!("lambdas have to go through closure conversion before the ffi"),
+        Expr::Apply(function, args, return_type) => {
+            let arg_vec = convert_vec(args);
+            let return_type = return_type.expect("calls have to go through typeck before the ffi");
+            FFISafeExpr::Apply(
+                convert_box(*function),
+                arg_vec.0,
+                arg_vec.1,
+                return_type.into(),
+            )
+        }
+        Expr::Closure(name, captures) => {
+            let capture_vec = convert_str_vec(captures);
+            FFISafeExpr::Closure(convert_str(name), capture_vec.0, capture_vec.1)
+        }
     };
     LocatedFFISafeExpr::new(located, expr.location)
 }
@@ -140,28 +469,79 @@ pub enum FFISafeExpr {
     NumberLiteral(bool, isize, f64),
     // string literal value
     StringLiteral(*mut c_char),
-    // function name, function args start pointer, function args length, function body
+    // `true` or `false`
+    BoolLiteral(bool),
+    // function name, function args start pointer, function arg types start pointer,
+    // function args length, function body, return type
     FunctionDefinition(
         *mut c_char,
         *mut *mut c_char,
+        *mut FFISafeType,
         usize,
         *mut LocatedFFISafeExpr,
+        FFISafeType,
+    ),
+    // function name, function args start pointer, function arg types start pointer,
+    // function args length, return type
+    ExternDeclaration(
+        *mut c_char,
+        *mut *mut c_char,
+        *mut FFISafeType,
+        usize,
+        FFISafeType,
     ),
     // chain links start pointer, chain length
     ChainExpression(*mut LocatedFFISafeExpr, usize),
-    // binary op as unsigned int, left, right
-    BinaryOperation(u8, *mut LocatedFFISafeExpr, *mut LocatedFFISafeExpr),
+    // operator, left, right
+    BinaryOperation(Operator, *mut LocatedFFISafeExpr, *mut LocatedFFISafeExpr),
+    // operator, operand
+    UnaryOperation(UnaryOperator, *mut LocatedFFISafeExpr),
     // predicate, result
     WhenExpression(*mut LocatedFFISafeExpr, *mut LocatedFFISafeExpr),
     // function name, argument values start pointer, argument values length
     FunctionCall(*mut c_char, *mut LocatedFFISafeExpr, usize),
     // variable name
     VariableRef(*mut c_char),
+    // elements start pointer, elements length, element type
+    ArrayLiteral(*mut LocatedFFISafeExpr, usize, FFISafeType),
+    // array, index
+    Index(*mut LocatedFFISafeExpr, *mut LocatedFFISafeExpr),
+    // array
+    Length(*mut LocatedFFISafeExpr),
+    // what to do with the results, loop variable, what to loop over, body, body type
+    For(
+        ForKind,
+        *mut c_char,
+        *mut LocatedFFISafeExpr,
+        *mut LocatedFFISafeExpr,
+        FFISafeType,
+    ),
+    // start, end (exclusive)
+    Range(*mut LocatedFFISafeExpr, *mut LocatedFFISafeExpr),
+    // name, value, body
+    Let(
+        *mut c_char,
+        *mut LocatedFFISafeExpr,
+        *mut LocatedFFISafeExpr,
+    ),
+    // function value, argument values start pointer, argument values length, return type
+    Apply(
+        *mut LocatedFFISafeExpr,
+        *mut LocatedFFISafeExpr,
+        usize,
+        FFISafeType,
+    ),
+    // lifted function name, captured names start pointer, captured names length
+    Closure(*mut c_char, *mut *mut c_char, usize),
 }
 
 #[repr(C)]
 pub struct FFISafeExprVec {
     pub ptr: *mut LocatedFFISafeExpr,
     pub len: usize,
-    pub out: *mut c_char,
+    pub options: FFISafeOptions,
+    // number of diagnostics already printed, `ptr` is null if this isn't zero
+    pub errors: usize,
+    // nothing is left for codegen to do (`--help`, `--emit=ast`...), `ptr` is null
+    pub finished: bool,
 }
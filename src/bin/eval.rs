@@ -0,0 +1,81 @@
+use rfx::{
+    errors::{Diagnostic, Location},
+    eval::Evaluator,
+    loader::Loader,
+    options::{Action, Options},
+    repl::Repl,
+    typeck::TypeChecker,
+};
+use std::{fs, io, process::ExitCode};
+
+const USAGE: &str = "usage: fx-eval <file> [-I <dir>] [--entry <name>] [--error-format=human|json]
+       fx-eval repl [-I <dir>]";
+
+// runs a script's `entry()` through the tree-walking evaluator, no llvm needed;
+// takes the same flags as `fx`, and ignores the ones that only matter to codegen
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().collect()) {
+        Ok(options) if options.action == Action::Compile => options,
+        Ok(options) if options.action == Action::Repl => {
+            let repl = Repl::new().with_include_paths(&options.include_paths);
+            return match repl.run(io::stdin().lock(), io::stdout().lock()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Ok(_) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprint!("{}", Diagnostic::from(e));
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let entry = options.entry.clone().unwrap_or("entry".to_string());
+
+    let checked = Loader::new()
+        .with_include_paths(&options.include_paths)
+        .load(&options.filename)
+        .and_then(|tree| {
+            let mut checker = TypeChecker::new();
+            let tree = checker.check(tree)?;
+            checker
+                .check_entry(&entry)
+                .map_err(|e| vec![Diagnostic::from(e)])?;
+            Ok(tree)
+        });
+    let tree = match checked {
+        Ok(tree) => tree,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprint!("{}", options.error_format.render(&diagnostic, &from_disk));
+            }
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match Evaluator::new(&tree).call(&entry, vec![], Location::internal().unwrap()) {
+        Ok(value) => {
+            println!("{}", value);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprint!(
+                "{}",
+                options
+                    .error_format
+                    .render(&Diagnostic::from(e), &from_disk)
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn from_disk(filename: &str) -> Option<String> {
+    fs::read_to_string(filename).ok()
+}
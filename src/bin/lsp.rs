@@ -0,0 +1,6 @@
+use std::{io, process::ExitCode};
+
+// speaks the language server protocol over stdin and stdout, point an editor at it
+fn main() -> ExitCode {
+    rfx::lsp::run(io::stdin().lock(), io::stdout().lock())
+}
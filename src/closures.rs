@@ -0,0 +1,222 @@
+use crate::ast::*;
+
+// closure conversion, which runs between typeck and the ffi: every lambda becomes a
+// definition of its own that takes whatever it captured before its own parameters,
+// and every function used as a value becomes a `Closure` codegen can call through
+pub fn convert(tree: Vec<LocatedExpr>) -> Vec<LocatedExpr> {
+    let mut converted = vec![];
+    for def in tree {
+        let Expr::FunctionDefinition(name, params, body, declared, signature) = def.get_expr()
+        else {
+            converted.push(def);
+            continue;
+        };
+        let mut lifter = Lifter {
+            prefix: name.clone(),
+            lifted: vec![],
+        };
+        let locals: Vec<String> = params.iter().map(|p| p.name.clone()).collect();
+        let body = lifter.expr(*body, &locals);
+        // lambdas go right before the definition they came from; codegen declares
+        // everything up front, so either one can use the other
+        converted.extend(lifter.lifted);
+        converted.push(LocatedExpr::new(
+            Expr::FunctionDefinition(name, params, body.into(), declared, signature),
+            def.get_location(),
+        ));
+    }
+    converted
+}
+
+struct Lifter {
+    // the definition the lambdas are in, `f.lambda0` can't clash with anything
+    // written by hand since names can't have a dot in them
+    prefix: String,
+    lifted: Vec<LocatedExpr>,
+}
+
+impl Lifter {
+    // `locals` are the names bound around `expr`, anything else is a definition
+    fn expr(&mut self, expr: LocatedExpr, locals: &[String]) -> LocatedExpr {
+        let location = expr.get_location();
+        let within = |name: &str| -> Vec<String> {
+            let mut inner = locals.to_vec();
+            inner.push(name.to_string());
+            inner
+        };
+        let converted = match expr.get_expr() {
+            Expr::VariableRef(name) if !locals.contains(&name) => Expr::Closure(name, vec![]),
+            Expr::Lambda(params, body, signature, captures) => {
+                let signature =
+                    signature.expect("lambdas have to go through typeck before closure conversion");
+                let (names, types): (Vec<String>, Vec<ReturnType>) = captures.into_iter().unzip();
+                let mut inner = names.clone();
+                inner.extend(params.iter().map(|p| p.name.clone()));
+                let body = self.expr(*body, &inner);
+
+                let name = format!("{}.lambda{}", self.prefix, self.lifted.len());
+                let params = names
+                    .iter()
+                    .map(|name| Param {
+                        name: name.clone(),
+                        ty: None,
+                    })
+                    .chain(params)
+                    .collect();
+                let signature = Signature {
+                    args: types.into_iter().chain(signature.args).collect(),
+                    ret: signature.ret,
+                };
+                self.lifted.push(LocatedExpr::new(
+                    Expr::FunctionDefinition(
+                        name.clone(),
+                        params,
+                        body.into(),
+                        None,
+                        Some(signature),
+                    ),
+                    location.clone(),
+                ));
+                Expr::Closure(name, names)
+            }
+            Expr::Let(name, value, body) => {
+                let value = self.expr(*value, locals);
+                let body = self.expr(*body, &within(&name));
+                Expr::Let(name, value.into(), body.into())
+            }
+            Expr::For(kind, variable, iterable, body, body_type) => {
+                let iterable = self.expr(*iterable, locals);
+                let body = self.expr(*body, &within(&variable));
+                Expr::For(kind, variable, iterable.into(), body.into(), body_type)
+            }
+            Expr::Apply(function, args, ret) => {
+                let function = self.expr(*function, locals);
+                Expr::Apply(function.into(), self.all(args, locals), ret)
+            }
+            Expr::FunctionCall(name, args) => Expr::FunctionCall(name, self.all(args, locals)),
+            Expr::ChainExpression(links) => Expr::ChainExpression(self.all(links, locals)),
+            Expr::ArrayLiteral(elements, element_type) => {
+                Expr::ArrayLiteral(self.all(elements, locals), element_type)
+            }
+            Expr::BinaryOperation(op, left, right) => Expr::BinaryOperation(
+                op,
+                self.expr(*left, locals).into(),
+                self.expr(*right, locals).into(),
+            ),
+            Expr::UnaryOperation(op, operand) => {
+                Expr::UnaryOperation(op, self.expr(*operand, locals).into())
+            }
+            Expr::WhenExpression(predicate, result) => Expr::WhenExpression(
+                self.expr(*predicate, locals).into(),
+                self.expr(*result, locals).into(),
+            ),
+            Expr::Index(array, index) => Expr::Index(
+                self.expr(*array, locals).into(),
+                self.expr(*index, locals).into(),
+            ),
+            Expr::Length(array) => Expr::Length(self.expr(*array, locals).into()),
+            Expr::Range(start, end) => Expr::Range(
+                self.expr(*start, locals).into(),
+                self.expr(*end, locals).into(),
+            ),
+            other => other,
+        };
+        LocatedExpr::new(converted, location)
+    }
+
+    fn all(&mut self, exprs: Vec<LocatedExpr>, locals: &[String]) -> Vec<LocatedExpr> {
+        exprs.into_iter().map(|e| self.expr(e, locals)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::*, parser::Parser, typeck::TypeChecker};
+
+    fn converted(file: &str) -> Vec<LocatedExpr> {
+        let tree = Parser::new(&format!("src/tests/{}.txt", file))
+            .unwrap()
+            .run()
+            .unwrap();
+        convert(TypeChecker::new().check(tree).unwrap())
+    }
+
+    fn find(tree: &[LocatedExpr], name: &str) -> Expr {
+        tree.iter()
+            .map(|def| def.get_expr())
+            .find(|def| matches!(def, Expr::FunctionDefinition(n, ..) if n == name))
+            .unwrap()
+    }
+
+    #[test]
+    fn lifting() {
+        let tree = converted("lambdas");
+        let names: Vec<String> = tree
+            .iter()
+            .filter_map(|def| match def.get_expr() {
+                Expr::FunctionDefinition(name, ..) => Some(name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "twice",
+                "adder.lambda0",
+                "adder",
+                "compose.lambda0",
+                "compose",
+                "double",
+                "map",
+                "scaled.lambda0",
+                "scaled",
+                "curried.lambda0",
+                "curried.lambda1",
+                "curried",
+                "entry.lambda0",
+                "entry",
+            ]
+        );
+
+        // captures come first, then the lambda's own parameters
+        let Expr::FunctionDefinition(_, params, _, _, signature) = find(&tree, "curried.lambda0")
+        else {
+            panic!("expected a definition");
+        };
+        let params: Vec<String> = params.into_iter().map(|p| p.name).collect();
+        assert_eq!(params, ["a", "b", "c"]);
+        assert_eq!(signature.unwrap().to_string(), "(Int, Int, Int) -> Int");
+        let Expr::FunctionDefinition(_, _, body, ..) = find(&tree, "curried.lambda1") else {
+            panic!("expected a definition");
+        };
+        let Expr::Closure(name, captures) = body.get_expr() else {
+            panic!("expected a closure");
+        };
+        assert_eq!(
+            (name.as_str(), captures),
+            ("curried.lambda0", vec!["a".to_string(), "b".to_string()])
+        );
+    }
+
+    #[test]
+    fn same_result() {
+        // lifted lambdas are plain definitions, so the evaluator runs them like any other
+        let tree = converted("lambdas");
+        assert_eq!(Evaluator::new(&tree).entry().unwrap(), Value::Int(39));
+    }
+
+    #[test]
+    fn recursive() {
+        let tree = converted("recursive_lambda");
+        // it's lifted out ahead of `countdown` but still calls it
+        let Expr::FunctionDefinition(_, _, body, ..) = find(&tree, "countdown.lambda0") else {
+            panic!("expected a definition");
+        };
+        let Expr::BinaryOperation(_, call, _) = body.get_expr() else {
+            panic!("expected an addition");
+        };
+        assert!(matches!(call.get_expr(), Expr::FunctionCall(name, _) if name == "countdown"));
+        assert_eq!(Evaluator::new(&tree).entry().unwrap(), Value::Int(5));
+    }
+}
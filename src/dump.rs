@@ -0,0 +1,345 @@
+use crate::{
+    ast::{Expr, ForKind, LocatedExpr, Param},
+    errors::Location,
+    json::Json,
+    lexer::{Bracket, Is, LocatedToken, Symbol, Token},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Text,
+    Json,
+}
+
+// what `--emit=tokens` and `--emit=ast` print; tokens and expressions both get
+// turned into these so the text and json output only have to be written once
+pub struct Node {
+    kind: &'static str,
+    // the small stuff that fits on one line, like a name or an operator
+    fields: Vec<(&'static str, Json)>,
+    location: Location,
+    children: Vec<Node>,
+}
+
+pub trait Dump {
+    fn dump(&self) -> Node;
+}
+
+pub fn render<T: Dump>(items: &[T], format: DumpFormat) -> String {
+    let nodes = items.iter().map(Dump::dump);
+    match format {
+        DumpFormat::Text => {
+            let mut out = String::new();
+            for node in nodes {
+                node.write_text(0, &mut out);
+            }
+            out
+        }
+        DumpFormat::Json => format!("{}\n", Json::Array(nodes.map(|n| n.to_json()).collect())),
+    }
+}
+
+impl Node {
+    fn new(kind: &'static str, location: Location) -> Node {
+        Node {
+            kind,
+            fields: vec![],
+            location,
+            children: vec![],
+        }
+    }
+
+    fn field(mut self, name: &'static str, value: Json) -> Node {
+        self.fields.push((name, value));
+        self
+    }
+
+    fn children<T: Dump>(mut self, children: &[T]) -> Node {
+        self.children.extend(children.iter().map(Dump::dump));
+        self
+    }
+
+    pub fn to_json(&self) -> Json {
+        let mut fields = vec![
+            ("kind".to_string(), self.kind.into()),
+            ("location".to_string(), self.location.to_json()),
+        ];
+        fields.extend(
+            self.fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone())),
+        );
+        let children = self.children.iter().map(Node::to_json).collect();
+        fields.push(("children".to_string(), Json::Array(children)));
+        Json::Object(fields)
+    }
+
+    // `Kind name="fib" @file:1:1`, children indented underneath; anything that
+    // wasn't filled in (like types before typeck) is left off
+    fn write_text(&self, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(self.kind);
+        for (name, value) in &self.fields {
+            if *value != Json::Null {
+                out.push_str(&format!(" {}={}", name, value));
+            }
+        }
+        // the full path is only worth repeating at the top level
+        if depth == 0 {
+            out.push_str(&format!(" {}\n", self.location.get_message()));
+        } else {
+            let (line, column) = (self.location.get_line(), self.location.get_column());
+            out.push_str(&format!(" @{}:{}\n", line + 1, column + 1));
+        }
+        for child in &self.children {
+            child.write_text(depth + 1, out);
+        }
+    }
+}
+
+fn symbol(s: &Symbol) -> String {
+    match s {
+        Symbol::Multiply => "*".to_string(),
+        Symbol::Divide => "/".to_string(),
+        Symbol::Add => "+".to_string(),
+        Symbol::Subtract => "-".to_string(),
+        Symbol::Remainder => "%".to_string(),
+        Symbol::ToPower => "^".to_string(),
+        Symbol::Comma => ",".to_string(),
+        Symbol::Equals => "=".to_string(),
+        Symbol::Colon => ":".to_string(),
+        Symbol::GreaterThan => ">".to_string(),
+        Symbol::LessThan => "<".to_string(),
+        Symbol::Ampersand => "&".to_string(),
+        Symbol::Pipe => "|".to_string(),
+        Symbol::Negate => "!".to_string(),
+        Symbol::Dot => ".".to_string(),
+        Symbol::Backslash => "\\".to_string(),
+        Symbol::Compound(first, second) => symbol(first) + &symbol(second),
+    }
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> Json {
+    value.as_ref().map_or(Json::Null, |v| v.to_string().into())
+}
+
+fn params(params: &[Param]) -> Json {
+    Json::Array(
+        params
+            .iter()
+            .map(|param| match &param.ty {
+                Some(ty) => format!("{}: {}", param.name, ty).into(),
+                None => param.name.as_str().into(),
+            })
+            .collect(),
+    )
+}
+
+impl Dump for LocatedToken {
+    fn dump(&self) -> Node {
+        let location = self.loc();
+        match self.tok() {
+            Token::Extern => Node::new("Extern", location),
+            Token::Import => Node::new("Import", location),
+            Token::When => Node::new("When", location),
+            Token::Let => Node::new("Let", location),
+            Token::For => Node::new("For", location),
+            Token::In => Node::new("In", location),
+            Token::Newline => Node::new("Newline", location),
+            Token::Identifier(name) => Node::new("Identifier", location).field("name", name.into()),
+            Token::Number(int, float) => {
+                let value = match (int, float) {
+                    (Some(int), _) => Json::Int(int as i64),
+                    (_, Some(float)) => Json::Float(float),
+                    _ => Json::Null,
+                };
+                Node::new("Number", location).field("value", value)
+            }
+            Token::String(value) => Node::new("String", location).field("value", value.into()),
+            Token::Bool(value) => Node::new("Bool", location).field("value", value.into()),
+            Token::Symbol(s) => Node::new("Symbol", location).field("symbol", symbol(&s).into()),
+            Token::Bracket(bracket) => {
+                let text = match bracket {
+                    Bracket::Parens(Is::Open) => "(",
+                    Bracket::Parens(Is::Closed) => ")",
+                    Bracket::Square(Is::Open) => "[",
+                    Bracket::Square(Is::Closed) => "]",
+                };
+                Node::new("Bracket", location).field("bracket", text.into())
+            }
+            Token::Grouping(tokens) => Node::new("Grouping", location).children(&tokens),
+            Token::FunctionCall(name, args) => Node::new("FunctionCall", location)
+                .field("name", name.into())
+                .children(&args),
+            // whatever's being indexed comes first, then what's in the brackets
+            Token::Index(target, inside) => Node::new("Index", location)
+                .children(&[*target])
+                .children(&inside),
+        }
+    }
+}
+
+impl Dump for LocatedExpr {
+    fn dump(&self) -> Node {
+        let location = self.get_location();
+        match self.get_expr() {
+            Expr::NumberLiteral(is_float, int, float) => {
+                let value = match is_float {
+                    true => Json::Float(float),
+                    false => Json::Int(int as i64),
+                };
+                Node::new("NumberLiteral", location).field("value", value)
+            }
+            Expr::StringLiteral(value) => {
+                Node::new("StringLiteral", location).field("value", value.into())
+            }
+            Expr::BoolLiteral(value) => {
+                Node::new("BoolLiteral", location).field("value", value.into())
+            }
+            Expr::FunctionDefinition(name, args, body, returns, signature) => {
+                Node::new("FunctionDefinition", location)
+                    .field("name", name.into())
+                    .field("params", params(&args))
+                    .field("returns", optional(&returns))
+                    .field("signature", optional(&signature))
+                    .children(&[*body])
+            }
+            Expr::ExternDeclaration(name, args, returns) => {
+                Node::new("ExternDeclaration", location)
+                    .field("name", name.into())
+                    .field("params", params(&args))
+                    .field("returns", returns.to_string().into())
+            }
+            Expr::ChainExpression(links) => Node::new("ChainExpression", location).children(&links),
+            Expr::BinaryOperation(op, left, right) => Node::new("BinaryOperation", location)
+                .field("op", op.symbol().into())
+                .children(&[*left, *right]),
+            Expr::UnaryOperation(op, operand) => Node::new("UnaryOperation", location)
+                .field("op", op.symbol().into())
+                .children(&[*operand]),
+            Expr::WhenExpression(predicate, result) => {
+                Node::new("WhenExpression", location).children(&[*predicate, *result])
+            }
+            Expr::FunctionCall(name, args) => Node::new("FunctionCall", location)
+                .field("name", name.into())
+                .children(&args),
+            Expr::VariableRef(name) => {
+                Node::new("VariableRef", location).field("name", name.into())
+            }
+            Expr::ArrayLiteral(elements, ty) => Node::new("ArrayLiteral", location)
+                .field("element", optional(&ty))
+                .children(&elements),
+            Expr::Index(array, index) => Node::new("Index", location).children(&[*array, *index]),
+            Expr::Length(array) => Node::new("Length", location).children(&[*array]),
+            Expr::For(kind, name, iterable, body, ty) => {
+                let kind = match kind {
+                    ForKind::Collect => "collect",
+                    ForKind::Sum => "sum",
+                };
+                Node::new("For", location)
+                    .field("kind", kind.into())
+                    .field("variable", name.into())
+                    .field("body", optional(&ty))
+                    .children(&[*iterable, *body])
+            }
+            Expr::Range(start, end) => Node::new("Range", location).children(&[*start, *end]),
+            Expr::Let(name, value, body) => Node::new("Let", location)
+                .field("name", name.into())
+                .children(&[*value, *body]),
+            Expr::Lambda(args, body, signature, captures) => {
+                let captures = match signature {
+                    Some(_) => Json::Array(
+                        captures
+                            .iter()
+                            .map(|(name, ty)| format!("{}: {}", name, ty).into())
+                            .collect(),
+                    ),
+                    None => Json::Null,
+                };
+                Node::new("Lambda", location)
+                    .field("params", params(&args))
+                    .field("signature", optional(&signature))
+                    .field("captures", captures)
+                    .children(&[*body])
+            }
+            Expr::Apply(function, args, returns) => Node::new("Apply", location)
+                .field("returns", optional(&returns))
+                .children(&[*function])
+                .children(&args),
+            Expr::Closure(name, captures) => Node::new("Closure", location)
+                .field("name", name.into())
+                .field(
+                    "captures",
+                    Json::Array(captures.into_iter().map(Json::from).collect()),
+                ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Interpreter, parser::Parser, typeck::TypeChecker};
+    use std::{fs::File, io::BufReader};
+
+    const FILE: &str = "src/tests/entries.txt";
+
+    fn tree() -> Vec<LocatedExpr> {
+        Parser::new(FILE).unwrap().run().unwrap()
+    }
+
+    #[test]
+    fn tokens() {
+        let file = BufReader::new(File::open(FILE).unwrap());
+        let tokens = Interpreter::from_reader(file, FILE.to_string())
+            .unwrap()
+            .pull()
+            .unwrap();
+        let text = render(&tokens, DumpFormat::Text);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Let @src/tests/entries.txt:1:1");
+        assert_eq!(
+            lines[1],
+            "Identifier name=\"start\" @src/tests/entries.txt:1:5"
+        );
+        // `start()` is already a call by the time it gets printed
+        assert!(lines.contains(&"FunctionCall name=\"start\" @src/tests/entries.txt:3:15"));
+        assert!(lines.contains(&"Number value=42 @src/tests/entries.txt:1:15"));
+    }
+
+    #[test]
+    fn text() {
+        let expected = "\
+FunctionDefinition name=\"start\" params=[] @src/tests/entries.txt:1:5
+  NumberLiteral value=42 @1:15
+FunctionDefinition name=\"entry\" params=[] @src/tests/entries.txt:3:5
+  BinaryOperation op=\"+\" @3:23
+    FunctionCall name=\"start\" @3:15
+    NumberLiteral value=1 @3:25
+FunctionDefinition name=\"twice\" params=[\"n\"] @src/tests/entries.txt:5:5
+  BinaryOperation op=\"*\" @5:18
+    VariableRef name=\"n\" @5:16
+    NumberLiteral value=2 @5:20
+";
+        assert_eq!(render(&tree(), DumpFormat::Text), expected);
+
+        // types only show up once typeck has filled them in
+        let typed = TypeChecker::new().check(tree()).unwrap();
+        let text = render(&typed, DumpFormat::Text);
+        assert!(text.contains("params=[\"n\"] signature=\"(Int) -> Int\""));
+    }
+
+    #[test]
+    fn json() {
+        let json = render(&tree()[..1], DumpFormat::Json);
+        assert!(json.starts_with(
+            "[{\"kind\":\"FunctionDefinition\",\"location\":{\"file\":\"src/tests/entries.txt\",\
+             \"line\":1,\"column\":5,"
+        ));
+        assert!(json.contains(
+            "\"name\":\"start\",\"params\":[],\"returns\":null,\"signature\":null,\
+             \"children\":[{\"kind\":\"NumberLiteral\""
+        ));
+        assert!(json.ends_with("\"value\":42,\"children\":[]}]}]\n"));
+    }
+}
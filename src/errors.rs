@@ -1,13 +1,16 @@
-use std::{error::Error, fmt};
+use crate::json::Json;
+use std::{error::Error, fmt, fs};
 
 pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Location {
     line: usize,
     column: usize,
     filename: String,
-    last_line_width: usize
+    // byte offsets into the source file, `end` is exclusive
+    start: usize,
+    end: usize,
 }
 
 impl Location {
@@ -16,17 +19,13 @@ impl Location {
             line,
             column,
             filename,
-            last_line_width: 0
+            start: 0,
+            end: 0,
         }
     }
 
     pub fn internal() -> Option<Location> {
-        Some(Location {
-            line: 0,
-            column: 0,
-            filename: "interal".to_string(),
-            last_line_width: 0
-        })
+        Some(Location::new(0, 0, "interal".to_string()))
     }
 
     pub fn get_line(&self) -> usize {
@@ -41,26 +40,237 @@ impl Location {
         self.filename.clone()
     }
 
+    pub fn get_start(&self) -> usize {
+        self.start
+    }
+
+    pub fn get_end(&self) -> usize {
+        self.end
+    }
+
+    pub fn with_end(mut self, end: usize) -> Location {
+        self.end = end.max(self.start);
+        self
+    }
+
     pub fn next_line(&mut self) {
-        self.last_line_width = self.column;
         self.column = 0;
         self.line += 1;
+        self.start += 1;
+        self.end = self.start;
+    }
+
+    // past a character `width` columns wide and `bytes` long
+    pub fn next_column(&mut self, width: usize, bytes: usize) {
+        self.column += width;
+        self.start += bytes;
+        self.end = self.start;
+    }
+
+    pub fn get_message(&self) -> String {
+        format!("@{}:{}:{}", self.filename, self.line + 1, self.column + 1)
     }
 
-    pub fn previous_column(&mut self) {
-        if self.column == 0 {
-            self.line -= 1;
-            self.column = self.last_line_width;
+    // lines and columns are one-based here too, same as `get_message`
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("file", self.filename.clone().into()),
+            ("line", (self.line + 1).into()),
+            ("column", (self.column + 1).into()),
+            ("start", self.start.into()),
+            ("end", self.end.into()),
+        ])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    location: Option<Location>,
+    stage_name: String,
+    message: String,
+    labels: Vec<(Location, String)>,
+    notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: &str, location: Option<Location>, stage_name: &str) -> Diagnostic {
+        Diagnostic {
+            location,
+            stage_name: stage_name.to_string(),
+            message: message.to_string(),
+            labels: vec![],
+            notes: vec![],
         }
-        self.column -= 1;
     }
 
-    pub fn next_column(&mut self) {
-        self.column += 1;
+    pub fn get_location(&self) -> Option<Location> {
+        self.location.clone()
     }
 
     pub fn get_message(&self) -> String {
-        format!("@{}:{}:{}", self.filename, self.line + 1, self.column + 1)
+        self.message.clone()
+    }
+
+    pub fn get_labels(&self) -> &[(Location, String)] {
+        &self.labels
+    }
+
+    pub fn get_notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    // secondary spans, e.g. "function declared here"
+    pub fn with_label(mut self, location: Location, label: &str) -> Diagnostic {
+        self.labels.push((location, label.to_string()));
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    // one line per diagnostic, for `--error-format=json`
+    pub fn to_json(&self) -> Json {
+        let labels = self
+            .labels
+            .iter()
+            .map(|(location, label)| {
+                Json::object(vec![
+                    ("location", location.to_json()),
+                    ("label", label.as_str().into()),
+                ])
+            })
+            .collect();
+        Json::object(vec![
+            ("stage", self.stage_name.as_str().into()),
+            ("message", self.message.as_str().into()),
+            (
+                "location",
+                self.location.as_ref().map_or(Json::Null, Location::to_json),
+            ),
+            ("labels", Json::Array(labels)),
+            (
+                "notes",
+                Json::Array(self.notes.iter().map(|n| n.as_str().into()).collect()),
+            ),
+        ])
+    }
+
+    // `source_of` maps a filename to its contents; spans whose source can't be
+    // found are just left out
+    pub fn render(&self, source_of: &dyn Fn(&str) -> Option<String>) -> String {
+        let msg = if let Some(l) = self.location.clone() {
+            l.get_message()
+        } else {
+            "internal".to_string()
+        };
+        let mut out = format!(
+            "\n\nERROR: [{}] {}\n >>   {}\n",
+            self.stage_name, self.message, msg
+        );
+
+        let mut spans = vec![];
+        if let Some(location) = &self.location {
+            spans.push((location, '^', ""));
+        }
+        for (location, label) in &self.labels {
+            spans.push((location, '-', label.as_str()));
+        }
+
+        // a label can point into another file (e.g. a clash with an import), which gets its own header
+        let primary = self.location.as_ref().map(|l| l.filename.as_str());
+        let snippets: Vec<(Option<String>, usize, String, String)> = spans
+            .into_iter()
+            .filter_map(|(location, marker, label)| {
+                let source = source_of(&location.filename)?;
+                let header =
+                    (primary != Some(location.filename.as_str())).then(|| location.get_message());
+                let (line, text, underline) =
+                    Diagnostic::snippet(&source, location, marker, label)?;
+                Some((header, line, text, underline))
+            })
+            .collect();
+        let width = snippets
+            .iter()
+            .map(|(_, line, _, _)| (line + 1).to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(width + 1);
+
+        for (header, line, text, underline) in snippets {
+            if let Some(header) = header {
+                out += &format!(" >>   {}\n", header);
+            }
+            out += &format!(
+                "{} |\n {:>width$} | {}\n{} | {}\n",
+                gutter,
+                line + 1,
+                text,
+                gutter,
+                underline
+            );
+        }
+        for note in &self.notes {
+            out += &format!("{} = note: {}\n", gutter, note);
+        }
+        out + "\n"
+    }
+
+    // the line the span starts on, and the marker line to print under it
+    fn snippet(
+        source: &str,
+        location: &Location,
+        marker: char,
+        label: &str,
+    ) -> Option<(usize, String, String)> {
+        let start = location.start;
+        if start > source.len() || !source.is_char_boundary(start) {
+            return None;
+        }
+
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let end = location.end.clamp(start, line_end);
+        let text = source[line_start..line_end].trim_end().replace('\t', " ");
+
+        let padding = source[line_start..start].chars().count();
+        let length = source
+            .get(start..end)
+            .map_or(0, |s| s.chars().count())
+            .max(1);
+        let mut underline = " ".repeat(padding) + &marker.to_string().repeat(length);
+        if !label.is_empty() {
+            underline += &format!(" {}", label);
+        }
+
+        let line = source[..start].matches('\n').count();
+        Some((line, text, underline))
+    }
+}
+
+impl Error for Diagnostic {}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.render(&|filename| fs::read_to_string(filename).ok())
+        )
+    }
+}
+
+// everything made by `def!` is boxed up as a `Diagnostic`, so this only has to
+// wrap errors from outside (io, utf8...)
+impl From<Box<dyn Error>> for Diagnostic {
+    fn from(error: Box<dyn Error>) -> Diagnostic {
+        match error.downcast::<Diagnostic>() {
+            Ok(diagnostic) => *diagnostic,
+            Err(other) => Diagnostic::new(&other.to_string(), None, "init"),
+        }
     }
 }
 
@@ -72,9 +282,9 @@ macro_rules! def {
             stage_name: String,
         }
         impl $name {
-            #[allow(dead_code)]
+            #[allow(dead_code, clippy::new_ret_no_self)]
             pub fn new<T>(location: Option<Location>, stage_name: &str) -> Result<T> {
-                Err(($name {
+                Err(Diagnostic::from($name {
                     location,
                     stage_name: stage_name.to_string(),
                 })
@@ -89,6 +299,25 @@ macro_rules! def {
                 }
             }
 
+            #[allow(dead_code)]
+            pub fn diagnostic(location: Location, stage_name: &str) -> Diagnostic {
+                $name {
+                    location: Some(location),
+                    stage_name: stage_name.to_string(),
+                }
+                .into()
+            }
+
+            // for errors that don't point anywhere but still want a note
+            #[allow(dead_code)]
+            pub fn unlocated(stage_name: &str) -> Diagnostic {
+                $name {
+                    location: None,
+                    stage_name: stage_name.to_string(),
+                }
+                .into()
+            }
+
             #[allow(dead_code)]
             pub fn while_parsing<T>(location: Location) -> Result<T> {
                 $name::new(Some(location), "parse")
@@ -98,22 +327,29 @@ macro_rules! def {
             pub fn while_initializing<T>() -> Result<T> {
                 $name::new(None, "init")
             }
+
+            #[allow(dead_code)]
+            pub fn while_checking<T>(location: Location) -> Result<T> {
+                $name::new(Some(location), "type")
+            }
+
+            #[allow(dead_code)]
+            pub fn while_evaluating<T>(location: Location) -> Result<T> {
+                $name::new(Some(location), "eval")
+            }
+        }
+
+        impl From<$name> for Diagnostic {
+            fn from(error: $name) -> Diagnostic {
+                Diagnostic::new($error, error.location, &error.stage_name)
+            }
         }
 
         impl Error for $name {}
 
         impl fmt::Display for $name {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                let msg = if let Some(l) = self.location.clone() {
-                    l.get_message()
-                } else {
-                    "internal".to_string()
-                };
-                write!(
-                    f,
-                    "\n\nERROR: [{}]\n >>   {}, {}\n\n",
-                    self.stage_name, msg, $error
-                )
+                write!(f, "{}", Diagnostic::from(self.clone()))
             }
         }
     };
@@ -121,13 +357,25 @@ macro_rules! def {
 
 def!(DeclarationError, "expected function declaration!");
 def!(RangeError, "improperly formatted range!");
+def!(ComprehensionError, "improperly formatted comprehension!");
+def!(BindingError, "improperly formatted local binding!");
+def!(LambdaError, "improperly formatted lambda!");
+def!(ExternError, "improperly formatted extern declaration!");
+def!(ImportError, "couldn't import file!");
+def!(ImportCycleError, "circular import!");
+def!(UnknownTypeError, "unknown type!");
+def!(ReturnTypeError, "return type doesn't match the declaration!");
 def!(EofError, "unexpected end of file!");
 def!(IdentifierError, "expected identifier!");
 def!(GroupingError, "improperly formatted grouping!");
 def!(UnknownTokenError, "unknown token!");
+def!(EncodingError, "invalid utf-8!");
+def!(UnterminatedStringError, "unterminated string literal!");
+def!(EscapeError, "invalid escape sequence!");
 def!(BadCommaError, "comma placed badly!");
 def!(BadArgumentError, "bad argument...");
-def!(MissingOutputFileError, "no output file supplied!");
+def!(MissingInputFileError, "no input file supplied!");
+def!(EntryError, "bad entry point!");
 def!(
     UnbalancedBinaryExpressionError,
     "unbalanced binary expression!"
@@ -136,3 +384,75 @@ def!(
     UnbalancedChainExpressionError,
     "chain does not have consistent types!"
 );
+def!(UnknownFunctionError, "unknown function!");
+def!(DuplicateFunctionError, "function defined more than once!");
+def!(UnknownVariableError, "unknown variable!");
+def!(UnknownOperatorError, "unknown operator!");
+def!(ArgumentCountError, "mismatched argument count!");
+def!(ArgumentTypeError, "mismatched argument type!");
+def!(NotFunctionError, "only functions can be called!");
+def!(
+    ArrayElementError,
+    "array elements don't all have the same type!"
+);
+def!(IndexTypeError, "arrays can only be indexed with an Int!");
+def!(NotArrayError, "expected an array!");
+def!(IndexOutOfBoundsError, "index out of bounds!");
+def!(RangeBoundError, "range bounds have to be Ints!");
+def!(SumTypeError, "only Ints and Floats can be summed!");
+def!(ConditionTypeError, "conditions have to be Bools!");
+def!(DivisionByZeroError, "division by zero!");
+def!(OverflowError, "integer overflow!");
+def!(
+    NegativePowerError,
+    "Ints can't be raised to a negative power!"
+);
+def!(DanglingWhenError, "dangling 'when' not allowed!");
+def!(UnmatchedChainError, "no link in the chain matched!");
+def!(RangeViolationError, "argument out of range!");
+def!(
+    ExternCallError,
+    "extern functions can only be called from compiled code!"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "let f(a, b) = a + b\nlet entry() = f(1)\n";
+
+    fn at(line: usize, column: usize, start: usize, end: usize) -> Location {
+        let mut location = Location::new(line, column, "test.txt".to_string());
+        location.start = start;
+        location.with_end(end)
+    }
+
+    #[test]
+    fn render() {
+        let rendered = ArgumentCountError::diagnostic(at(1, 14, 34, 38), "eval")
+            .with_label(at(0, 4, 4, 11), "function declared here")
+            .with_note("'f' takes 2 argument(s), got 1")
+            .render(&|_| Some(SOURCE.to_string()));
+        let expected = [
+            "ERROR: [eval] mismatched argument count!",
+            " >>   @test.txt:2:15",
+            "   |",
+            " 2 | let entry() = f(1)",
+            "   |               ^^^^",
+            "   |",
+            " 1 | let f(a, b) = a + b",
+            "   |     ------- function declared here",
+            "   = note: 'f' takes 2 argument(s), got 1",
+        ];
+        assert_eq!(rendered.trim(), expected.join("\n"));
+    }
+
+    #[test]
+    fn render_without_source() {
+        let rendered = EofError::diagnostic(at(0, 0, 0, 1), "parse").render(&|_| None);
+        assert_eq!(
+            rendered.trim(),
+            "ERROR: [parse] unexpected end of file!\n >>   @test.txt:1:1"
+        );
+    }
+}
@@ -0,0 +1,617 @@
+use crate::{ast::*, errors::*};
+use std::{collections::HashMap, fmt, rc::Rc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(isize),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    Array(Vec<Value>),
+    Function(Function),
+}
+
+// what calling a function value runs: a definition with its first few arguments
+// already filled in (which is all a `Closure` is), or a lambda with the scope it
+// was made in
+#[derive(Debug, Clone)]
+pub enum Function {
+    Definition(String, Vec<Value>),
+    Lambda(Rc<(Vec<Param>, LocatedExpr, Scope)>),
+}
+
+// lambdas are only ever equal to themselves
+impl PartialEq for Function {
+    fn eq(&self, other: &Function) -> bool {
+        match (self, other) {
+            (Function::Definition(a, a_args), Function::Definition(b, b_args)) => {
+                a == b && a_args == b_args
+            }
+            (Function::Lambda(a), Function::Lambda(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Function(Function::Definition(name, _)) => write!(f, "<function {}>", name),
+            Value::Function(Function::Lambda(_)) => write!(f, "<lambda>"),
+        }
+    }
+}
+
+type Scope = HashMap<String, Value>;
+
+// walks the tree from `Parser::run` directly, so scripts can be run (and the
+// compiled output diffed against something) without the llvm backend
+pub struct Evaluator {
+    // parameters, body and where the function was declared
+    functions: HashMap<String, (Vec<Param>, LocatedExpr, Location)>,
+    // externs only exist once the compiled output is linked, so they're just remembered for errors
+    externs: HashMap<String, Location>,
+}
+
+impl Evaluator {
+    pub fn new(tree: &[LocatedExpr]) -> Evaluator {
+        let mut functions = HashMap::new();
+        let mut externs = HashMap::new();
+        for expr in tree {
+            match expr.get_expr() {
+                Expr::FunctionDefinition(name, args, body, _, _) => {
+                    functions.insert(name, (args, *body, expr.get_location()));
+                }
+                Expr::ExternDeclaration(name, _, _) => {
+                    externs.insert(name, expr.get_location());
+                }
+                _ => {}
+            }
+        }
+        Evaluator { functions, externs }
+    }
+
+    pub fn entry(&self) -> Result<Value> {
+        self.call("entry", vec![], Location::internal().unwrap())
+    }
+
+    pub fn call(&self, name: &str, args: Vec<Value>, location: Location) -> Result<Value> {
+        let Some((params, body, declared)) = self.functions.get(name) else {
+            if let Some(declared) = self.externs.get(name) {
+                return Err(ExternCallError::diagnostic(location, "eval")
+                    .with_label(declared.clone(), "declared as extern here")
+                    .into());
+            }
+            return UnknownFunctionError::while_evaluating(location);
+        };
+        if params.len() != args.len() {
+            let note = format!(
+                "'{}' takes {} argument(s), got {}",
+                name,
+                params.len(),
+                args.len()
+            );
+            return Err(ArgumentCountError::diagnostic(location, "eval")
+                .with_label(declared.clone(), "function declared here")
+                .with_note(&note)
+                .into());
+        }
+
+        for (param, arg) in params.iter().zip(&args) {
+            if !Evaluator::in_range(param, arg) {
+                let note = format!(
+                    "'{}' must be in {}, got {}",
+                    param.name,
+                    param.ty.as_ref().unwrap(),
+                    arg
+                );
+                return Err(RangeViolationError::diagnostic(location, "eval")
+                    .with_label(declared.clone(), "function declared here")
+                    .with_note(&note)
+                    .into());
+            }
+        }
+
+        let scope: Scope = params.iter().map(|p| p.name.clone()).zip(args).collect();
+        self.eval(body, &scope)
+    }
+
+    // only range annotations are checked here, plain types are the parser's problem
+    fn in_range(param: &Param, arg: &Value) -> bool {
+        let Some(ParamType::Range(_, low, high)) = &param.ty else {
+            return true;
+        };
+        let value = match arg {
+            Value::Int(i) => *i as f64,
+            Value::Float(f) => *f,
+            Value::String(_) | Value::Bool(_) | Value::Array(_) | Value::Function(_) => {
+                return false
+            }
+        };
+        low.is_none_or(|l| value >= l) && high.is_none_or(|h| value <= h)
+    }
+
+    fn eval(&self, expr: &LocatedExpr, scope: &Scope) -> Result<Value> {
+        let location = expr.get_location();
+        match expr.get_expr() {
+            Expr::NumberLiteral(true, _, float) => Ok(Value::Float(float)),
+            Expr::NumberLiteral(false, int, _) => Ok(Value::Int(int)),
+            Expr::StringLiteral(s) => Ok(Value::String(s)),
+            Expr::BoolLiteral(b) => Ok(Value::Bool(b)),
+            Expr::VariableRef(name) => match scope.get(&name) {
+                Some(value) => Ok(value.clone()),
+                None if self.functions.contains_key(&name) || self.externs.contains_key(&name) => {
+                    Ok(Value::Function(Function::Definition(name, vec![])))
+                }
+                None => UnknownVariableError::while_evaluating(location),
+            },
+            Expr::FunctionCall(name, args) => {
+                let mut values = vec![];
+                for arg in &args {
+                    values.push(self.eval(arg, scope)?);
+                }
+                match scope.get(&name) {
+                    Some(function) => self.apply(function.clone(), values, location),
+                    None => self.call(&name, values, location),
+                }
+            }
+            Expr::Apply(function, args, _) => {
+                let function = self.eval(&function, scope)?;
+                let mut values = vec![];
+                for arg in &args {
+                    values.push(self.eval(arg, scope)?);
+                }
+                self.apply(function, values, location)
+            }
+            Expr::Lambda(params, body, _, _) => Ok(Value::Function(Function::Lambda(Rc::new((
+                params,
+                *body,
+                scope.clone(),
+            ))))),
+            Expr::Closure(name, captures) => {
+                let mut values = vec![];
+                for capture in captures {
+                    match scope.get(&capture) {
+                        Some(value) => values.push(value.clone()),
+                        None => return UnknownVariableError::while_evaluating(location),
+                    }
+                }
+                Ok(Value::Function(Function::Definition(name, values)))
+            }
+            Expr::ChainExpression(links) => {
+                for link in &links {
+                    match link.get_expr() {
+                        Expr::WhenExpression(predicate, result) => {
+                            if self.truthy(&self.eval(&predicate, scope)?, &predicate)? {
+                                return self.eval(&result, scope);
+                            }
+                        }
+                        _ => return self.eval(link, scope),
+                    }
+                }
+                UnmatchedChainError::while_evaluating(location)
+            }
+            Expr::BinaryOperation(op, left, right) => {
+                self.binary_operation(op, &left, &right, scope)
+            }
+            Expr::UnaryOperation(op, operand) => {
+                let value = self.eval(&operand, scope)?;
+                match (op, value) {
+                    (UnaryOperator::Negate, Value::Int(i)) => match i.checked_neg() {
+                        Some(negated) => Ok(Value::Int(negated)),
+                        None => OverflowError::while_evaluating(location),
+                    },
+                    (UnaryOperator::Negate, Value::Float(f)) => Ok(Value::Float(-f)),
+                    (UnaryOperator::Plus, value @ (Value::Int(_) | Value::Float(_))) => Ok(value),
+                    (UnaryOperator::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                    _ => UnknownOperatorError::while_evaluating(location),
+                }
+            }
+            Expr::Let(name, value, body) => {
+                let mut inner = scope.clone();
+                inner.insert(name, self.eval(&value, scope)?);
+                self.eval(&body, &inner)
+            }
+            Expr::WhenExpression(_, _) => DanglingWhenError::while_evaluating(location),
+            Expr::FunctionDefinition(..) | Expr::ExternDeclaration(..) => {
+                DeclarationError::while_evaluating(location)
+            }
+            Expr::ArrayLiteral(elements, _) => {
+                let mut values = vec![];
+                for element in &elements {
+                    values.push(self.eval(element, scope)?);
+                }
+                Ok(Value::Array(values))
+            }
+            Expr::Index(array, index) => {
+                let elements = self.eval_array(&array, scope)?;
+                let Value::Int(i) = self.eval(&index, scope)? else {
+                    return IndexTypeError::while_evaluating(index.get_location());
+                };
+                match usize::try_from(i).ok().and_then(|i| elements.get(i)) {
+                    Some(element) => Ok(element.clone()),
+                    None => {
+                        let note = format!("the index is {}, the length is {}", i, elements.len());
+                        Err(IndexOutOfBoundsError::diagnostic(location, "eval")
+                            .with_note(&note)
+                            .into())
+                    }
+                }
+            }
+            Expr::Length(array) => Ok(Value::Int(self.eval_array(&array, scope)?.len() as isize)),
+            Expr::Range(start, end) => {
+                let (Value::Int(start), Value::Int(end)) =
+                    (self.eval(&start, scope)?, self.eval(&end, scope)?)
+                else {
+                    return RangeBoundError::while_evaluating(location);
+                };
+                Ok(Value::Array((start..end).map(Value::Int).collect()))
+            }
+            Expr::For(kind, variable, iterable, body, body_type) => {
+                let mut inner = scope.clone();
+                let mut results = vec![];
+                for element in self.eval_array(&iterable, scope)? {
+                    inner.insert(variable.clone(), element);
+                    results.push(self.eval(&body, &inner)?);
+                }
+                match kind {
+                    ForKind::Collect => Ok(Value::Array(results)),
+                    ForKind::Sum => {
+                        let zero = match body_type {
+                            Some(ReturnType::Float) => Value::Float(0.0),
+                            _ => Value::Int(0),
+                        };
+                        let mut results = results.into_iter();
+                        let first = results.next().unwrap_or(zero);
+                        results.try_fold(first, |total, value| match (total, value) {
+                            (Value::Int(l), Value::Int(r)) => match l.checked_add(r) {
+                                Some(total) => Ok(Value::Int(total)),
+                                None => OverflowError::while_evaluating(location.clone()),
+                            },
+                            (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l + r)),
+                            _ => SumTypeError::while_evaluating(location.clone()),
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply(&self, function: Value, args: Vec<Value>, location: Location) -> Result<Value> {
+        match function {
+            Value::Function(Function::Definition(name, mut bound)) => {
+                bound.extend(args);
+                self.call(&name, bound, location)
+            }
+            Value::Function(Function::Lambda(lambda)) => {
+                let (params, body, captured) = lambda.as_ref();
+                if params.len() != args.len() {
+                    let note = format!(
+                        "the lambda takes {} argument(s), got {}",
+                        params.len(),
+                        args.len()
+                    );
+                    return Err(ArgumentCountError::diagnostic(location, "eval")
+                        .with_label(body.get_location(), "lambda body here")
+                        .with_note(&note)
+                        .into());
+                }
+                if let Some((param, arg)) = params
+                    .iter()
+                    .zip(&args)
+                    .find(|(param, arg)| !Evaluator::in_range(param, arg))
+                {
+                    let note = format!(
+                        "'{}' must be in {}, got {}",
+                        param.name,
+                        param.ty.as_ref().unwrap(),
+                        arg
+                    );
+                    return Err(RangeViolationError::diagnostic(location, "eval")
+                        .with_note(&note)
+                        .into());
+                }
+                let mut scope = captured.clone();
+                scope.extend(params.iter().map(|p| p.name.clone()).zip(args));
+                self.eval(body, &scope)
+            }
+            other => {
+                let note = format!("this is {}", other);
+                Err(NotFunctionError::diagnostic(location, "eval")
+                    .with_note(&note)
+                    .into())
+            }
+        }
+    }
+
+    fn eval_array(&self, array: &LocatedExpr, scope: &Scope) -> Result<Vec<Value>> {
+        match self.eval(array, scope)? {
+            Value::Array(elements) => Ok(elements),
+            _ => NotArrayError::while_evaluating(array.get_location()),
+        }
+    }
+
+    fn truthy(&self, value: &Value, expr: &LocatedExpr) -> Result<bool> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            _ => ConditionTypeError::while_evaluating(expr.get_location()),
+        }
+    }
+
+    fn binary_operation(
+        &self,
+        op: Operator,
+        left: &LocatedExpr,
+        right: &LocatedExpr,
+        scope: &Scope,
+    ) -> Result<Value> {
+        let location = right.get_location();
+        let lhs = self.eval(left, scope)?;
+
+        // && and || only evaluate the right side when they have to
+        if matches!(op, Operator::And | Operator::Or) {
+            let left_true = self.truthy(&lhs, left)?;
+            // a true left side settles `||` and a false one settles `&&`
+            let result = if left_true == (op == Operator::Or) {
+                left_true
+            } else {
+                self.truthy(&self.eval(right, scope)?, right)?
+            };
+            return Ok(Value::Bool(result));
+        }
+
+        let rhs = self.eval(right, scope)?;
+        match (lhs, rhs) {
+            (Value::Int(l), Value::Int(r)) => {
+                let value = match op {
+                    Operator::Power if r < 0 => {
+                        return NegativePowerError::while_evaluating(location)
+                    }
+                    Operator::Power => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+                    Operator::Multiply => l.checked_mul(r),
+                    Operator::Divide | Operator::Remainder if r == 0 => {
+                        return DivisionByZeroError::while_evaluating(location)
+                    }
+                    Operator::Divide => l.checked_div(r),
+                    Operator::Remainder => l.checked_rem(r),
+                    Operator::Add => l.checked_add(r),
+                    Operator::Subtract => l.checked_sub(r),
+                    _ => return Evaluator::compare(op, l.cmp(&r), location).map(Value::Bool),
+                };
+                match value {
+                    Some(v) => Ok(Value::Int(v)),
+                    None => OverflowError::while_evaluating(location),
+                }
+            }
+            (Value::Float(l), Value::Float(r)) => match op {
+                Operator::Power => Ok(Value::Float(l.powf(r))),
+                Operator::Multiply => Ok(Value::Float(l * r)),
+                Operator::Divide => Ok(Value::Float(l / r)),
+                Operator::Remainder => Ok(Value::Float(l % r)),
+                Operator::Add => Ok(Value::Float(l + r)),
+                Operator::Subtract => Ok(Value::Float(l - r)),
+                // unordered comparisons (nan) are true, like the `FCmpU*` codegen uses
+                _ => match l.partial_cmp(&r) {
+                    Some(ordering) => Evaluator::compare(op, ordering, location),
+                    None => Ok(true),
+                }
+                .map(Value::Bool),
+            },
+            (Value::String(l), Value::String(r)) => match op {
+                Operator::Add => Ok(Value::String(l + &r)),
+                _ => Evaluator::compare(op, l.cmp(&r), location).map(Value::Bool),
+            },
+            (Value::Bool(l), Value::Bool(r)) => {
+                Evaluator::compare(op, l.cmp(&r), location).map(Value::Bool)
+            }
+            _ => UnbalancedBinaryExpressionError::while_evaluating(location),
+        }
+    }
+
+    fn compare(op: Operator, ordering: std::cmp::Ordering, location: Location) -> Result<bool> {
+        use std::cmp::Ordering::*;
+        let result = match op {
+            Operator::Less => ordering == Less,
+            Operator::Greater => ordering == Greater,
+            Operator::LessEqual => ordering != Greater,
+            Operator::GreaterEqual => ordering != Less,
+            Operator::Equal => ordering == Equal,
+            Operator::NotEqual => ordering != Equal,
+            _ => return UnknownOperatorError::while_evaluating(location),
+        };
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn run(file: &str) -> Result<Value> {
+        let tree = Parser::new(&format!("src/tests/{}.txt", file))?
+            .run()
+            .unwrap();
+        Evaluator::new(&tree).entry()
+    }
+
+    #[test]
+    fn fib() {
+        assert_eq!(run("fib").unwrap(), Value::Int(55));
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(run("arithmetic").unwrap(), Value::Float(14.5));
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(run("logic").unwrap(), Value::Int(622));
+
+        // a false left side is enough, whatever the right side would have been
+        let tree = Parser::new("src/tests/logic.txt").unwrap().run().unwrap();
+        let evaluator = Evaluator::new(&tree);
+        let location = Location::internal().unwrap();
+        let mixed = |x| evaluator.call("mixed", vec![Value::Float(x)], location.clone());
+        assert_eq!(mixed(0.25).unwrap(), Value::Bool(false));
+        assert_eq!(mixed(0.75).unwrap(), Value::Bool(true));
+        let both = |a, b| {
+            evaluator.call(
+                "both",
+                vec![Value::Bool(a), Value::Bool(b)],
+                location.clone(),
+            )
+        };
+        assert_eq!(both(false, true).unwrap(), Value::Bool(false));
+        assert_eq!(both(true, false).unwrap(), Value::Bool(false));
+        assert_eq!(both(true, true).unwrap(), Value::Bool(true));
+    }
+
+    #[test]
+    fn unary() {
+        assert_eq!(run("unary").unwrap(), Value::Int(-3));
+    }
+
+    #[test]
+    fn bools() {
+        assert_eq!(run("bools").unwrap(), Value::Int(10));
+        assert_eq!(Value::Bool(true).to_string(), "true");
+    }
+
+    #[test]
+    fn lambdas() {
+        assert_eq!(run("lambdas").unwrap(), Value::Int(39));
+
+        let tree = Parser::new("src/tests/lambdas.txt").unwrap().run().unwrap();
+        let evaluator = Evaluator::new(&tree);
+        let location = Location::internal().unwrap();
+        let adder = evaluator
+            .call("adder", vec![Value::Int(1)], location.clone())
+            .unwrap();
+        assert_eq!(adder.to_string(), "<lambda>");
+        // a name on its own is the function, which can be passed back in
+        let twice = evaluator.call("twice", vec![adder, Value::Int(5)], location.clone());
+        assert_eq!(twice.unwrap(), Value::Int(7));
+        let double = Value::Function(Function::Definition("double".to_string(), vec![]));
+        assert_eq!(double.to_string(), "<function double>");
+        let mapped = evaluator.call(
+            "map",
+            vec![double, Value::Array(vec![Value::Int(4)])],
+            location.clone(),
+        );
+        assert_eq!(mapped.unwrap(), Value::Array(vec![Value::Int(8)]));
+        assert!(evaluator
+            .call("twice", vec![Value::Int(1), Value::Int(5)], location)
+            .is_err());
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(run("annotations").unwrap(), Value::Int(43));
+    }
+
+    #[test]
+    fn range_violation() {
+        let tree = Parser::new("src/tests/annotations.txt")
+            .unwrap()
+            .run()
+            .unwrap();
+        let evaluator = Evaluator::new(&tree);
+        let location = Location::internal().unwrap();
+        let args = |x| vec![Value::Float(x), Value::Float(2.0)];
+        assert_eq!(
+            evaluator
+                .call("scale", args(0.5), location.clone())
+                .unwrap(),
+            Value::Float(1.0)
+        );
+        assert!(evaluator.call("scale", args(1.5), location).is_err());
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(run("arrays").unwrap(), Value::Int(30));
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(run("loops").unwrap(), Value::Int(27));
+    }
+
+    #[test]
+    fn bindings() {
+        assert_eq!(run("bindings").unwrap(), Value::Int(34));
+    }
+
+    #[test]
+    fn externs() {
+        let error = run("externs").unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Diagnostic>().unwrap().get_message(),
+            "extern functions can only be called from compiled code!"
+        );
+    }
+
+    #[test]
+    fn negative_power() {
+        let message = |source| {
+            let tree = Parser::from_source("<test>", source)
+                .unwrap()
+                .run()
+                .unwrap();
+            let error = Evaluator::new(&tree).entry().unwrap_err();
+            error.downcast_ref::<Diagnostic>().unwrap().get_message()
+        };
+        assert_eq!(
+            message("let entry() = 2 ^ -1"),
+            "Ints can't be raised to a negative power!"
+        );
+        assert_eq!(message("let entry() = 2 ^ 64"), "integer overflow!");
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let tree = Parser::new("src/tests/arrays.txt").unwrap().run().unwrap();
+        let evaluator = Evaluator::new(&tree);
+        let location = Location::internal().unwrap();
+        let row = Value::Array(vec![Value::Int(1)]);
+        // grid[0][1] is past the end of a one element row
+        let grid = Value::Array(vec![row.clone(), row.clone()]);
+        assert!(evaluator
+            .call("corners", vec![grid], location.clone())
+            .is_err());
+        // `sum` checks the length itself before indexing
+        assert_eq!(
+            evaluator
+                .call("sum", vec![row, Value::Int(5)], location)
+                .unwrap(),
+            Value::Int(0)
+        );
+    }
+
+    #[test]
+    fn argument_count() {
+        let tree = Parser::new("src/tests/fib.txt").unwrap().run().unwrap();
+        let evaluator = Evaluator::new(&tree);
+        let location = Location::internal().unwrap();
+        assert!(evaluator.call("fib", vec![], location.clone()).is_err());
+        assert!(evaluator
+            .call("fob", vec![Value::Int(1)], location.clone())
+            .is_err());
+        assert_eq!(
+            evaluator
+                .call("fib", vec![Value::Int(1)], location)
+                .unwrap(),
+            Value::Int(1)
+        );
+    }
+}
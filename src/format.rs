@@ -0,0 +1,493 @@
+use crate::{
+    ast::{Expr, ForKind, LocatedExpr, Operator, Param},
+    errors::*,
+    parser::Parser,
+};
+use std::{collections::VecDeque, fs};
+
+const INDENT: &str = "    ";
+
+struct Comment {
+    text: String,
+    line: usize,
+    // written after some code on the same line, rather than on a line of its own
+    trailing: bool,
+    // followed by a blank line, like a comment at the top of a file
+    spaced: bool,
+}
+
+// `fx fmt`, which reprints a file from its tree: bodies that need more than one
+// line (`when` chains, local bindings) are indented by four spaces, there's one
+// blank line between definitions, and comments are put back next to whatever
+// line they were next to
+pub struct Formatter {
+    lines: Vec<String>,
+    comments: VecDeque<Comment>,
+}
+
+pub fn format_file(path: &str) -> std::result::Result<String, Vec<Diagnostic>> {
+    let source = fs::read_to_string(path).map_err(|e| vec![Diagnostic::from(Box::from(e))])?;
+    format_source(path, &source)
+}
+
+pub fn format_source(name: &str, source: &str) -> std::result::Result<String, Vec<Diagnostic>> {
+    let mut parser = Parser::from_source(name, source).map_err(|e| vec![Diagnostic::from(e)])?;
+    let tree = parser.run()?;
+
+    // imports aren't in the tree, so they're put back in wherever they were
+    let mut items: Vec<(Location, Option<LocatedExpr>, String)> = parser
+        .imports()
+        .iter()
+        .map(|(import, location)| (location.clone(), None, import.clone()))
+        .chain(
+            tree.into_iter()
+                .map(|def| (def.get_location(), Some(def), String::new())),
+        )
+        .collect();
+    items.sort_by_key(|(location, ..)| (location.get_line(), location.get_column()));
+
+    let mut formatter = Formatter::new(source, parser.comments());
+    let mut last_was_import = false;
+    for (location, def, import) in items {
+        match def {
+            Some(def) => {
+                formatter.blank();
+                formatter.definition(&def);
+                last_was_import = false;
+            }
+            None => {
+                // imports stay together, with no blank lines between them
+                if !last_was_import {
+                    formatter.blank();
+                }
+                let text = format!("import {}", Formatter::quoted(&import));
+                formatter.line(0, text, location.get_line());
+                last_was_import = true;
+            }
+        }
+    }
+    Ok(formatter.finish())
+}
+
+impl Formatter {
+    fn new(source: &str, comments: &[(String, Location)]) -> Formatter {
+        let lines: Vec<&str> = source.lines().collect();
+        let comments = comments
+            .iter()
+            .map(|(text, location)| {
+                let line = location.get_line();
+                let before = source.get(..location.get_start()).unwrap_or("");
+                let before = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+                Comment {
+                    text: text.clone(),
+                    line,
+                    trailing: !before.trim().is_empty(),
+                    spaced: lines.get(line + 1).is_some_and(|l| l.trim().is_empty()),
+                }
+            })
+            .collect();
+        Formatter {
+            lines: vec![],
+            comments,
+        }
+    }
+
+    // separates two definitions, but never doubles up or starts the file
+    fn blank(&mut self) {
+        if self.lines.last().is_some_and(|l| !l.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    // `last` is the last source line the text came from: any comments on their
+    // own line up to there go above it, and any trailing ones go on the end
+    fn line(&mut self, indent: usize, text: String, last: usize) {
+        let mut trailing = vec![];
+        while self.comments.front().is_some_and(|c| c.line <= last) {
+            let comment = self.comments.pop_front().unwrap();
+            if comment.trailing {
+                trailing.push(comment.text);
+            } else {
+                self.comment(indent, comment);
+            }
+        }
+
+        let mut line = INDENT.repeat(indent) + &text;
+        for comment in trailing {
+            line = line + " " + &comment;
+        }
+        self.lines.push(line);
+    }
+
+    fn comment(&mut self, indent: usize, comment: Comment) {
+        self.lines.push(INDENT.repeat(indent) + &comment.text);
+        if comment.spaced && indent == 0 {
+            self.blank();
+        }
+    }
+
+    fn finish(mut self) -> String {
+        if !self.comments.is_empty() {
+            self.blank();
+        }
+        while let Some(comment) = self.comments.pop_front() {
+            self.comment(0, comment);
+        }
+        while self.lines.last().is_some_and(|l| l.is_empty()) {
+            self.lines.pop();
+        }
+        self.lines.join("\n") + "\n"
+    }
+
+    fn definition(&mut self, def: &LocatedExpr) {
+        let line = def.get_location().get_line();
+        match def.get_expr() {
+            Expr::FunctionDefinition(name, params, body, returns, _) => {
+                let returns = returns.map_or(String::new(), |r| format!(": {}", r));
+                let header = format!("let {}({}){} =", name, Formatter::params(&params), returns);
+                if Formatter::needs_block(&body) {
+                    self.line(0, header, line);
+                    self.block(1, &body);
+                } else {
+                    let text = format!("{} {}", header, Formatter::inline(&body));
+                    self.line(0, text, Formatter::last_line(def));
+                }
+            }
+            Expr::ExternDeclaration(name, params, returns) => {
+                let text = format!(
+                    "extern {}({}): {}",
+                    name,
+                    Formatter::params(&params),
+                    returns
+                );
+                self.line(0, text, line);
+            }
+            _ => self.block(0, def),
+        }
+    }
+
+    fn needs_block(body: &LocatedExpr) -> bool {
+        matches!(body.get_expr(), Expr::ChainExpression(_) | Expr::Let(..))
+    }
+
+    // one `when` link per line, and local bindings on their own line without the `in`
+    fn block(&mut self, indent: usize, body: &LocatedExpr) {
+        match body.get_expr() {
+            Expr::Let(name, value, rest) => {
+                let text = format!("let {} = {}", name, Formatter::value(&value));
+                let last = body
+                    .get_location()
+                    .get_line()
+                    .max(Formatter::last_line(&value));
+                self.line(indent, text, last);
+                self.block(indent, &rest);
+            }
+            Expr::ChainExpression(links) => {
+                for (i, link) in links.iter().enumerate() {
+                    let comma = if i + 1 < links.len() { "," } else { "" };
+                    let text = Formatter::inline(link) + comma;
+                    self.line(indent, text, Formatter::last_line(link));
+                }
+            }
+            _ => self.line(indent, Formatter::inline(body), Formatter::last_line(body)),
+        }
+    }
+
+    fn params(params: &[Param]) -> String {
+        let params: Vec<String> = params
+            .iter()
+            .map(|param| match &param.ty {
+                Some(ty) => format!("{}: {}", param.name, ty),
+                None => param.name.clone(),
+            })
+            .collect();
+        params.join(", ")
+    }
+
+    // the furthest line anything in `expr` came from
+    fn last_line(expr: &LocatedExpr) -> usize {
+        let line = expr.get_location().get_line();
+        let children = match expr.get_expr() {
+            Expr::FunctionDefinition(_, _, body, ..)
+            | Expr::Lambda(_, body, ..)
+            | Expr::Length(body)
+            | Expr::UnaryOperation(_, body) => vec![*body],
+            Expr::Apply(function, mut args, _) => {
+                args.insert(0, *function);
+                args
+            }
+            Expr::ChainExpression(all)
+            | Expr::FunctionCall(_, all)
+            | Expr::ArrayLiteral(all, _) => all,
+            Expr::BinaryOperation(_, first, second)
+            | Expr::WhenExpression(first, second)
+            | Expr::Index(first, second)
+            | Expr::For(_, _, first, second, _)
+            | Expr::Range(first, second)
+            | Expr::Let(_, first, second) => vec![*first, *second],
+            _ => vec![],
+        };
+        children
+            .iter()
+            .map(Formatter::last_line)
+            .fold(line, usize::max)
+    }
+
+    // a local binding's value can't be another binding or a chain without parentheses
+    fn value(expr: &LocatedExpr) -> String {
+        match expr.get_expr() {
+            Expr::WhenExpression(..) | Expr::Lambda(..) => Formatter::inline(expr),
+            _ => Formatter::operand(expr),
+        }
+    }
+
+    // anything that fills the rest of a line or a pair of parentheses
+    fn inline(expr: &LocatedExpr) -> String {
+        match expr.get_expr() {
+            Expr::WhenExpression(predicate, result) => format!(
+                "{} when {}",
+                Formatter::operand(&result),
+                Formatter::operand(&predicate)
+            ),
+            Expr::ChainExpression(links) => {
+                let links: Vec<String> = links.iter().map(Formatter::inline).collect();
+                links.join(", ")
+            }
+            Expr::Let(name, value, body) => format!(
+                "let {} = {} in {}",
+                name,
+                Formatter::operand(&value),
+                Formatter::inline(&body)
+            ),
+            _ => Formatter::expr(expr),
+        }
+    }
+
+    // anything that has to stay one piece, like one side of an operator
+    fn operand(expr: &LocatedExpr) -> String {
+        match expr.get_expr() {
+            Expr::WhenExpression(..)
+            | Expr::ChainExpression(_)
+            | Expr::Let(..)
+            | Expr::Lambda(..) => {
+                format!("({})", Formatter::inline(expr))
+            }
+            _ => Formatter::expr(expr),
+        }
+    }
+
+    // the lexer's escapes the other way around, raw strings come out as normal ones
+    fn quoted(value: &str) -> String {
+        let mut out = "\"".to_string();
+        for c in value.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                '\r' => out.push_str("\\r"),
+                c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        out + "\""
+    }
+
+    // the commas stop a lambda's body, so those don't need brackets here
+    fn list(exprs: &[LocatedExpr]) -> String {
+        let exprs: Vec<String> = exprs
+            .iter()
+            .map(|expr| match expr.get_expr() {
+                Expr::Lambda(..) => Formatter::expr(expr),
+                _ => Formatter::operand(expr),
+            })
+            .collect();
+        exprs.join(", ")
+    }
+
+    // `\x -> x * 2`, with brackets around the parameters unless it's one without a type
+    fn lambda(params: &[Param], body: &LocatedExpr) -> String {
+        let params = match params {
+            [Param { name, ty: None }] => name.clone(),
+            _ => format!("({})", Formatter::params(params)),
+        };
+        let body = match body.get_expr() {
+            Expr::Lambda(..) => Formatter::expr(body),
+            _ => Formatter::operand(body),
+        };
+        format!("\\{} -> {}", params, body)
+    }
+
+    fn expr(expr: &LocatedExpr) -> String {
+        match expr.get_expr() {
+            Expr::NumberLiteral(false, int, _) => int.to_string(),
+            Expr::NumberLiteral(true, _, float) => {
+                // `2.0` has to keep its point or it'd come back as an int
+                let text = float.to_string();
+                if text.contains('.') {
+                    text
+                } else {
+                    text + ".0"
+                }
+            }
+            Expr::StringLiteral(s) => Formatter::quoted(&s),
+            Expr::BoolLiteral(value) => value.to_string(),
+            Expr::VariableRef(name) => name,
+            Expr::FunctionCall(name, args) => format!("{}({})", name, Formatter::list(&args)),
+            Expr::Lambda(params, body, ..) => Formatter::lambda(&params, &body),
+            Expr::Apply(function, args, _) => {
+                let function = match function.get_expr() {
+                    Expr::VariableRef(_)
+                    | Expr::FunctionCall(..)
+                    | Expr::Apply(..)
+                    | Expr::Index(..) => Formatter::expr(&function),
+                    _ => format!("({})", Formatter::inline(&function)),
+                };
+                format!("{}({})", function, Formatter::list(&args))
+            }
+            Expr::Length(array) => format!("len({})", Formatter::operand(&array)),
+            Expr::ArrayLiteral(elements, _) => format!("[{}]", Formatter::list(&elements)),
+            Expr::Index(array, index) => {
+                // only a name, a call or something in brackets can be indexed straight away
+                let array = match array.get_expr() {
+                    Expr::VariableRef(_)
+                    | Expr::FunctionCall(..)
+                    | Expr::Length(_)
+                    | Expr::Index(..)
+                    | Expr::ArrayLiteral(..)
+                    | Expr::For(..) => Formatter::expr(&array),
+                    _ => format!("({})", Formatter::inline(&array)),
+                };
+                format!("{}[{}]", array, Formatter::inline(&index))
+            }
+            Expr::For(kind, variable, iterable, body, _) => {
+                let inner = format!(
+                    "{} for {} in {}",
+                    Formatter::operand(&body),
+                    variable,
+                    Formatter::operand(&iterable)
+                );
+                match kind {
+                    ForKind::Collect => format!("[{}]", inner),
+                    ForKind::Sum => format!("sum({})", inner),
+                }
+            }
+            Expr::Range(start, end) => format!(
+                "{}..{}",
+                Formatter::operand(&start),
+                Formatter::operand(&end)
+            ),
+            Expr::BinaryOperation(op, left, right) => Formatter::binary(op, &left, &right),
+            // `^` is the only operator that binds tighter, so `-a ^ 2` is `-(a ^ 2)`
+            Expr::UnaryOperation(op, operand) => match operand.get_expr() {
+                Expr::BinaryOperation(Operator::Power, ..) => {
+                    format!("{}{}", op.symbol(), Formatter::expr(&operand))
+                }
+                Expr::BinaryOperation(..) => {
+                    format!("{}({})", op.symbol(), Formatter::expr(&operand))
+                }
+                _ => format!("{}{}", op.symbol(), Formatter::operand(&operand)),
+            },
+            _ => Formatter::operand(expr),
+        }
+    }
+
+    // parentheses go around a side that binds looser than the operator, and around
+    // one that binds the same on the side the operator doesn't group towards
+    fn binary(op: Operator, left: &LocatedExpr, right: &LocatedExpr) -> String {
+        let bare = |side: &LocatedExpr, associative: bool| match side.get_expr() {
+            Expr::BinaryOperation(inner, ..) => {
+                inner.precedence() > op.precedence()
+                    || inner.precedence() == op.precedence() && associative
+            }
+            _ => true,
+        };
+        // and `(-a) ^ 2` needs them since `-a ^ 2` would negate the whole thing
+        let prefixed = match left.get_expr() {
+            Expr::UnaryOperation(..) => true,
+            Expr::NumberLiteral(false, int, _) => int < 0,
+            Expr::NumberLiteral(true, _, float) => float.is_sign_negative(),
+            _ => false,
+        };
+        let left_bare = bare(left, !op.right_associative()) && !(op == Operator::Power && prefixed);
+        let side = |side: &LocatedExpr, bare: bool| match bare {
+            true => Formatter::operand(side),
+            false => format!("({})", Formatter::expr(side)),
+        };
+        format!(
+            "{} {} {}",
+            side(left, left_bare),
+            op.symbol(),
+            side(right, bare(right, op.right_associative()))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::{render, DumpFormat};
+
+    fn format(file: &str) -> String {
+        format_file(&format!("src/tests/{}.txt", file)).unwrap()
+    }
+
+    // the tree without any locations, since those move around when formatting
+    fn shape(source: &str) -> Vec<String> {
+        let tree = Parser::from_source("shape", source).unwrap().run().unwrap();
+        render(&tree, DumpFormat::Text)
+            .lines()
+            .map(|line| line[..line.rfind(" @").unwrap()].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn layout() {
+        let expected = fs::read_to_string("src/tests/formatted.txt").unwrap();
+        assert_eq!(format("unformatted"), expected);
+        assert_eq!(format("formatted"), expected);
+    }
+
+    #[test]
+    fn strings() {
+        let source = "import r\"lib\\x.txt\"\n\nlet s() = r#\"say \"hi\"\"# + \"\\u{e9}\\t\"\n";
+        let formatted = format_source("strings", source).unwrap();
+        assert_eq!(
+            formatted,
+            "import \"lib\\\\x.txt\"\n\nlet s() = \"say \\\"hi\\\"\" + \"é\\t\"\n"
+        );
+        assert_eq!(shape(source), shape(&formatted));
+    }
+
+    #[test]
+    fn round_trips() {
+        let fixtures = [
+            "unformatted",
+            "fib",
+            "arithmetic",
+            "annotations",
+            "arrays",
+            "bindings",
+            "externs",
+            "loops",
+            "recursion",
+            "returns",
+            "unary",
+            "logic",
+            "bools",
+            "lambdas",
+        ];
+        for fixture in fixtures {
+            let original = fs::read_to_string(format!("src/tests/{}.txt", fixture)).unwrap();
+            let formatted = format(fixture);
+
+            assert_eq!(shape(&original), shape(&formatted), "{}", fixture);
+            assert_eq!(
+                format_source(fixture, &formatted).unwrap(),
+                formatted,
+                "{}",
+                fixture
+            );
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::{fmt, iter::Peekable, str::Chars};
+
+// just enough json to talk to other tools: written out for `--error-format` and
+// the dumps, and read back in by the language server
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    // keeps the order fields were added in, so output is stable
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Json::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    // `None` for anything that isn't exactly one json value
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut chars = text.chars().peekable();
+        let value = Json::parse_value(&mut chars)?;
+        Json::skip_whitespace(&mut chars);
+        chars.peek().is_none().then_some(value)
+    }
+
+    fn skip_whitespace(chars: &mut Peekable<Chars>) {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+
+    fn parse_value(chars: &mut Peekable<Chars>) -> Option<Json> {
+        Json::skip_whitespace(chars);
+        match chars.peek()? {
+            '{' => {
+                chars.next();
+                let mut fields = vec![];
+                Json::skip_whitespace(chars);
+                if chars.next_if_eq(&'}').is_some() {
+                    return Some(Json::Object(fields));
+                }
+                loop {
+                    Json::skip_whitespace(chars);
+                    let key = Json::parse_str(chars)?;
+                    Json::skip_whitespace(chars);
+                    chars.next_if_eq(&':')?;
+                    fields.push((key, Json::parse_value(chars)?));
+                    Json::skip_whitespace(chars);
+                    match chars.next()? {
+                        ',' => {}
+                        '}' => return Some(Json::Object(fields)),
+                        _ => return None,
+                    }
+                }
+            }
+            '[' => {
+                chars.next();
+                let mut items = vec![];
+                Json::skip_whitespace(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Some(Json::Array(items));
+                }
+                loop {
+                    items.push(Json::parse_value(chars)?);
+                    Json::skip_whitespace(chars);
+                    match chars.next()? {
+                        ',' => {}
+                        ']' => return Some(Json::Array(items)),
+                        _ => return None,
+                    }
+                }
+            }
+            '"' => Json::parse_str(chars).map(Json::String),
+            c if c.is_alphabetic() => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_alphabetic()) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "null" => Some(Json::Null),
+                    "true" => Some(Json::Bool(true)),
+                    "false" => Some(Json::Bool(false)),
+                    _ => None,
+                }
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(c) = chars
+                    .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                {
+                    number.push(c);
+                }
+                match number.parse::<i64>() {
+                    Ok(n) => Some(Json::Int(n)),
+                    Err(_) => number.parse::<f64>().ok().map(Json::Float),
+                }
+            }
+        }
+    }
+
+    fn parse_str(chars: &mut Peekable<Chars>) -> Option<String> {
+        chars.next_if_eq(&'"')?;
+        let mut s = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(s),
+                '\\' => match chars.next()? {
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => {
+                        let mut code = Json::parse_hex(chars)?;
+                        // anything past the first plane comes in as two halves
+                        if (0xd800..0xdc00).contains(&code) {
+                            chars.next_if_eq(&'\\')?;
+                            chars.next_if_eq(&'u')?;
+                            let low = Json::parse_hex(chars)?;
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low.checked_sub(0xdc00)?);
+                        }
+                        s.push(char::from_u32(code)?);
+                    }
+                    // `\"`, `\\` and `\/`
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn parse_hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+        let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+        u32::from_str_radix(&hex, 16).ok()
+    }
+
+    fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+        write!(f, "\"")?;
+        for c in s.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Int(n as i64)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(n) => write!(f, "{}", n),
+            // json has no way to write these
+            Json::Float(n) if !n.is_finite() => write!(f, "null"),
+            Json::Float(n) => write!(f, "{:?}", n),
+            Json::String(s) => Json::write_str(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    Json::write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let json = Json::object(vec![
+            ("message", "say \"hi\"\n".into()),
+            ("line", 3usize.into()),
+            ("labels", Json::Array(vec![Json::Null, Json::Int(-1)])),
+            (
+                "values",
+                Json::Array(vec![Json::Float(2.0), Json::Float(f64::NAN)]),
+            ),
+        ]);
+        assert_eq!(
+            json.to_string(),
+            r#"{"message":"say \"hi\"\n","line":3,"labels":[null,-1],"values":[2.0,null]}"#
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let json = Json::parse(
+            r#" {"id": 1, "params": {"text": "let \"x\"\n\u00e9\ud83d\ude00", "ok": true},
+                "list": [1.5, -2, null, false, []], "empty": {}} "#,
+        )
+        .unwrap();
+        assert_eq!(json.get("id").and_then(Json::as_int), Some(1));
+        let params = json.get("params").unwrap();
+        assert_eq!(
+            params.get("text").and_then(Json::as_str),
+            Some("let \"x\"\n\u{e9}\u{1f600}")
+        );
+        assert_eq!(params.get("ok"), Some(&Json::Bool(true)));
+        assert_eq!(
+            json.get("list").and_then(Json::as_array).unwrap(),
+            &[
+                Json::Float(1.5),
+                Json::Int(-2),
+                Json::Null,
+                Json::Bool(false),
+                Json::Array(vec![])
+            ]
+        );
+        assert_eq!(json.get("empty"), Some(&Json::Object(vec![])));
+
+        // whatever gets written can be read back
+        assert_eq!(Json::parse(&json.to_string()), Some(json));
+        for bad in ["", "{", "[1,]", "{\"a\" 1}", "nope", "\"open", "1 2"] {
+            assert_eq!(Json::parse(bad), None, "{}", bad);
+        }
+    }
+}
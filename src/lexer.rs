@@ -1,7 +1,4 @@
-use std::{
-    fs::File,
-    io::{self, BufReader, Read},
-};
+use std::io::{self, Read};
 
 use crate::errors::*;
 
@@ -22,6 +19,7 @@ pub enum Symbol {
     Pipe,
     Negate,
     Dot,
+    Backslash,
     Compound(Box<Symbol>, Box<Symbol>),
 }
 
@@ -41,16 +39,22 @@ pub enum Is {
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token {
     Extern,
+    Import,
     Identifier(String),
     Number(Option<isize>, Option<f64>),
+    Bool(bool),
     When,
     Symbol(Symbol),
     Let,
+    For,
+    In,
     Bracket(Bracket),
     Newline,
     Grouping(Vec<LocatedToken>),
     String(String),
     FunctionCall(String, Vec<LocatedToken>),
+    // whatever's being indexed, then what's inside the square brackets
+    Index(Box<LocatedToken>, Vec<LocatedToken>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -59,12 +63,23 @@ pub struct LocatedToken {
     location: Location,
 }
 
+// what a column counts; lsp positions are in utf-16 code units, so the language
+// server lexes with `Utf16` and can use the columns as they are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Columns {
+    Chars,
+    Utf16,
+}
+
 pub struct Interpreter {
+    // only ever read a whole utf-8 character at a time, `index` is a byte offset
     bytes: Vec<u8>,
     index: usize,
-    size: usize,
+    columns: Columns,
     inside_string: bool,
     current_location: Location,
+    // `# ...` comments never make it into the tokens, they're kept here for the formatter
+    comments: Vec<(String, Location)>,
 }
 
 impl LocatedToken {
@@ -82,50 +97,90 @@ impl LocatedToken {
 }
 
 impl Interpreter {
-    pub fn new(mut buf: BufReader<File>, filename: String) -> io::Result<Interpreter> {
+    // anything readable works, like a `BufReader<File>` or stdin
+    pub fn from_reader(mut buf: impl Read, filename: String) -> io::Result<Interpreter> {
         let mut bytes = vec![];
         buf.read_to_end(&mut bytes)?;
+        Ok(Interpreter::from_bytes(bytes, filename))
+    }
+
+    // source that's already in memory, like an open editor buffer or a line typed into the repl
+    pub fn from_str(source: &str, filename: String) -> Interpreter {
+        Interpreter::from_bytes(source.as_bytes().to_vec(), filename)
+    }
+
+    fn from_bytes(mut bytes: Vec<u8>, filename: String) -> Interpreter {
         bytes.push(0);
 
-        let size = bytes.len().clone();
-        Ok(Interpreter {
+        Interpreter {
             bytes,
             index: 0,
-            size,
+            columns: Columns::Chars,
             inside_string: false,
             current_location: Location::new(0, 0, filename),
-        })
+            comments: vec![],
+        }
+    }
+
+    pub fn with_columns(mut self, columns: Columns) -> Interpreter {
+        self.columns = columns;
+        self
     }
 
-    fn lt(&self, token: Token) -> LocatedToken {
-        LocatedToken::new(token, self.current_location.clone())
+    // locates a token that started at `start` and ends at the current position
+    fn lt(&self, token: Token, start: Location) -> LocatedToken {
+        LocatedToken::new(token, start.with_end(self.index))
+    }
+
+    // the character starting at `index`, or `Err` if the bytes there aren't utf-8
+    fn decode(&self, index: usize) -> Option<std::result::Result<char, ()>> {
+        let rest = self.bytes.get(index..).filter(|rest| !rest.is_empty())?;
+        let longest = &rest[..rest.len().min(4)];
+        let valid = match std::str::from_utf8(longest) {
+            Ok(valid) => valid,
+            Err(e) => std::str::from_utf8(&longest[..e.valid_up_to()]).unwrap(),
+        };
+        Some(valid.chars().next().ok_or(()))
+    }
+
+    // bad utf-8 stops whatever's being read and gets reported by `next`
+    fn peek(&self) -> Option<char> {
+        self.decode(self.index)
+            .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
     }
 
     fn next(&mut self) -> Result<char> {
-        if self.index < self.size {
-            let byte = self.bytes[self.index];
-            let ch = byte as char;
-            if ch == '\n' {
-                self.current_location.next_line();
-            } else {
-                self.current_location.next_column();
+        match self.decode(self.index) {
+            Some(Ok(ch)) => {
+                if ch == '\n' {
+                    self.current_location.next_line();
+                } else {
+                    let width = match self.columns {
+                        Columns::Chars => 1,
+                        Columns::Utf16 => ch.len_utf16(),
+                    };
+                    self.current_location.next_column(width, ch.len_utf8());
+                }
+                self.index += ch.len_utf8();
+                Ok(ch)
             }
-            self.index += 1;
-            Ok(ch)
-        } else {
-            EofError::while_parsing(self.current_location.clone())
+            Some(Err(())) => {
+                let location = self.current_location.clone().with_end(self.index + 1);
+                EncodingError::while_parsing(location)
+            }
+            None => EofError::while_parsing(self.current_location.clone()),
         }
     }
 
-    fn parse_grouping(&mut self, bracket: Bracket) -> Result<LocatedToken> {
+    fn parse_grouping(&mut self, bracket: Bracket, start: Location) -> Result<LocatedToken> {
         match bracket {
             Bracket::Parens(Is::Closed) | Bracket::Square(Is::Closed) => {
-                return Ok(self.lt(Token::Bracket(bracket)));
+                return Ok(self.lt(Token::Bracket(bracket), start));
             }
             _ => {}
         }
 
-        let mut group = vec![self.lt(Token::Bracket(bracket))];
+        let mut group = vec![self.lt(Token::Bracket(bracket), start.clone())];
         loop {
             let next = self.parse_next()?;
             match next.tok() {
@@ -146,10 +201,10 @@ impl Interpreter {
 
         if let (Token::Bracket(o), Token::Bracket(c)) = (open.tok(), close.tok()) {
             if std::mem::discriminant(&o) == std::mem::discriminant(&c) {
-                return Ok(self.lt(Token::Grouping(group)));
+                return Ok(self.lt(Token::Grouping(group), start));
             }
         }
-        return GroupingError::while_parsing(self.current_location.clone());
+        GroupingError::while_parsing(close.loc())
     }
 
     fn parse_symbol(next: char) -> Option<Symbol> {
@@ -169,113 +224,267 @@ impl Interpreter {
             '|' => Some(Symbol::Pipe),
             '!' => Some(Symbol::Negate),
             '.' => Some(Symbol::Dot),
+            '\\' => Some(Symbol::Backslash),
             _ => None,
         }
     }
 
+    // only these pairs stick together, so `x*-1` is still a `*` and then a `-`
+    fn compound(first: &Symbol, second: &Symbol) -> bool {
+        matches!(
+            (first, second),
+            (Symbol::LessThan, Symbol::Equals)
+                | (Symbol::GreaterThan, Symbol::Equals)
+                | (Symbol::Equals, Symbol::Equals)
+                | (Symbol::Negate, Symbol::Equals)
+                | (Symbol::Ampersand, Symbol::Ampersand)
+                | (Symbol::Pipe, Symbol::Pipe)
+                | (Symbol::Dot, Symbol::Dot)
+                | (Symbol::Subtract, Symbol::GreaterThan)
+        )
+    }
+
     fn ident(next: char) -> bool {
         let symbols = ['_', '$'];
         next.is_alphabetic() || symbols.contains(&next)
     }
 
-    pub fn parse_next(&mut self) -> Result<LocatedToken> {
-        let mut next = self.next()?;
-        let mut identifier = String::new();
+    // blank lines and comment-only lines all fold into the newline before them
+    fn skip_blank_lines(&mut self) -> Result<()> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {}
+                Some('#') => {
+                    let start = self.current_location.clone();
+                    self.next()?;
+                    self.read_comment(start)?;
+                    continue;
+                }
+                _ => return Ok(()),
+            }
+            self.next()?;
+        }
+    }
 
-        while next.is_whitespace() && !self.inside_string {
-            if next == '\n' {
-                loop {
-                    next = self.next()?;
-                    if next != '\n' {
-                        self.index -= 1;
-                        self.current_location.previous_column();
-                        break;
+    // `"..."` (which can go over more than one line), the opening quote has already been read
+    fn read_string(&mut self, start: Location) -> Result<LocatedToken> {
+        let mut value = String::new();
+        loop {
+            let escape = self.current_location.clone();
+            match self.peek() {
+                // the nul is the one `from_bytes` put on the end
+                None | Some('\0') => return Interpreter::unterminated(start, 1),
+                Some('"') => break,
+                Some('\\') => {
+                    self.next()?;
+                    if matches!(self.peek(), None | Some('\0')) {
+                        return Interpreter::unterminated(start, 1);
                     }
+                    value.push(self.read_escape(escape)?);
                 }
-                return Ok(self.lt(Token::Newline));
+                Some(_) => value.push(self.next()?),
             }
-            next = self.next()?;
         }
+        self.next()?;
+        Ok(self.lt(Token::String(value), start))
+    }
 
-        if next == '\"' {
-            let mut next_char = self.next()?;
-            let mut full_string = String::new();
-            while next_char != '\"' {
-                full_string.push(next_char);
-                next_char = self.next()?;
+    // whatever's after a `\`, which was at `escape`
+    fn read_escape(&mut self, escape: Location) -> Result<char> {
+        let ch = match self.peek() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            None => return EofError::while_parsing(escape),
+            Some('u') => {
+                self.next()?;
+                return self.read_unicode(escape);
+            }
+            Some(other) => {
+                let location = escape.with_end(self.index + other.len_utf8());
+                let note = format!(
+                    "'\\{}' isn't an escape, try \\n, \\t, \\r, \\\\, \\\" or \\u{{...}}",
+                    other
+                );
+                return Err(EscapeError::diagnostic(location, "parse")
+                    .with_note(&note)
+                    .into());
+            }
+        };
+        self.next()?;
+        Ok(ch)
+    }
+
+    // `\u{1f600}`, up to the `u`'s been read already
+    fn read_unicode(&mut self, escape: Location) -> Result<char> {
+        let mut digits = String::new();
+        let braced = self.peek() == Some('{');
+        if braced {
+            self.next()?;
+            while let Some(c) = self.peek().filter(char::is_ascii_hexdigit) {
+                digits.push(c);
+                self.next()?;
+            }
+        }
+        if !braced || self.peek() != Some('}') || digits.is_empty() || digits.len() > 6 {
+            let location = escape.with_end(self.index);
+            return Err(EscapeError::diagnostic(location, "parse")
+                .with_note("'\\u' needs one to six hex digits in braces, like \\u{e9}")
+                .into());
+        }
+        self.next()?;
+        let location = escape.with_end(self.index);
+        // nul can't be passed on to codegen, which takes strings nul-terminated
+        match char::from_u32(u32::from_str_radix(&digits, 16).unwrap()) {
+            Some(ch) if ch != '\0' => Ok(ch),
+            _ => {
+                let note = format!("'\\u{{{}}}' isn't a character strings can hold", digits);
+                Err(EscapeError::diagnostic(location, "parse")
+                    .with_note(&note)
+                    .into())
+            }
+        }
+    }
+
+    // how many `#`s are between an `r` that's just been read and a `"`, if that's what comes next
+    fn raw_hashes(&self) -> Option<usize> {
+        let rest = &self.bytes[self.index..];
+        let hashes = rest.iter().take_while(|&&byte| byte == b'#').count();
+        (rest.get(hashes) == Some(&b'"')).then_some(hashes)
+    }
+
+    // `r"..."` or `r#"..."#`, where nothing's escaped and it only ends at a `"`
+    // followed by as many `#`s as it started with
+    fn read_raw_string(&mut self, start: Location, hashes: usize) -> Result<LocatedToken> {
+        for _ in 0..=hashes {
+            self.next()?;
+        }
+        let closing = format!("\"{}", "#".repeat(hashes));
+        let mut value = String::new();
+        while !self.bytes[self.index..].starts_with(closing.as_bytes()) {
+            match self.peek() {
+                None | Some('\0') => return Interpreter::unterminated(start, hashes + 2),
+                Some(_) => value.push(self.next()?),
+            }
+        }
+        for _ in 0..=hashes {
+            self.next()?;
+        }
+        Ok(self.lt(Token::String(value), start))
+    }
+
+    // points at the `"` (and `r#...` before it) a string was started with
+    fn unterminated<T>(start: Location, opening: usize) -> Result<T> {
+        let location = start.clone().with_end(start.get_start() + opening);
+        UnterminatedStringError::while_parsing(location)
+    }
+
+    // the rest of the line after a `#`, which has already been read
+    fn read_comment(&mut self, start: Location) -> Result<()> {
+        let mut text = "#".to_string();
+        while !matches!(self.peek(), Some('\n') | None) {
+            text.push(self.next()?);
+        }
+        let location = start.with_end(self.index);
+        self.comments.push((text.trim_end().to_string(), location));
+        Ok(())
+    }
+
+    pub fn parse_next(&mut self) -> Result<LocatedToken> {
+        while matches!(self.peek(), Some(c) if c.is_whitespace() && c != '\n' && !self.inside_string)
+        {
+            self.next()?;
+        }
+
+        let start = self.current_location.clone();
+        let next = self.next()?;
+        let mut identifier = String::new();
+
+        if next == '\n' {
+            let newline = self.lt(Token::Newline, start);
+            self.skip_blank_lines()?;
+            return Ok(newline);
+        }
+
+        if next == '"' {
+            return self.read_string(start);
+        }
+
+        if next == 'r' {
+            if let Some(hashes) = self.raw_hashes() {
+                return self.read_raw_string(start, hashes);
             }
-            return Ok(self.lt(Token::String(full_string)));
         }
 
         if Interpreter::ident(next) {
-            loop {
-                identifier.push(next);
-                next = self.next()?;
-                if !Interpreter::ident(next) && !next.is_numeric() {
-                    self.index -= 1;
-                    self.current_location.previous_column();
+            identifier.push(next);
+            while let Some(c) = self.peek() {
+                if !Interpreter::ident(c) && !c.is_numeric() {
                     break;
                 }
+                identifier.push(self.next()?);
             }
 
             let token = match identifier.as_str() {
                 "extern" => Token::Extern,
+                "import" => Token::Import,
                 "when" => Token::When,
                 "let" => Token::Let,
+                "for" => Token::For,
+                "in" => Token::In,
+                "true" => Token::Bool(true),
+                "false" => Token::Bool(false),
                 i => Token::Identifier(i.to_string()),
             };
 
-            return Ok(self.lt(token));
+            return Ok(self.lt(token, start));
         }
 
-        let mut num_str = None;
-        while next.is_numeric() || next == '.' {
-            num_str = match num_str {
-                None => Some(String::new()),
-                _ => num_str,
-            };
-            num_str.as_mut().unwrap().push(next);
-            next = self.next()?;
-        }
+        if next.is_numeric() || (next == '.' && matches!(self.peek(), Some(c) if c.is_numeric())) {
+            let mut num = next.to_string();
+            while let Some(c) = self.peek() {
+                // `0..n` is a range, not a malformed float
+                let range = c == '.' && self.bytes.get(self.index + 1) == Some(&b'.');
+                if (!c.is_numeric() && c != '.') || range {
+                    break;
+                }
+                num.push(self.next()?);
+            }
 
-        if let Some(num) = num_str {
-            let mut int_val: Option<isize> = None;
-            let mut float_val = None;
-            self.index -= 1;
-            self.current_location.previous_column();
-            if num == "." {
-                next = '.';
+            let token = if num.contains('.') {
+                match num.parse::<f64>() {
+                    Ok(float) => Token::Number(None, Some(float)),
+                    Err(_) => return UnknownTokenError::while_parsing(start.with_end(self.index)),
+                }
             } else {
-                if num.contains('.') {
-                    float_val = Some(num.parse::<f64>().unwrap());
-                } else {
-                    int_val = Some(num.parse::<isize>().unwrap());
+                match num.parse::<isize>() {
+                    Ok(int) => Token::Number(Some(int), None),
+                    Err(_) => return UnknownTokenError::while_parsing(start.with_end(self.index)),
                 }
-                return Ok(self.lt(Token::Number(int_val, float_val)));
-            }
+            };
+            return Ok(self.lt(token, start));
         }
 
         if next == '#' {
-            loop {
-                next = self.next()?;
-                if next == '\n' || next == '\r' {
-                    return self.parse_next();
-                }
-            }
+            self.read_comment(start)?;
+            return self.parse_next();
         }
 
         // check binary operators
         let symbol = Interpreter::parse_symbol(next);
 
         if let Some(op) = symbol {
-            if let Ok(another) = self.next() {
-                if let Some(second) = Interpreter::parse_symbol(another) {
-                    return Ok(self.lt(Token::Symbol(Symbol::Compound(op.into(), second.into()))));
-                }
-                self.index -= 1;
-                self.current_location.previous_column();
+            let second = self.peek().and_then(Interpreter::parse_symbol);
+            if let Some(second) = second.filter(|second| Interpreter::compound(&op, second)) {
+                self.next()?;
+                return Ok(self.lt(
+                    Token::Symbol(Symbol::Compound(op.into(), second.into())),
+                    start,
+                ));
             }
-            return Ok(self.lt(Token::Symbol(op)));
+            return Ok(self.lt(Token::Symbol(op), start));
         }
 
         let bracket = match next {
@@ -287,41 +496,61 @@ impl Interpreter {
         };
 
         if let Some(br) = bracket {
-            self.parse_grouping(br)
+            self.parse_grouping(br, start)
         } else {
-            UnknownTokenError::while_parsing(self.current_location.clone())
+            UnknownTokenError::while_parsing(start.with_end(self.index))
         }
     }
 
+    // `name(...)` turns into a `FunctionCall` and `operand[...]` into an `Index`,
+    // all the way down through any groupings
     fn compress_fn_calls(tokens: Vec<LocatedToken>) -> Vec<LocatedToken> {
-        let mut comp = vec![];
+        let mut comp: Vec<LocatedToken> = vec![];
         let mut last = None;
         let mut double_last = None;
 
         for token in tokens {
-            match token.tok() {
-                Token::Grouping(args) => {
-                    if let Some(Token::Identifier(name)) = last.clone() {
-                        if !matches!(double_last, Some(Token::Let)) {
-                            comp.pop();
-                            comp.push(LocatedToken::new(Token::FunctionCall(
-                                name,
-                                Interpreter::compress_fn_calls(args[1..args.len() - 1].to_vec()),
-                            ), token.loc()));
-                            continue;
+            let token = match token.tok() {
+                Token::Grouping(group) => {
+                    let inner = Interpreter::compress_fn_calls(group[1..group.len() - 1].to_vec());
+                    let square = group[0].tok() == Token::Bracket(Bracket::Square(Is::Open));
+                    match last.clone() {
+                        Some(Token::Identifier(name))
+                            if !square
+                                && !matches!(double_last, Some(Token::Let | Token::Extern)) =>
+                        {
+                            let name_location = comp.pop().unwrap().loc();
+                            LocatedToken::new(
+                                Token::FunctionCall(name, inner),
+                                name_location.with_end(token.loc().get_end()),
+                            )
+                        }
+                        Some(
+                            Token::Identifier(_)
+                            | Token::FunctionCall(_, _)
+                            | Token::Index(_, _)
+                            | Token::Grouping(_),
+                        ) if square => {
+                            let target = comp.pop().unwrap();
+                            let location = target.loc().with_end(token.loc().get_end());
+                            LocatedToken::new(Token::Index(Box::new(target), inner), location)
+                        }
+                        _ => {
+                            let mut regrouped = vec![group[0].clone()];
+                            regrouped.extend(inner);
+                            regrouped.push(group[group.len() - 1].clone());
+                            LocatedToken::new(Token::Grouping(regrouped), token.loc())
                         }
                     }
-                    comp.push(LocatedToken::new(Token::Grouping(args), token.loc()));
                 }
-                t => {
-                    double_last = last;
-                    last = Some(t.clone());
-                    comp.push(LocatedToken::new(t, token.loc()));
-                }
-            }
+                _ => token,
+            };
+            double_last = last;
+            last = Some(token.tok());
+            comp.push(token);
         }
 
-        return comp;
+        comp
     }
 
     pub fn pull(&mut self) -> Result<Vec<LocatedToken>> {
@@ -343,7 +572,11 @@ impl Interpreter {
         }
     }
 
+    pub fn comments(&self) -> &[(String, Location)] {
+        &self.comments
+    }
+
     pub fn done(&self) -> bool {
-        self.index == self.size
+        self.index == self.bytes.len()
     }
 }
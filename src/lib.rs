@@ -1,53 +1,256 @@
 use ast::*;
-use options::Options;
-use parser::Parser;
-use std::ffi::c_char;
+use dump::render;
+use errors::Diagnostic;
+use format::format_file;
+use lexer::Interpreter;
+use loader::Loader;
+use options::{Action, Emit, FFISafeOptions, Options, USAGE};
+use repl::Repl;
+use std::{
+    ffi::{c_char, CStr},
+    fs::{self, File},
+    io::{self, BufReader},
+};
+use typeck::TypeChecker;
 
-mod ast;
-mod errors;
-mod lexer;
-mod options;
-mod parser;
+pub mod ast;
+pub mod closures;
+pub mod dump;
+pub mod errors;
+pub mod eval;
+pub mod format;
+pub mod json;
+pub mod lexer;
+pub mod loader;
+pub mod lsp;
+pub mod options;
+pub mod parser;
+pub mod repl;
+pub mod typeck;
 
+/// # Safety
+///
+/// `start` and `size` must be the `argv` and `argc` handed to `main`.
 #[no_mangle]
-pub extern "C" fn recieve_tokens(start: *mut *mut c_char, size: usize) -> FFISafeExprVec {
-    let options = Options::new(start, size).expect("no filename given!");
-    let tree = Parser::new(&options.filename).expect("missing file!").run();
-    
-    let ffi_safe_tree = convert_vec(tree);
-    let ffi_safe = FFISafeExprVec {
-        ptr: ffi_safe_tree.0,
-        len: ffi_safe_tree.1,
-        out: convert_str(options.outfile),
+pub unsafe extern "C" fn recieve_tokens(start: *mut *mut c_char, size: usize) -> FFISafeExprVec {
+    let options = match Options::new(start, size) {
+        Ok(options) => options,
+        Err(e) => {
+            eprint!("{}", Diagnostic::from(e));
+            eprintln!("run `fx --help` to see what's available");
+            return FFISafeExprVec {
+                ptr: std::ptr::null_mut(),
+                len: 0,
+                options: FFISafeOptions::default(),
+                errors: 1,
+                finished: true,
+            };
+        }
+    };
+    let result = match options.action {
+        Action::Help => {
+            print!("{}", USAGE);
+            Ok(None)
+        }
+        Action::Version => {
+            println!("fx {}", env!("CARGO_PKG_VERSION"));
+            Ok(None)
+        }
+        Action::Format => run_formatter(&options).map(|_| None),
+        Action::Repl => Repl::new()
+            .with_include_paths(&options.include_paths)
+            .run(io::stdin().lock(), io::stdout().lock())
+            .map(|_| None)
+            .map_err(|e| vec![Diagnostic::from(Box::from(e))]),
+        Action::Compile => run_frontend(&options),
+    };
+    hand_over(result, &options, &|filename| {
+        fs::read_to_string(filename).ok()
+    })
+}
+
+/// # Safety
+///
+/// `name` must be a valid, nul-terminated string and `source` must point to `len` bytes
+/// (which don't have to be nul-terminated).
+///
+/// Like `recieve_tokens` for `fx <name>`, but the file's contents come from `source`
+/// instead of being read from disk. Imports are still read from disk, relative to `name`.
+#[no_mangle]
+pub unsafe extern "C" fn recieve_source(
+    name: *const c_char,
+    source: *const c_char,
+    len: usize,
+) -> FFISafeExprVec {
+    let name = CStr::from_ptr(name).to_string_lossy().to_string();
+    let bytes = std::slice::from_raw_parts(source as *const u8, len);
+    let options = match Options::parse(vec!["fx".to_string(), name.clone()]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprint!("{}", Diagnostic::from(e));
+            return FFISafeExprVec {
+                ptr: std::ptr::null_mut(),
+                len: 0,
+                options: FFISafeOptions::default(),
+                errors: 1,
+                finished: true,
+            };
+        }
     };
-    return ffi_safe;
+    let result = std::str::from_utf8(bytes)
+        .map_err(|e| vec![Diagnostic::from(Box::from(e))])
+        .and_then(|source| Loader::new().load_source(&name, source))
+        .and_then(|tree| TypeChecker::new().check(tree))
+        .map(Some);
+    let source_of = buffer_source(&name, bytes);
+    hand_over(result, &options, &source_of)
+}
+
+// like reading from disk, except that `name` is `source` however it looks when saved
+fn buffer_source<'a>(name: &'a str, source: &'a [u8]) -> impl Fn(&str) -> Option<String> + 'a {
+    move |filename| match filename == name {
+        true => std::str::from_utf8(source).ok().map(str::to_string),
+        false => fs::read_to_string(filename).ok(),
+    }
+}
+
+// what the c++ side gets back: a tree to generate code for, or `finished` if
+// there's nothing left to do (including because of errors, which are printed here)
+fn hand_over(
+    result: std::result::Result<Option<Vec<LocatedExpr>>, Vec<Diagnostic>>,
+    options: &Options,
+    source_of: &dyn Fn(&str) -> Option<String>,
+) -> FFISafeExprVec {
+    match result {
+        Ok(Some(tree)) => {
+            let ffi_safe_tree = convert_vec(closures::convert(tree));
+            FFISafeExprVec {
+                ptr: ffi_safe_tree.0,
+                len: ffi_safe_tree.1,
+                options: FFISafeOptions::from(options),
+                errors: 0,
+                finished: false,
+            }
+        }
+        Ok(None) => FFISafeExprVec {
+            ptr: std::ptr::null_mut(),
+            len: 0,
+            options: FFISafeOptions::from(options),
+            errors: 0,
+            finished: true,
+        },
+        Err(diagnostics) => {
+            eprint!("{}", report(&diagnostics, options, source_of));
+            FFISafeExprVec {
+                ptr: std::ptr::null_mut(),
+                len: 0,
+                options: FFISafeOptions::from(options),
+                errors: diagnostics.len(),
+                finished: true,
+            }
+        }
+    }
+}
+
+fn report(
+    diagnostics: &[Diagnostic],
+    options: &Options,
+    source_of: &dyn Fn(&str) -> Option<String>,
+) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| options.error_format.render(diagnostic, source_of))
+        .collect()
+}
+
+fn run_formatter(options: &Options) -> std::result::Result<(), Vec<Diagnostic>> {
+    let formatted = format_file(&options.filename)?;
+    if options.outfile == "-" {
+        print!("{}", formatted);
+        return Ok(());
+    }
+    fs::write(&options.outfile, formatted).map_err(|e| vec![Diagnostic::from(Box::from(e))])
+}
+
+// everything before codegen, or `None` if `--emit` stops short of it
+fn run_frontend(
+    options: &Options,
+) -> std::result::Result<Option<Vec<LocatedExpr>>, Vec<Diagnostic>> {
+    if options.emit == Emit::Tokens {
+        let tokens = File::open(&options.filename)
+            .and_then(|file| {
+                Interpreter::from_reader(BufReader::new(file), options.filename.clone())
+            })
+            .map_err(|e| vec![Diagnostic::from(Box::from(e))])?
+            .pull()
+            .map_err(|e| vec![Diagnostic::from(e)])?;
+        print!("{}", render(&tokens, options.dump_format));
+        return Ok(None);
+    }
+
+    let tree = Loader::new()
+        .with_include_paths(&options.include_paths)
+        .load(&options.filename)?;
+    if options.emit == Emit::Ast {
+        print!("{}", render(&tree, options.dump_format));
+        return Ok(None);
+    }
+
+    let mut checker = TypeChecker::new();
+    let tree = checker.check(tree)?;
+    if let Some(entry) = &options.entry {
+        checker
+            .check_entry(entry)
+            .map_err(|e| vec![Diagnostic::from(e)])?;
+    }
+    if options.emit == Emit::TypedAst {
+        print!("{}", render(&tree, options.dump_format));
+        return Ok(None);
+    }
+    Ok(Some(tree))
 }
 
 unsafe fn box_drop<T>(ffi_val: *mut T) {
     drop(Box::from_raw(ffi_val));
 }
 
+// the name and parameters that definitions and extern declarations share
+unsafe fn drop_header(
+    name: *mut c_char,
+    arg_start: *mut *mut c_char,
+    type_start: *mut FFISafeType,
+    len: usize,
+) {
+    let args = Box::from_raw(std::ptr::slice_from_raw_parts_mut(arg_start, len));
+    box_drop(name);
+    for &arg in args.iter() {
+        box_drop(arg);
+    }
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+        type_start, len,
+    )));
+}
+
 unsafe fn drop_expr(expr: &LocatedFFISafeExpr) {
     match *expr.get_expr() {
         FFISafeExpr::StringLiteral(s) => {
             box_drop(s);
         }
-        FFISafeExpr::FunctionDefinition(name, arg_start, len, body) => {
-            let args = std::slice::from_raw_parts(arg_start, len);
-            box_drop(name);
-            for &arg in args {
-                box_drop(arg);
-            }
-            if len > 0 {
-                box_drop(arg_start);
-            }
+        FFISafeExpr::FunctionDefinition(name, arg_start, type_start, len, body, _) => {
+            drop_header(name, arg_start, type_start, len);
             drop_expr(body.as_ref().unwrap());
             box_drop(body);
         }
+        FFISafeExpr::ExternDeclaration(name, arg_start, type_start, len, _) => {
+            drop_header(name, arg_start, type_start, len);
+        }
         FFISafeExpr::ChainExpression(chain_start, len) => {
             drop_all(chain_start, len);
         }
-        FFISafeExpr::BinaryOperation(_, left, right) | FFISafeExpr::WhenExpression(left, right) => {
+        FFISafeExpr::BinaryOperation(_, left, right)
+        | FFISafeExpr::WhenExpression(left, right)
+        | FFISafeExpr::Index(left, right)
+        | FFISafeExpr::Range(left, right) => {
             drop_expr(left.as_ref().unwrap());
             drop_expr(right.as_ref().unwrap());
             box_drop(left);
@@ -60,15 +263,97 @@ unsafe fn drop_expr(expr: &LocatedFFISafeExpr) {
         FFISafeExpr::VariableRef(name) => {
             box_drop(name);
         }
+        FFISafeExpr::ArrayLiteral(element_start, len, _) => {
+            drop_all(element_start, len);
+        }
+        FFISafeExpr::Length(inner) | FFISafeExpr::UnaryOperation(_, inner) => {
+            drop_expr(inner.as_ref().unwrap());
+            box_drop(inner);
+        }
+        FFISafeExpr::Apply(function, arg_start, len, _) => {
+            drop_expr(function.as_ref().unwrap());
+            box_drop(function);
+            drop_all(arg_start, len);
+        }
+        FFISafeExpr::Closure(name, capture_start, len) => {
+            box_drop(name);
+            let captures = Box::from_raw(std::ptr::slice_from_raw_parts_mut(capture_start, len));
+            for &capture in captures.iter() {
+                box_drop(capture);
+            }
+        }
+        FFISafeExpr::For(_, name, first, body, _) | FFISafeExpr::Let(name, first, body) => {
+            box_drop(name);
+            drop_expr(first.as_ref().unwrap());
+            drop_expr(body.as_ref().unwrap());
+            box_drop(first);
+            box_drop(body);
+        }
         _ => {}
     }
     box_drop(expr.get_filename());
 }
 
+/// # Safety
+///
+/// `start` and `len` must describe a slice handed out by `recieve_tokens` (or
+/// one of its nested expression vectors) that has not already been dropped.
 #[no_mangle]
 pub unsafe extern "C" fn drop_all(start: *mut LocatedFFISafeExpr, len: usize) {
-    let arr = Box::from_raw(std::slice::from_raw_parts_mut(start, len));
+    let arr = Box::from_raw(std::ptr::slice_from_raw_parts_mut(start, len));
     for expr in arr.iter() {
         drop_expr(expr);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use options::drop_options;
+    use std::ffi::CString;
+
+    fn compile(source: &str) -> FFISafeExprVec {
+        let name = CString::new("src/tests/imports/unsaved.txt").unwrap();
+        unsafe {
+            recieve_source(
+                name.as_ptr(),
+                source.as_ptr() as *const c_char,
+                source.len(),
+            )
+        }
+    }
+
+    #[test]
+    fn sources() {
+        // nothing after `len` is read, so there's no need for a nul at the end
+        let source = "import \"math.txt\"\n\nlet area(r) = square(r) * 3junk";
+        let vec = compile(&source[..46]);
+        assert_eq!((vec.len, vec.errors, vec.finished), (2, 0, false));
+        unsafe {
+            drop_all(vec.ptr, vec.len);
+            drop_options(vec.options);
+        }
+
+        let vec = compile("let bad() = nope()");
+        assert_eq!((vec.errors, vec.finished), (1, true));
+        unsafe { drop_options(vec.options) };
+    }
+
+    #[test]
+    fn snippets_come_from_the_buffer() {
+        // math.txt on disk only has `square` in it
+        let name = "src/tests/imports/math.txt";
+        let source = "let square(x) = x * x\n\nlet cube(x) = x * nope(x)\n";
+        let diagnostics = Loader::new()
+            .load_source(name, source)
+            .and_then(|tree| TypeChecker::new().check(tree))
+            .unwrap_err();
+        let options = Options::parse(vec!["fx".to_string(), name.to_string()]).unwrap();
+        let rendered = report(
+            &diagnostics,
+            &options,
+            &buffer_source(name, source.as_bytes()),
+        );
+        assert!(rendered.contains("let cube(x) = x * nope(x)"));
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{ast::LocatedExpr, errors::*, parser::Parser};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// follows every `import "..."` from the file it starts at and merges all the
+// definitions into one tree, so typeck and codegen never see more than one file
+pub struct Loader {
+    // files that have been read all the way through, so a file imported from
+    // two places only ends up in the tree once
+    loaded: HashSet<PathBuf>,
+    // the imports currently being followed, outermost first
+    stack: Vec<PathBuf>,
+    // where to look (in order) when an import isn't next to the file importing it
+    include_paths: Vec<PathBuf>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Default for Loader {
+    fn default() -> Loader {
+        Loader::new()
+    }
+}
+
+impl Loader {
+    pub fn new() -> Loader {
+        Loader {
+            loaded: HashSet::new(),
+            stack: vec![],
+            include_paths: vec![],
+            errors: vec![],
+        }
+    }
+
+    pub fn with_include_paths(mut self, paths: &[String]) -> Loader {
+        self.include_paths.extend(paths.iter().map(PathBuf::from));
+        self
+    }
+
+    pub fn load(&mut self, src: &str) -> std::result::Result<Vec<LocatedExpr>, Vec<Diagnostic>> {
+        let tree = self.load_file(Path::new(src), None, None);
+        self.finish(tree)
+    }
+
+    // like `load`, but the first file comes from memory; `name` is still where
+    // its imports are looked for relative to
+    pub fn load_source(
+        &mut self,
+        name: &str,
+        source: &str,
+    ) -> std::result::Result<Vec<LocatedExpr>, Vec<Diagnostic>> {
+        let tree = self.load_file(Path::new(name), Some(source), None);
+        self.finish(tree)
+    }
+
+    fn finish(
+        &mut self,
+        tree: Vec<LocatedExpr>,
+    ) -> std::result::Result<Vec<LocatedExpr>, Vec<Diagnostic>> {
+        if self.errors.is_empty() {
+            Ok(tree)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    // imported definitions come before the importing file's own, so codegen
+    // has already declared everything a body could call
+    fn load_file(
+        &mut self,
+        path: &Path,
+        source: Option<&str>,
+        imported_at: Option<Location>,
+    ) -> Vec<LocatedExpr> {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if let Some(start) = self.stack.iter().position(|open| open == &key) {
+            let cycle: Vec<String> = self.stack[start..]
+                .iter()
+                .chain([&key])
+                .map(|file| Loader::display(file))
+                .collect();
+            let note = format!("the imports go {}", cycle.join(" -> "));
+            self.errors
+                .push(Diagnostic::from(ImportCycleError::basic(imported_at)).with_note(&note));
+            return vec![];
+        }
+        if self.loaded.contains(&key) {
+            return vec![];
+        }
+
+        let parsed = match source {
+            Some(source) => Parser::from_source(&path.to_string_lossy(), source),
+            None => Parser::new(&path.to_string_lossy()),
+        };
+        let mut parser = match parsed {
+            Ok(parser) => parser,
+            Err(e) => {
+                let error = match imported_at {
+                    Some(location) => {
+                        let note = format!("couldn't read '{}': {}", path.display(), e);
+                        ImportError::diagnostic(location, "parse").with_note(&note)
+                    }
+                    None => Diagnostic::from(e),
+                };
+                self.errors.push(error);
+                return vec![];
+            }
+        };
+        // a file with errors still has its imports followed, so they get reported too
+        let own = parser.run().unwrap_or_else(|mut errors| {
+            self.errors.append(&mut errors);
+            vec![]
+        });
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut tree = vec![];
+        self.stack.push(key.clone());
+        for (import, location) in parser.imports().to_vec() {
+            let path = self.resolve(directory, &import);
+            tree.extend(self.load_file(&path, None, Some(location)));
+        }
+        self.stack.pop();
+        self.loaded.insert(key);
+
+        tree.extend(own);
+        tree
+    }
+
+    // next to the importing file wins, then the include paths; if it's nowhere
+    // the path next to the importing file is what the error mentions
+    pub fn resolve(&self, directory: &Path, import: &str) -> PathBuf {
+        let local = directory.join(import);
+        if local.exists() {
+            return local;
+        }
+        self.include_paths
+            .iter()
+            .map(|include| include.join(import))
+            .find(|path| path.exists())
+            .unwrap_or(local)
+    }
+
+    fn display(file: &Path) -> String {
+        file.file_name()
+            .map_or(file.to_string_lossy(), |name| name.to_string_lossy())
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::Expr, typeck::TypeChecker};
+
+    fn load(file: &str) -> std::result::Result<Vec<LocatedExpr>, Vec<Diagnostic>> {
+        Loader::new().load(&format!("src/tests/imports/{}.txt", file))
+    }
+
+    fn messages(errors: Vec<Diagnostic>) -> Vec<(String, usize, String)> {
+        errors
+            .iter()
+            .map(|e| {
+                let location = e.get_location().unwrap();
+                (
+                    location.get_filename(),
+                    location.get_line(),
+                    e.get_message(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merges_imports() {
+        let tree = load("main").unwrap();
+        let names: Vec<(String, String)> = tree
+            .iter()
+            .filter_map(|def| match def.get_expr() {
+                Expr::FunctionDefinition(name, ..) => {
+                    Some((name, def.get_location().get_filename()))
+                }
+                _ => None,
+            })
+            .collect();
+        // math.txt is imported twice but only shows up once, before anything using it
+        assert_eq!(
+            names,
+            vec![
+                (
+                    "square".to_string(),
+                    "src/tests/imports/math.txt".to_string()
+                ),
+                (
+                    "area".to_string(),
+                    "src/tests/imports/shapes.txt".to_string()
+                ),
+                (
+                    "entry".to_string(),
+                    "src/tests/imports/main.txt".to_string()
+                ),
+            ]
+        );
+        assert!(TypeChecker::new().check(tree).is_ok());
+    }
+
+    #[test]
+    fn include_paths() {
+        // helpers.txt only lives in lib/, so it's missing without the include path
+        assert!(load("includes").is_err());
+        let tree = Loader::new()
+            .with_include_paths(&["src/tests/imports/lib".to_string()])
+            .load("src/tests/imports/includes.txt")
+            .unwrap();
+        assert_eq!(
+            tree[0].get_location().get_filename(),
+            "src/tests/imports/lib/helpers.txt"
+        );
+    }
+
+    #[test]
+    fn sources() {
+        // main.txt doesn't exist here, it's only where `math.txt` is looked for from
+        let source = "import \"math.txt\"\n\nlet entry() = square(2)\n";
+        let tree = Loader::new()
+            .load_source("src/tests/imports/unsaved.txt", source)
+            .unwrap();
+        let files: Vec<String> = tree
+            .iter()
+            .map(|def| def.get_location().get_filename())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                "src/tests/imports/math.txt",
+                "src/tests/imports/unsaved.txt"
+            ]
+        );
+        assert!(Loader::new()
+            .load_source("nowhere/unsaved.txt", source)
+            .is_err());
+    }
+
+    #[test]
+    fn import_errors() {
+        assert_eq!(
+            messages(load("cycle_a").unwrap_err()),
+            vec![(
+                "src/tests/imports/cycle_b.txt".to_string(),
+                0,
+                "circular import!".to_string()
+            )]
+        );
+        assert_eq!(
+            messages(load("missing").unwrap_err()),
+            vec![
+                (
+                    "src/tests/imports/missing.txt".to_string(),
+                    2,
+                    "couldn't import file!".to_string()
+                ),
+                (
+                    "src/tests/imports/missing.txt".to_string(),
+                    0,
+                    "couldn't import file!".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn clashing_names() {
+        let tree = load("clash").unwrap();
+        let errors = TypeChecker::new().check(tree).unwrap_err();
+        assert_eq!(
+            messages(errors),
+            vec![(
+                "src/tests/imports/clash.txt".to_string(),
+                2,
+                "function defined more than once!".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,550 @@
+use crate::{
+    ast::{Expr, Param, Signature},
+    errors::*,
+    json::Json,
+    lexer::{Columns, Interpreter},
+    loader::Loader,
+    parser::Parser,
+    typeck::TypeChecker,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+// a function the open file can see, from the file itself or anything it imports
+#[derive(Debug, Clone)]
+struct Definition {
+    name: String,
+    params: Vec<Param>,
+    external: bool,
+    location: Location,
+    signature: Option<Signature>,
+}
+
+impl Definition {
+    // what it would look like written out with every type inferred,
+    // `let fib(n: Int): Int`
+    fn header(&self) -> String {
+        let keyword = if self.external { "extern" } else { "let" };
+        let params: Vec<String> = match &self.signature {
+            Some(signature) => self
+                .params
+                .iter()
+                .zip(&signature.args)
+                .map(|(param, ty)| format!("{}: {}", param.name, ty))
+                .collect(),
+            None => self.params.iter().map(|p| p.name.clone()).collect(),
+        };
+        let ret = self
+            .signature
+            .as_ref()
+            .map_or(String::new(), |s| format!(": {}", s.ret));
+        format!("{} {}({}){}", keyword, self.name, params.join(", "), ret)
+    }
+}
+
+struct Document {
+    // what locations in this file are filed under
+    path: String,
+    text: String,
+    // from the last version that parsed, so half a line being typed doesn't lose them all
+    definitions: Vec<Definition>,
+}
+
+// talks json-rpc to an editor: diagnostics as files change, go-to-definition,
+// hover and completion for functions; every file is reparsed and rechecked on
+// every change, they're small enough
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutting_down: bool,
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+// reads messages from `input` until the editor says to stop, the exit code is
+// whether it asked to shut down first (like the spec wants)
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> ExitCode {
+    let mut server = Server::new();
+    loop {
+        let body = match read_message(&mut input) {
+            Ok(Some(body)) => body,
+            Ok(None) | Err(_) => return ExitCode::FAILURE,
+        };
+        let replies = match Json::parse(&body) {
+            Some(message) if message.get("method").and_then(Json::as_str) == Some("exit") => {
+                return match server.shutting_down {
+                    true => ExitCode::SUCCESS,
+                    false => ExitCode::FAILURE,
+                };
+            }
+            Some(message) => server.handle(&message),
+            None => vec![Server::error(
+                &Json::Null,
+                -32700,
+                "couldn't parse that as json",
+            )],
+        };
+        for reply in replies {
+            if write_message(&mut output, &reply).is_err() {
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+}
+
+// `Content-Length: n`, a blank line, then n bytes of json; `None` once the input's closed
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no Content-Length",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            documents: HashMap::new(),
+            shutting_down: false,
+        }
+    }
+
+    // everything to send back, the response (if it was a request) and any notifications
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let id = match message.get("id") {
+            Some(id) => id,
+            // notifications never get a response
+            None => return self.notify(method, params),
+        };
+
+        let result = match method {
+            "initialize" => Server::capabilities(),
+            "shutdown" => {
+                self.shutting_down = true;
+                Json::Null
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            _ => {
+                let message = format!("'{}' isn't supported", method);
+                return vec![Server::error(id, -32601, &message)];
+            }
+        };
+        vec![Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id.clone()),
+            ("result", result),
+        ])]
+    }
+
+    fn error(id: &Json, code: i64, message: &str) -> Json {
+        Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id.clone()),
+            (
+                "error",
+                Json::object(vec![("code", Json::Int(code)), ("message", message.into())]),
+            ),
+        ])
+    }
+
+    fn capabilities() -> Json {
+        Json::object(vec![
+            (
+                "capabilities",
+                Json::object(vec![
+                    // the whole file comes with every change
+                    ("textDocumentSync", Json::Int(1)),
+                    ("definitionProvider", true.into()),
+                    ("hoverProvider", true.into()),
+                    ("completionProvider", Json::object(vec![])),
+                ]),
+            ),
+            (
+                "serverInfo",
+                Json::object(vec![
+                    ("name", "fx-lsp".into()),
+                    ("version", env!("CARGO_PKG_VERSION").into()),
+                ]),
+            ),
+        ])
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|doc| doc.get("uri"))
+            .and_then(Json::as_str)
+            .unwrap_or("")
+            .to_string();
+        let text = match method {
+            "textDocument/didOpen" => params.get("textDocument").and_then(|doc| doc.get("text")),
+            // only full syncs are asked for, so the last change is the whole file
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(|changes| changes.last())
+                .and_then(|change| change.get("text")),
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![Server::diagnostics(&uri, vec![])];
+            }
+            _ => return vec![],
+        };
+        let Some(text) = text.and_then(Json::as_str) else {
+            return vec![];
+        };
+
+        let path = uri_to_path(&uri);
+        let (diagnostics, definitions) = analyze(&path, text);
+        let previous = self.documents.remove(&uri).map(|doc| doc.definitions);
+        let document = Document {
+            path,
+            text: text.to_string(),
+            definitions: definitions.or(previous).unwrap_or_default(),
+        };
+
+        let diagnostics = diagnostics
+            .iter()
+            .filter_map(|diagnostic| document.diagnostic(diagnostic))
+            .collect();
+        self.documents.insert(uri.clone(), document);
+        vec![Server::diagnostics(&uri, diagnostics)]
+    }
+
+    fn diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+        Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                Json::object(vec![
+                    ("uri", uri.into()),
+                    ("diagnostics", Json::Array(diagnostics)),
+                ]),
+            ),
+        ])
+    }
+
+    // the open document and the definition of whatever name is under the cursor
+    fn lookup(&self, params: &Json) -> Option<(&Document, &Definition)> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let document = self.documents.get(uri)?;
+        let position = params.get("position")?;
+        let line = position.get("line")?.as_int()? as usize;
+        let character = position.get("character")?.as_int()? as usize;
+
+        let word = word_at(&document.text, line, character)?;
+        let definition = document.definitions.iter().find(|d| d.name == word)?;
+        Some((document, definition))
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        let Some((document, definition)) = self.lookup(params) else {
+            return Json::Null;
+        };
+        let file = definition.location.get_filename();
+        let text = match file == document.path {
+            true => Some(document.text.clone()),
+            false => fs::read_to_string(&file).ok(),
+        };
+        Json::object(vec![
+            ("uri", path_to_uri(&file).into()),
+            ("range", range(text.as_deref(), &definition.location)),
+        ])
+    }
+
+    fn hover(&self, params: &Json) -> Json {
+        let Some((_, definition)) = self.lookup(params) else {
+            return Json::Null;
+        };
+        let value = format!("```fx\n{}\n```", definition.header());
+        Json::object(vec![(
+            "contents",
+            Json::object(vec![("kind", "markdown".into()), ("value", value.into())]),
+        )])
+    }
+
+    fn completion(&self, params: &Json) -> Json {
+        let uri = params
+            .get("textDocument")
+            .and_then(|doc| doc.get("uri"))
+            .and_then(Json::as_str)
+            .unwrap_or("");
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Array(vec![]);
+        };
+        let items = document
+            .definitions
+            .iter()
+            .map(|definition| {
+                Json::object(vec![
+                    ("label", definition.name.as_str().into()),
+                    // `Function`
+                    ("kind", Json::Int(3)),
+                    ("detail", definition.header().into()),
+                ])
+            })
+            .collect();
+        Json::Array(items)
+    }
+}
+
+impl Document {
+    // errors in other files are left for when those files get opened,
+    // except for the import that pulled them in (which `analyze` reports)
+    fn diagnostic(&self, diagnostic: &Diagnostic) -> Option<Json> {
+        let location = diagnostic.get_location();
+        if location
+            .as_ref()
+            .is_some_and(|l| l.get_filename() != self.path)
+        {
+            return None;
+        }
+        let span = match &location {
+            Some(location) => range(Some(&self.text), location),
+            None => range(None, &Location::new(0, 0, self.path.clone())),
+        };
+
+        let mut message = diagnostic.get_message();
+        for note in diagnostic.get_notes() {
+            message = format!("{}\n{}", message, note);
+        }
+        let related = diagnostic
+            .get_labels()
+            .iter()
+            .map(|(location, label)| {
+                let file = location.get_filename();
+                let text = match file == self.path {
+                    true => Some(self.text.clone()),
+                    false => fs::read_to_string(&file).ok(),
+                };
+                Json::object(vec![
+                    (
+                        "location",
+                        Json::object(vec![
+                            ("uri", path_to_uri(&file).into()),
+                            ("range", range(text.as_deref(), location)),
+                        ]),
+                    ),
+                    ("message", label.as_str().into()),
+                ])
+            })
+            .collect();
+        Some(Json::object(vec![
+            ("range", span),
+            // `Error`, there aren't any warnings yet
+            ("severity", Json::Int(1)),
+            ("source", "fx".into()),
+            ("message", message.into()),
+            ("relatedInformation", Json::Array(related)),
+        ]))
+    }
+}
+
+// lexes, parses and checks `text` as if it were saved at `path`; imports are read
+// from disk. the definitions are `None` if the file itself didn't parse
+fn analyze(path: &str, text: &str) -> (Vec<Diagnostic>, Option<Vec<Definition>>) {
+    let lexer = Interpreter::from_str(text, path.to_string()).with_columns(Columns::Utf16);
+    let mut parser = match Parser::from_lexer(lexer) {
+        Ok(parser) => parser,
+        Err(e) => return (vec![Diagnostic::from(e)], None),
+    };
+    let own = match parser.run() {
+        Ok(tree) => tree,
+        Err(errors) => return (errors, None),
+    };
+
+    let mut diagnostics = vec![];
+    let mut tree = vec![];
+    let mut loader = Loader::new();
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    for (import, location) in parser.imports() {
+        match loader.load(&directory.join(import).to_string_lossy()) {
+            Ok(imported) => tree.extend(imported),
+            Err(errors) => {
+                let note = format!("'{}': {}", import, errors[0].get_message());
+                diagnostics
+                    .push(ImportError::diagnostic(location.clone(), "parse").with_note(&note));
+            }
+        }
+    }
+    tree.extend(own);
+
+    let mut checker = TypeChecker::new();
+    if let Err(mut errors) = checker.check(tree.clone()) {
+        diagnostics.append(&mut errors);
+    }
+    let definitions = tree
+        .iter()
+        .filter_map(|def| {
+            let external = matches!(def.get_expr(), Expr::ExternDeclaration(..));
+            match def.get_expr() {
+                Expr::FunctionDefinition(name, params, ..)
+                | Expr::ExternDeclaration(name, params, _) => Some(Definition {
+                    signature: checker.signature(&name),
+                    name,
+                    params,
+                    external,
+                    location: def.get_location(),
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+    (diagnostics, Some(definitions))
+}
+
+// lsp positions are a line and how many utf-16 units into it; open documents are
+// lexed with utf-16 columns, so without the text only the end has to be guessed
+fn range(text: Option<&str>, location: &Location) -> Json {
+    let position = |offset: usize| {
+        let (line, character) = match text {
+            Some(text) => {
+                let before = text.get(..offset.min(text.len())).unwrap_or(text);
+                let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                (
+                    before.matches('\n').count(),
+                    before[line_start..].encode_utf16().count(),
+                )
+            }
+            None => (
+                location.get_line(),
+                location.get_column() + offset - location.get_start(),
+            ),
+        };
+        Json::object(vec![("line", line.into()), ("character", character.into())])
+    };
+    Json::object(vec![
+        ("start", position(location.get_start())),
+        (
+            "end",
+            position(location.get_end().max(location.get_start())),
+        ),
+    ])
+}
+
+// the name `character` is in (or just after, like at the end of a word being typed)
+fn word_at(text: &str, line: usize, character: usize) -> Option<String> {
+    let line = text.lines().nth(line)?;
+    let chars: Vec<char> = line.chars().collect();
+    // `character` counts utf-16 units, so turn it back into an index into `chars`
+    let mut units = 0;
+    let mut index = chars.len();
+    for (i, c) in chars.iter().enumerate() {
+        if units >= character {
+            index = i;
+            break;
+        }
+        units += c.len_utf16();
+    }
+
+    let is_name = |c: &char| c.is_alphanumeric() || matches!(c, '_' | '$');
+    let start = chars[..index]
+        .iter()
+        .rposition(|c| !is_name(c))
+        .map_or(0, |i| i + 1);
+    let end = chars[index..]
+        .iter()
+        .position(|c| !is_name(c))
+        .map_or(chars.len(), |i| index + i);
+    (start < end).then(|| chars[start..end].iter().collect())
+}
+
+fn uri_to_path(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    // %xx escapes, for spaces and the like
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+fn path_to_uri(path: &str) -> String {
+    let absolute =
+        fs::canonicalize(path).map_or(path.to_string(), |p| p.to_string_lossy().to_string());
+    let mut uri = "file://".to_string();
+    for byte in absolute.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words() {
+        let text = "let fib(n) =\n    fib(n - 1) + 1\n";
+        assert_eq!(word_at(text, 0, 5).as_deref(), Some("fib"));
+        assert_eq!(word_at(text, 1, 4).as_deref(), Some("fib"));
+        // the end of a word still counts
+        assert_eq!(word_at(text, 1, 7).as_deref(), Some("fib"));
+        assert_eq!(word_at(text, 1, 15), None);
+        assert_eq!(word_at(text, 5, 0), None);
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(uri_to_path("file:///tmp/my%20file.txt"), "/tmp/my file.txt");
+        assert_eq!(path_to_uri("/tmp/my file.txt"), "file:///tmp/my%20file.txt");
+    }
+}
@@ -1,34 +1,386 @@
-use crate::errors::{BadArgumentError, MissingOutputFileError, Result};
-use std::ffi::{c_char, CStr};
+use crate::{
+    ast::convert_str,
+    dump::DumpFormat,
+    errors::{BadArgumentError, Diagnostic, MissingInputFileError, Result},
+};
+use std::{
+    ffi::{c_char, CStr, CString},
+    path::Path,
+    ptr::null_mut,
+};
 
-#[derive(Debug)]
+pub const USAGE: &str = "usage: fx <file> [options]
+       fx fmt <file> [-o <file>]
+       fx repl [-I <dir>]
+
+options:
+  -o <file>                 where to write the output (defaults to <file> with a new extension,
+                            or <file> itself for `fmt`; `-` is stdout)
+  --emit=<kind>             tokens, ast, typed-ast, llvm-ir, bc (default) or obj
+  -O0, -O1, -O2, -O3        optimization level (default -O2)
+  --target=<triple>         target to compile for (defaults to the host)
+  --cpu=<name>              cpu to compile for (default generic)
+  --entry <name>            function the program starts at, exported as `entry`
+  -I <dir>                  extra directory to look for imports in
+  --dump-format=<format>    how tokens and asts get printed, text (default) or json
+  --error-format=<format>   human (default) or json
+  -h, --help                print this and exit
+  -V, --version             print the version and exit
+";
+
+// how far through the pipeline to go, everything up to `TypedAst` is done on the rust side
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    TypedAst,
+    LlvmIr,
+    Bc,
+    Obj,
+}
+
+impl Emit {
+    fn extension(&self) -> &str {
+        match self {
+            Emit::Tokens => "tokens",
+            Emit::Ast | Emit::TypedAst => "ast",
+            Emit::LlvmIr => "ll",
+            Emit::Bc => "bc",
+            Emit::Obj => "o",
+        }
+    }
+
+    // these never reach codegen
+    pub fn is_frontend(&self) -> bool {
+        matches!(self, Emit::Tokens | Emit::Ast | Emit::TypedAst)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl ErrorFormat {
+    // `source_of` is what `Diagnostic::render` quotes the snippets from
+    pub fn render(
+        &self,
+        diagnostic: &Diagnostic,
+        source_of: &dyn Fn(&str) -> Option<String>,
+    ) -> String {
+        match self {
+            ErrorFormat::Human => diagnostic.render(source_of),
+            ErrorFormat::Json => format!("{}\n", diagnostic.to_json()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Compile,
+    // `fx fmt`, which rewrites the file in place unless there's a `-o`
+    Format,
+    // `fx repl`, definitions and expressions typed in and run by the evaluator
+    Repl,
+    Help,
+    Version,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Options {
+    pub action: Action,
     pub filename: String,
     pub outfile: String,
+    pub emit: Emit,
+    pub opt_level: u8,
+    // `None` leaves it up to llvm, which picks the host
+    pub target: Option<String>,
+    pub cpu: String,
+    // `None` means there's nothing to export, like when building a library
+    pub entry: Option<String>,
+    pub include_paths: Vec<String>,
+    pub dump_format: DumpFormat,
+    pub error_format: ErrorFormat,
     pub original_args: Vec<String>,
 }
 
 impl Options {
-    pub fn new(argv_ptr: *mut *mut c_char, size: usize) -> Result<Options> {
+    /// # Safety
+    ///
+    /// `argv_ptr` must point to `size` valid, nul-terminated strings (i.e. `argv` from `main`).
+    pub unsafe fn new(argv_ptr: *mut *mut c_char, size: usize) -> Result<Options> {
         let mut argv: Vec<String> = vec![];
-        unsafe {
-            let slice = std::slice::from_raw_parts(argv_ptr, size);
-            for string in slice {
-                match CStr::from_ptr(string.clone()).to_str() {
-                    Ok(s) => argv.push(s.to_string()),
-                    Err(_) => return BadArgumentError::while_initializing(),
+        let slice = std::slice::from_raw_parts(argv_ptr, size);
+        for string in slice {
+            match CStr::from_ptr(*string).to_str() {
+                Ok(s) => argv.push(s.to_string()),
+                Err(_) => return BadArgumentError::while_initializing(),
+            }
+        }
+        Options::parse(argv)
+    }
+
+    // `argv[0]` is the program name, like it is in `main`
+    pub fn parse(argv: Vec<String>) -> Result<Options> {
+        let mut action = Action::Compile;
+        let mut positional = vec![];
+        let mut outfile = None;
+        let mut emit = Emit::Bc;
+        let mut opt_level = 2;
+        let mut target = None;
+        let mut cpu = "generic".to_string();
+        let mut entry = None;
+        let mut include_paths = vec![];
+        let mut dump_format = DumpFormat::Text;
+        let mut error_format = ErrorFormat::Human;
+
+        let mut args = argv.iter().skip(1).peekable();
+        match args.peek().map(|arg| arg.as_str()) {
+            Some("fmt") => action = Action::Format,
+            Some("repl") => action = Action::Repl,
+            _ => {}
+        }
+        if action != Action::Compile {
+            args.next();
+        }
+        while let Some(arg) = args.next() {
+            // `--flag=value` and `--flag value` both work, so does `-Idir`
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+                _ if arg.starts_with("-I") && arg.len() > 2 => ("-I", Some(arg[2..].to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || match inline.clone().or_else(|| args.next().cloned()) {
+                Some(value) => Ok(value),
+                None => Options::bad_argument(&format!("'{}' needs a value", flag)),
+            };
+
+            match flag {
+                "-h" | "--help" => action = Action::Help,
+                "-V" | "--version" => action = Action::Version,
+                "-o" => outfile = Some(value()?),
+                "--emit" => {
+                    emit = match value()?.as_str() {
+                        "tokens" => Emit::Tokens,
+                        "ast" => Emit::Ast,
+                        "typed-ast" => Emit::TypedAst,
+                        "llvm-ir" => Emit::LlvmIr,
+                        "bc" => Emit::Bc,
+                        "obj" => Emit::Obj,
+                        other => {
+                            let note = format!(
+                                "can't emit '{}', try tokens, ast, typed-ast, llvm-ir, bc or obj",
+                                other
+                            );
+                            return Options::bad_argument(&note);
+                        }
+                    }
+                }
+                "-O0" | "-O1" | "-O2" | "-O3" => opt_level = flag.as_bytes()[2] - b'0',
+                "--target" => target = Some(value()?),
+                "--cpu" => cpu = value()?,
+                "--entry" => entry = Some(value()?),
+                "-I" => include_paths.push(value()?),
+                "--dump-format" => {
+                    dump_format = match value()?.as_str() {
+                        "text" => DumpFormat::Text,
+                        "json" => DumpFormat::Json,
+                        other => {
+                            let note = format!("unknown dump format '{}', try text or json", other);
+                            return Options::bad_argument(&note);
+                        }
+                    }
                 }
+                "--error-format" => {
+                    error_format = match value()?.as_str() {
+                        "human" => ErrorFormat::Human,
+                        "json" => ErrorFormat::Json,
+                        other => {
+                            let note =
+                                format!("unknown error format '{}', try human or json", other);
+                            return Options::bad_argument(&note);
+                        }
+                    }
+                }
+                _ if flag.starts_with('-') && flag.len() > 1 => {
+                    return Options::bad_argument(&format!("unknown option '{}'", arg));
+                }
+                _ => positional.push(arg.clone()),
+            }
+        }
+
+        // `fx in.txt out.bc` still works, the second file is just `-o`
+        let (filename, legacy_outfile) = match positional.as_slice() {
+            [] if action != Action::Compile && action != Action::Format => (String::new(), None),
+            [] => return MissingInputFileError::while_initializing(),
+            [filename] => (filename.clone(), None),
+            [filename, outfile] => (filename.clone(), Some(outfile.clone())),
+            [_, _, extra, ..] => {
+                return Options::bad_argument(&format!("unexpected argument '{}'", extra));
             }
+        };
+        if outfile.is_some() && legacy_outfile.is_some() {
+            return Options::bad_argument("the output file was given twice");
+        }
+        let outfile = outfile.or(legacy_outfile).unwrap_or_else(|| match action {
+            Action::Format => filename.clone(),
+            _ => Path::new(&filename)
+                .with_extension(emit.extension())
+                .to_string_lossy()
+                .to_string(),
+        });
+
+        Ok(Options {
+            action,
+            filename,
+            outfile,
+            emit,
+            opt_level,
+            target,
+            cpu,
+            entry,
+            include_paths,
+            dump_format,
+            error_format,
+            original_args: argv,
+        })
+    }
+
+    fn bad_argument<T>(note: &str) -> Result<T> {
+        Err(BadArgumentError::unlocated("init").with_note(note).into())
+    }
+}
+
+// the part of `Options` codegen cares about, strings that weren't given are null
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFISafeOptions {
+    pub outfile: *mut c_char,
+    pub emit: Emit,
+    pub opt_level: u8,
+    pub target: *mut c_char,
+    pub cpu: *mut c_char,
+    pub entry: *mut c_char,
+}
+
+// for when the arguments couldn't even be read
+impl Default for FFISafeOptions {
+    fn default() -> FFISafeOptions {
+        FFISafeOptions {
+            outfile: null_mut(),
+            emit: Emit::Bc,
+            opt_level: 2,
+            target: null_mut(),
+            cpu: null_mut(),
+            entry: null_mut(),
+        }
+    }
+}
+
+impl From<&Options> for FFISafeOptions {
+    fn from(options: &Options) -> FFISafeOptions {
+        let optional = |s: &Option<String>| s.clone().map_or(null_mut(), convert_str);
+        FFISafeOptions {
+            outfile: convert_str(options.outfile.clone()),
+            emit: options.emit,
+            opt_level: options.opt_level,
+            target: optional(&options.target),
+            cpu: convert_str(options.cpu.clone()),
+            entry: optional(&options.entry),
         }
+    }
+}
 
-        if argv.len() > 2 {
-            Ok(Options {
-                filename: argv[1].clone(),
-                outfile: argv[2].clone(),
-                original_args: argv,
-            })
-        } else {
-            MissingOutputFileError::while_initializing()
+/// # Safety
+///
+/// `options` must have come from `recieve_tokens` and not been dropped already.
+#[no_mangle]
+pub unsafe extern "C" fn drop_options(options: FFISafeOptions) {
+    for s in [options.outfile, options.target, options.cpu, options.entry] {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options> {
+        Options::parse(args.split_whitespace().map(String::from).collect())
+    }
+
+    fn note(args: &str) -> String {
+        let error = parse(args).unwrap_err();
+        Diagnostic::from(error).to_json().to_string()
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse("fx script.txt").unwrap();
+        assert_eq!(options.action, Action::Compile);
+        assert_eq!(options.outfile, "script.bc");
+        assert_eq!(options.emit, Emit::Bc);
+        assert_eq!(options.opt_level, 2);
+        assert_eq!(options.cpu, "generic");
+        assert_eq!((options.target, options.entry), (None, None));
+        assert_eq!(options.dump_format, DumpFormat::Text);
+        assert_eq!(options.error_format, ErrorFormat::Human);
+
+        // the old `fx <in> <out>` form
+        assert_eq!(parse("fx a.txt b.bc").unwrap().outfile, "b.bc");
+    }
+
+    #[test]
+    fn flags() {
+        let options = parse(
+            "fx -O0 --emit=obj src/a.txt --target x86_64-unknown-linux-gnu --cpu=znver3 \
+             --entry main -I lib -Istd --error-format=json --dump-format json",
+        )
+        .unwrap();
+        assert_eq!(options.filename, "src/a.txt");
+        assert_eq!(options.outfile, "src/a.o");
+        assert_eq!(options.emit, Emit::Obj);
+        assert_eq!(options.opt_level, 0);
+        assert_eq!(options.target.as_deref(), Some("x86_64-unknown-linux-gnu"));
+        assert_eq!(options.cpu, "znver3");
+        assert_eq!(options.entry.as_deref(), Some("main"));
+        assert_eq!(options.include_paths, vec!["lib", "std"]);
+        assert_eq!(options.dump_format, DumpFormat::Json);
+        assert_eq!(options.error_format, ErrorFormat::Json);
+
+        assert_eq!(parse("fx --help").unwrap().action, Action::Help);
+        assert_eq!(parse("fx -V").unwrap().action, Action::Version);
+
+        let format = parse("fx fmt a.txt").unwrap();
+        assert_eq!(
+            (format.action, format.outfile),
+            (Action::Format, "a.txt".to_string())
+        );
+        assert_eq!(parse("fx fmt a.txt -o -").unwrap().outfile, "-");
+        let repl = parse("fx repl -I lib").unwrap();
+        assert_eq!(
+            (repl.action, repl.include_paths),
+            (Action::Repl, vec!["lib".to_string()])
+        );
+        assert_eq!(
+            parse("fx a.txt --emit llvm-ir -o out.ll").unwrap().outfile,
+            "out.ll"
+        );
+    }
+
+    #[test]
+    fn bad_flags() {
+        assert!(note("fx a.txt --emit=exe").contains("can't emit 'exe'"));
+        assert!(note("fx a.txt --frobnicate").contains("unknown option '--frobnicate'"));
+        assert!(note("fx a.txt -o").contains("'-o' needs a value"));
+        assert!(note("fx a.txt b.bc -o c.bc").contains("given twice"));
+        assert!(note("fx a.txt b.bc c.bc").contains("unexpected argument 'c.bc'"));
+        assert!(note("fx a.txt --dump-format=yaml").contains("unknown dump format 'yaml'"));
+        assert!(note("fx -O2").contains("no input file supplied!"));
+        assert!(note("fx fmt").contains("no input file supplied!"));
+    }
+}
@@ -1,17 +1,19 @@
 use crate::{ast::*, errors::*, lexer::*};
-use std::{collections::HashMap, error::Error, fs::File, io::BufReader};
+use std::{fs::File, io::BufReader};
 
 pub struct Parser {
     tokens: Vec<LocatedToken>,
     index: usize,
-    stack: Vec<Box<dyn Error>>,
-    registry: HashMap<String, ReturnType>,
+    stack: Vec<Diagnostic>,
+    // every `import "..."` in the file, left for the `Loader` to follow
+    imports: Vec<(String, Location)>,
+    // every `# ...` comment in the file, which only the formatter cares about
+    comments: Vec<(String, Location)>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    const BASIC_OP_COUNT: u8 = 10;
     const TEST_INPUT: &str = "src/tests/";
 
     fn path(add: &str) -> String {
@@ -38,50 +40,125 @@ mod tests {
     }
 
     #[test]
-    fn basic_op_prec() {
-        let has_op_prec = [
-            Symbol::ToPower,
-            Symbol::Multiply,
-            Symbol::Divide,
-            Symbol::Remainder,
-            Symbol::Add,
-            Symbol::Subtract,
-            Symbol::LessThan,
-            Symbol::GreaterThan,
-            Symbol::Comma,
-            Symbol::Equals,
+    fn binary_operator() {
+        let basic = [
+            (Symbol::ToPower, Operator::Power),
+            (Symbol::Multiply, Operator::Multiply),
+            (Symbol::Divide, Operator::Divide),
+            (Symbol::Remainder, Operator::Remainder),
+            (Symbol::Add, Operator::Add),
+            (Symbol::Subtract, Operator::Subtract),
+            (Symbol::LessThan, Operator::Less),
+            (Symbol::GreaterThan, Operator::Greater),
+        ];
+        for (symbol, op) in basic {
+            assert_eq!(Parser::binary_operator(&symbol), Some(op));
+        }
+
+        let compounds = [
+            (Symbol::LessThan, Symbol::Equals, Operator::LessEqual),
+            (Symbol::GreaterThan, Symbol::Equals, Operator::GreaterEqual),
+            (Symbol::Equals, Symbol::Equals, Operator::Equal),
+            (Symbol::Negate, Symbol::Equals, Operator::NotEqual),
+            (Symbol::Ampersand, Symbol::Ampersand, Operator::And),
+            (Symbol::Pipe, Symbol::Pipe, Operator::Or),
         ];
-        for (i, symbol) in has_op_prec.into_iter().enumerate() {
-            assert_eq!(Parser::basic_op_prec(symbol).unwrap(), i as u8);
+        for (first, second, op) in compounds {
+            let symbol = Symbol::Compound(first.into(), second.into());
+            assert_eq!(Parser::binary_operator(&symbol), Some(op));
         }
 
-        let no_op_prec = [
+        let not_binary = [
             Symbol::Ampersand,
             Symbol::Colon,
+            Symbol::Comma,
+            Symbol::Equals,
             Symbol::Pipe,
             Symbol::Negate,
             Symbol::Dot,
         ];
-        for symbol in no_op_prec {
-            assert!(Parser::basic_op_prec(symbol).is_none());
+        for symbol in not_binary {
+            assert!(Parser::binary_operator(&symbol).is_none());
+        }
+    }
+
+    // every operation in parentheses, to see how things were grouped
+    fn grouped(expr: &LocatedExpr) -> String {
+        match expr.get_expr() {
+            Expr::BinaryOperation(op, left, right) => {
+                format!("({} {} {})", grouped(&left), op.symbol(), grouped(&right))
+            }
+            Expr::UnaryOperation(op, operand) => format!("({}{})", op.symbol(), grouped(&operand)),
+            Expr::VariableRef(name) => name,
+            Expr::NumberLiteral(_, i, _) => i.to_string(),
+            Expr::Lambda(params, body, ..) => {
+                let params: Vec<String> = params.into_iter().map(|p| p.name).collect();
+                format!("(\\{} -> {})", params.join(", "), grouped(&body))
+            }
+            Expr::FunctionCall(name, args) => {
+                let args: Vec<String> = args.iter().map(grouped).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            Expr::Apply(function, args, _) => {
+                let args: Vec<String> = args.iter().map(grouped).collect();
+                format!("{}({})", grouped(&function), args.join(", "))
+            }
+            other => panic!("unexpected {:?}", other),
         }
     }
 
     #[test]
-    fn compound_op_prec() {
-        let compounds = [
-            (Symbol::LessThan, Symbol::Equals),
-            (Symbol::GreaterThan, Symbol::Equals),
-            (Symbol::Equals, Symbol::Equals),
-            (Symbol::Negate, Symbol::Equals),
-            (Symbol::Ampersand, Symbol::Ampersand),
-            (Symbol::Pipe, Symbol::Pipe),
+    fn precedence() {
+        let cases = [
+            ("a - b * c - d", "((a - (b * c)) - d)"),
+            ("1 - 2 + 3", "((1 - 2) + 3)"),
+            ("a / b % c * d", "(((a / b) % c) * d)"),
+            ("2 ^ 3 ^ 2", "(2 ^ (3 ^ 2))"),
+            ("a * b ^ c ^ d * e", "((a * (b ^ (c ^ d))) * e)"),
+            ("a + b < c * d", "((a + b) < (c * d))"),
+            ("a < b == c >= d", "((a < b) == (c >= d))"),
+            ("a || b && c || d", "((a || (b && c)) || d)"),
+            ("a == b && c != d", "((a == b) && (c != d))"),
         ];
-        for (i, symbol) in compounds.into_iter().enumerate() {
-            assert_eq!(
-                Parser::compound_op_prec(symbol).unwrap(),
-                i as u8 + BASIC_OP_COUNT
-            );
+        for (source, expected) in cases {
+            let mut parser = Parser::from_source("<test>", source).unwrap();
+            let expr = parser.expression().unwrap();
+            assert_eq!(grouped(&expr), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn unary() {
+        let cases = [
+            ("-2 ^ 2", "(-(2 ^ 2))"),
+            ("2 ^ -a", "(2 ^ (-a))"),
+            ("-a * b", "((-a) * b)"),
+            ("a*-b", "(a * (-b))"),
+            ("a - -b", "(a - (-b))"),
+            ("!a && !!b", "((!a) && (!(!b)))"),
+            ("+a < -1", "((+a) < -1)"),
+            ("- -1", "1"),
+        ];
+        for (source, expected) in cases {
+            let mut parser = Parser::from_source("<test>", source).unwrap();
+            let expr = parser.expression().unwrap();
+            assert_eq!(grouped(&expr), expected, "{}", source);
+        }
+
+        // a minus in front of a number is part of the literal
+        let mut parser = Parser::from_source("<test>", "f(-1.5)").unwrap();
+        let Expr::FunctionCall(_, args) = parser.expression().unwrap().get_expr() else {
+            panic!("expected a call");
+        };
+        assert!(matches!(args[0].get_expr(), Expr::NumberLiteral(true, _, f) if f == -1.5));
+        assert_eq!(args[0].get_location().get_column(), 2);
+    }
+
+    #[test]
+    fn unbalanced_operators() {
+        for source in ["a +", "a + * b", "a b"] {
+            let mut parser = Parser::from_source("<test>", source).unwrap();
+            assert!(parser.expression().is_err(), "{}", source);
         }
     }
 
@@ -89,10 +166,14 @@ mod tests {
     fn next_token() {
         // stupid
         let mut parser = parser_from("basic").unwrap();
+        // unlike `look_ahead`, the newlines come back too
         let expected = [
             Token::Let,
+            Token::Newline,
             Token::Identifier("age".to_string()),
+            Token::Newline,
             Token::Symbol(Symbol::Equals),
+            Token::Newline,
             Token::Number(None, Some(17.0)),
         ];
         for token in expected {
@@ -101,6 +182,24 @@ mod tests {
         assert!(parser.next_token().is_err());
     }
 
+    #[test]
+    fn spans() {
+        let mut parser = parser_from("basic").unwrap();
+        let expected = [(0, 0, 0, 3), (1, 4, 9, 12), (2, 8, 22, 23), (4, 8, 42, 46)];
+        for (line, column, start, end) in expected {
+            let location = parser.look_ahead().unwrap().loc();
+            assert_eq!(
+                (
+                    location.get_line(),
+                    location.get_column(),
+                    location.get_start(),
+                    location.get_end()
+                ),
+                (line, column, start, end)
+            );
+        }
+    }
+
     #[test]
     fn expect_identifier() {
         let mut parser = parser_from("basic").unwrap();
@@ -165,6 +264,448 @@ mod tests {
         assert_eq!(parser.last().unwrap().tok(), Token::Extern);
     }
 
+    #[test]
+    fn run() {
+        let tree = parser_from("fib").unwrap().run().unwrap();
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn run_collects_errors() {
+        let errors = parser_from("errors").unwrap().run().unwrap_err();
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|e| e.get_location().unwrap().get_line())
+            .collect();
+        assert_eq!(lines, vec![0, 4]);
+    }
+
+    #[test]
+    fn from_source() {
+        let source = "# one\nlet one() = 1\n\nlet two() = one() + 1\n";
+        let mut parser = Parser::from_source("memory", source).unwrap();
+        let tree = parser.run().unwrap();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[1].get_location().get_filename(), "memory");
+        assert_eq!(parser.comments().len(), 1);
+        assert!(Parser::from_source("memory", "let big() = 99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn unicode() {
+        let source = "let größe(ñ) = ñ * 2\n\nlet π() = \"héllo wörld\"\n";
+        let tree = Parser::from_source("memory", source)
+            .unwrap()
+            .run()
+            .unwrap();
+        let Expr::FunctionDefinition(name, params, body, ..) = tree[0].get_expr() else {
+            panic!("expected a definition");
+        };
+        assert_eq!((name.as_str(), params[0].name.as_str()), ("größe", "ñ"));
+        // columns count characters, the offsets are still bytes
+        let times = body.get_location();
+        assert_eq!((times.get_column(), times.get_start()), (17, 21));
+        assert!(matches!(
+            tree[1].get_expr(),
+            Expr::FunctionDefinition(name, _, body, ..)
+                if name == "π" && matches!(body.get_expr(), Expr::StringLiteral(s) if s == "héllo wörld")
+        ));
+
+        // `𝑥` is two utf-16 units
+        let tokens = Interpreter::from_str("let 𝑥() = 1", "memory".to_string())
+            .with_columns(Columns::Utf16)
+            .pull()
+            .unwrap();
+        let columns: Vec<usize> = tokens.iter().map(|t| t.loc().get_column()).collect();
+        assert_eq!(columns, vec![0, 4, 6, 9, 11]);
+
+        let bytes: &[u8] = b"let a() = 1\nlet \xffb() = 2\n";
+        let error = Interpreter::from_reader(bytes, "memory".to_string())
+            .unwrap()
+            .pull()
+            .unwrap_err();
+        let error = Diagnostic::from(error);
+        assert_eq!(error.get_message(), "invalid utf-8!");
+        let location = error.get_location().unwrap();
+        assert_eq!((location.get_line(), location.get_column()), (1, 4));
+    }
+
+    #[test]
+    fn strings() {
+        let values = |source: &str| -> Vec<String> {
+            Interpreter::from_str(source, "memory".to_string())
+                .pull()
+                .unwrap()
+                .into_iter()
+                .filter_map(|token| match token.tok() {
+                    Token::String(value) => Some(value),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(
+            values(r#"let s() = "say \"hi\"\n\tand \\ \u{e9}\u{1F600}""#),
+            vec!["say \"hi\"\n\tand \\ é😀"]
+        );
+        assert_eq!(
+            values("let s() = r\"C:\\no\\escapes\" + r#\"has \"quotes\"\"#"),
+            vec!["C:\\no\\escapes", "has \"quotes\""]
+        );
+        assert_eq!(values("let s() = \"two\nlines\""), vec!["two\nlines"]);
+        // `r` on its own is still a name
+        assert_eq!(
+            values("let r(r) = r # r\"not a string\""),
+            Vec::<String>::new()
+        );
+
+        let error = |source: &str| {
+            let error = Interpreter::from_str(source, "memory".to_string())
+                .pull()
+                .unwrap_err();
+            let error = Diagnostic::from(error);
+            let location = error.get_location().unwrap();
+            (
+                error.get_message(),
+                location.get_column(),
+                location.get_end() - location.get_start(),
+            )
+        };
+        let unterminated = "unterminated string literal!".to_string();
+        assert_eq!(error("let s() = \"oops\n\n"), (unterminated.clone(), 10, 1));
+        assert_eq!(
+            error("let s() = r#\"oops\"\n"),
+            (unterminated.clone(), 10, 3)
+        );
+        assert_eq!(error("let s() = \"oops\\"), (unterminated, 10, 1));
+        let invalid = "invalid escape sequence!".to_string();
+        assert_eq!(error(r#"let s() = "a\qb""#), (invalid.clone(), 12, 2));
+        assert_eq!(
+            error(r#"let s() = "\u{110000}""#),
+            (invalid.clone(), 11, 10)
+        );
+        assert_eq!(error(r#"let s() = "\u{0}""#), (invalid.clone(), 11, 5));
+        assert_eq!(error(r#"let s() = "\u00e9""#).0, invalid);
+    }
+
+    #[test]
+    fn incremental() {
+        let lex = |text: &str, name: &str| {
+            Interpreter::from_str(text, name.to_string())
+                .pull()
+                .unwrap()
+        };
+        let mut parser = Parser::from_tree(vec![]);
+        parser.extend(lex("let square(x) = x * x", "one"));
+        assert_eq!(parser.run().unwrap().len(), 1);
+
+        // only the new tokens get parsed, and old errors aren't reported again
+        parser.extend(lex("let bad = 1", "two"));
+        assert_eq!(parser.run().unwrap_err().len(), 1);
+        parser.extend(lex("let cube(x) = x * square(x)", "three"));
+        let tree = parser.run().unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].get_location().get_filename(), "three");
+
+        parser.extend(lex("cube(2) + 1", "four"));
+        let expr = parser.expression().unwrap();
+        assert!(matches!(
+            expr.get_expr(),
+            Expr::BinaryOperation(Operator::Add, ..)
+        ));
+        assert!(parser.run().unwrap().is_empty());
+    }
+
+    #[test]
+    fn annotations() {
+        let params: Vec<Vec<Param>> = parser_from("annotations")
+            .unwrap()
+            .run()
+            .unwrap()
+            .into_iter()
+            .filter_map(|def| match def.get_expr() {
+                Expr::FunctionDefinition(_, params, ..) => Some(params),
+                _ => None,
+            })
+            .collect();
+        let param = |name: &str, ty: Option<ParamType>| Param {
+            name: name.to_string(),
+            ty,
+        };
+        assert_eq!(
+            params,
+            vec![
+                vec![param("n", Some(ParamType::Plain(ReturnType::Int)))],
+                vec![
+                    param(
+                        "x",
+                        Some(ParamType::Range(ReturnType::Float, Some(0.0), Some(1.0)))
+                    ),
+                    param("by", Some(ParamType::Plain(ReturnType::Float))),
+                ],
+                vec![
+                    param(
+                        "n",
+                        Some(ParamType::Range(ReturnType::Int, Some(-1.0), None))
+                    ),
+                    param("label", Some(ParamType::Plain(ReturnType::String))),
+                ],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_ranges() {
+        let errors = parser_from("ranges").unwrap().run().unwrap_err();
+        let messages: Vec<(usize, String)> = errors
+            .iter()
+            .map(|e| (e.get_location().unwrap().get_line(), e.get_message()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (0, "improperly formatted range!".to_string()),
+                (2, "improperly formatted range!".to_string()),
+                (4, "unknown type!".to_string()),
+                (6, "improperly formatted range!".to_string()),
+                (10, "improperly formatted range!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn return_annotations() {
+        // whether the body agrees is up to typeck, the parser only records the annotation
+        let declared: Vec<(String, Option<ReturnType>)> = parser_from("returns")
+            .unwrap()
+            .run()
+            .unwrap()
+            .into_iter()
+            .filter_map(|def| match def.get_expr() {
+                Expr::FunctionDefinition(name, _, _, declared, _) => Some((name, declared)),
+                _ => None,
+            })
+            .collect();
+        let entry = |name: &str, ty: Option<ReturnType>| (name.to_string(), ty);
+        assert_eq!(
+            declared,
+            vec![
+                entry("fib", Some(ReturnType::Int)),
+                entry("forever", Some(ReturnType::String)),
+                entry("half", Some(ReturnType::Float)),
+                entry("bad", Some(ReturnType::Int)),
+                entry("entry", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let tree = parser_from("arrays").unwrap().run().unwrap();
+        let Expr::FunctionDefinition(_, params, ..) = tree[0].get_expr() else {
+            panic!("expected a definition");
+        };
+        assert_eq!(
+            params[0].ty,
+            Some(ParamType::Plain(ReturnType::Array(ReturnType::Int.into())))
+        );
+
+        // grid[0][1] + grid[1][0]
+        let Expr::FunctionDefinition(_, _, body, ..) = tree[1].get_expr() else {
+            panic!("expected a definition");
+        };
+        let Expr::BinaryOperation(Operator::Add, left, _) = body.get_expr() else {
+            panic!("expected an addition");
+        };
+        let Expr::Index(inner, column) = left.get_expr() else {
+            panic!("expected an index");
+        };
+        assert!(matches!(inner.get_expr(), Expr::Index(..)));
+        assert!(matches!(
+            column.get_expr(),
+            Expr::NumberLiteral(false, 1, _)
+        ));
+
+        let Expr::FunctionDefinition(_, _, body, ..) = tree[2].get_expr() else {
+            panic!("expected a definition");
+        };
+        let Expr::BinaryOperation(Operator::Add, _, length) = body.get_expr() else {
+            panic!("expected an addition");
+        };
+        let Expr::Length(empty) = length.get_expr() else {
+            panic!("expected len");
+        };
+        assert!(
+            matches!(empty.get_expr(), Expr::ArrayLiteral(elements, None) if elements.is_empty())
+        );
+    }
+
+    #[test]
+    fn comprehensions() {
+        let tree = parser_from("loops").unwrap().run().unwrap();
+        let Expr::FunctionDefinition(_, _, body, ..) = tree[0].get_expr() else {
+            panic!("expected a definition");
+        };
+        let Expr::For(ForKind::Collect, variable, iterable, _, None) = body.get_expr() else {
+            panic!("expected a comprehension");
+        };
+        assert_eq!(variable, "i");
+        assert!(matches!(iterable.get_expr(), Expr::Range(..)));
+
+        let Expr::FunctionDefinition(_, _, body, ..) = tree[1].get_expr() else {
+            panic!("expected a definition");
+        };
+        let Expr::For(ForKind::Sum, _, iterable, ..) = body.get_expr() else {
+            panic!("expected a sum");
+        };
+        assert!(matches!(iterable.get_expr(), Expr::VariableRef(name) if name == "xs"));
+
+        let errors = parser_from("bad_loops").unwrap().run().unwrap_err();
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|e| e.get_location().unwrap().get_line())
+            .collect();
+        assert_eq!(lines, vec![0, 4, 6]);
+    }
+
+    #[test]
+    fn bindings() {
+        let tree = parser_from("bindings").unwrap().run().unwrap();
+        assert_eq!(tree.len(), 4);
+
+        // inner * tall, under both indented bindings
+        let Expr::FunctionDefinition(_, _, body, ..) = tree[1].get_expr() else {
+            panic!("expected a definition");
+        };
+        let Expr::Let(outer, _, rest) = body.get_expr() else {
+            panic!("expected a binding");
+        };
+        let Expr::Let(inner, value, rest) = rest.get_expr() else {
+            panic!("expected a nested binding");
+        };
+        assert_eq!((outer.as_str(), inner.as_str()), ("inner", "tall"));
+        assert!(matches!(
+            value.get_expr(),
+            Expr::BinaryOperation(Operator::Subtract, ..)
+        ));
+        assert!(matches!(
+            rest.get_expr(),
+            Expr::BinaryOperation(Operator::Multiply, ..)
+        ));
+
+        // the body after a trailing `in` is the whole chain
+        let Expr::FunctionDefinition(_, _, body, ..) = tree[2].get_expr() else {
+            panic!("expected a definition");
+        };
+        let Expr::Let(_, _, rest) = body.get_expr() else {
+            panic!("expected a binding");
+        };
+        assert!(matches!(rest.get_expr(), Expr::ChainExpression(..)));
+
+        let errors = parser_from("bad_bindings").unwrap().run().unwrap_err();
+        let messages: Vec<(usize, String)> = errors
+            .iter()
+            .map(|e| (e.get_location().unwrap().get_line(), e.get_message()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (0, "improperly formatted local binding!".to_string()),
+                (3, "improperly formatted local binding!".to_string()),
+                (5, "expected identifier!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn externs() {
+        let tree = parser_from("externs").unwrap().run().unwrap();
+        assert_eq!(tree.len(), 4);
+        let Expr::ExternDeclaration(name, params, ReturnType::Float) = tree[1].get_expr() else {
+            panic!("expected an extern");
+        };
+        assert_eq!(name, "pow");
+        assert_eq!(
+            params,
+            vec![
+                Param {
+                    name: "base".to_string(),
+                    ty: Some(ParamType::Plain(ReturnType::Float)),
+                },
+                Param {
+                    name: "exponent".to_string(),
+                    ty: Some(ParamType::Plain(ReturnType::Float)),
+                },
+            ]
+        );
+
+        let errors = parser_from("bad_externs").unwrap().run().unwrap_err();
+        let messages: Vec<(usize, String)> = errors
+            .iter()
+            .map(|e| (e.get_location().unwrap().get_line(), e.get_message()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (0, "improperly formatted extern declaration!".to_string()),
+                (2, "improperly formatted extern declaration!".to_string()),
+                (4, "improperly formatted range!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lambdas() {
+        let cases = [
+            ("\\x -> x * 2", "(\\x -> (x * 2))"),
+            ("1 + \\x -> x + 1", "(1 + (\\x -> (x + 1)))"),
+            (
+                "\\(a, b) -> \\c -> a * b + c",
+                "(\\a, b -> (\\c -> ((a * b) + c)))",
+            ),
+            ("f(1)(2, x) * 3", "(f(1)(2, x) * 3)"),
+            ("(\\x -> x)(a) - 1", "((\\x -> x)(a) - 1)"),
+        ];
+        for (source, expected) in cases {
+            let mut parser = Parser::from_source("<test>", source).unwrap();
+            let expr = parser.expression().unwrap();
+            assert_eq!(grouped(&expr), expected, "{}", source);
+        }
+
+        // function types can go anywhere a type can, and nest
+        let tree = parser_from("lambdas").unwrap().run().unwrap();
+        let Expr::FunctionDefinition(name, params, _, declared, _) = tree[2].get_expr() else {
+            panic!("expected a definition");
+        };
+        let int_to_int = ReturnType::Function(vec![ReturnType::Int], ReturnType::Int.into());
+        assert_eq!(name, "compose");
+        assert_eq!(params[1].ty, Some(ParamType::Plain(int_to_int.clone())));
+        assert_eq!(declared, Some(int_to_int));
+        let source = "let f(g: ((Int) -> Int, Float) -> (Int) -> Bool) = g";
+        let tree = Parser::from_source("<test>", source)
+            .unwrap()
+            .run()
+            .unwrap();
+        let Expr::FunctionDefinition(_, params, ..) = tree[0].get_expr() else {
+            panic!("expected a definition");
+        };
+        assert_eq!(
+            params[0].ty.as_ref().unwrap().to_string(),
+            "((Int) -> Int, Float) -> (Int) -> Bool"
+        );
+
+        for source in ["\\ -> 1", "\\x x", "\\x ->", "\\1 -> 1"] {
+            let mut parser = Parser::from_source("<test>", source).unwrap();
+            let errors = parser.expression().unwrap_err();
+            assert_eq!(
+                errors[0].get_message(),
+                "improperly formatted lambda!",
+                "{}",
+                source
+            );
+        }
+    }
+
     #[test]
     fn parse_expr_or_err() {
         let tokens_err = vec![Token::Let, Token::When, Token::Newline, Token::Extern];
@@ -178,13 +719,24 @@ impl Parser {
     // Create a new `Parser` from the given source file
     pub fn new(src: &str) -> Result<Parser> {
         let file = BufReader::new(File::open(src)?);
-        let mut lexer = Interpreter::new(file, src.to_string())?;
+        Parser::from_lexer(Interpreter::from_reader(file, src.to_string())?)
+    }
+
+    // source that isn't on disk (or has changed since), `name` is what the locations say it's from
+    pub fn from_source(name: &str, source: &str) -> Result<Parser> {
+        Parser::from_lexer(Interpreter::from_str(source, name.to_string()))
+    }
+
+    // for a lexer that's been set up some other way, like with utf-16 columns
+    pub fn from_lexer(mut lexer: Interpreter) -> Result<Parser> {
+        let tokens = lexer.pull()?;
 
         Ok(Parser {
-            tokens: lexer.pull()?,
+            tokens,
             index: 0,
             stack: vec![],
-            registry: HashMap::new()
+            imports: vec![],
+            comments: lexer.comments().to_vec(),
         })
     }
 
@@ -193,55 +745,69 @@ impl Parser {
             tokens,
             index: 0,
             stack: vec![],
-            registry: HashMap::new()
+            imports: vec![],
+            comments: vec![],
         }
     }
 
-    pub fn or_dump<T>(&self, opt: Result<T>) -> T {
-        match opt {
-            Ok(v) => v,
-            Err(e) => {
-                self.print_error();
-                panic!("{}", e);
-            }
+    // more tokens for the same parser, like the next line typed into the repl; whatever
+    // was parsed already stays parsed, `run` and `expression` carry on from there
+    pub fn extend(&mut self, tokens: Vec<LocatedToken>) {
+        let Some(first) = tokens.first() else { return };
+        // so a `let` at the start of the new tokens still starts a definition
+        if self
+            .tokens
+            .last()
+            .is_some_and(|last| last.tok() != Token::Newline)
+        {
+            self.tokens
+                .push(LocatedToken::new(Token::Newline, first.loc()));
         }
+        self.tokens.extend(tokens);
     }
 
-    fn add_to_stack(&mut self, error: Box<dyn Error>) {
-        self.stack.push(error);
+    pub fn imports(&self) -> &[(String, Location)] {
+        &self.imports
     }
 
-    pub fn print_error(&self) {
-        for error in &self.stack {
-            panic!("{}", *error);
-        }
+    pub fn comments(&self) -> &[(String, Location)] {
+        &self.comments
     }
 
-    // if you change this, make sure to change BASIC_OP_COUNT above
-    fn basic_op_prec(symbol: Symbol) -> Option<u8> {
+    fn add_to_stack(&mut self, error: Diagnostic) {
+        self.stack.push(error);
+    }
+
+    // the prefix operator a symbol stands for
+    fn unary_operator(symbol: &Symbol) -> Option<UnaryOperator> {
         match symbol {
-            Symbol::ToPower => Some(0),
-            Symbol::Multiply => Some(1),
-            Symbol::Divide => Some(2),
-            Symbol::Remainder => Some(3),
-            Symbol::Add => Some(4),
-            Symbol::Subtract => Some(5),
-            Symbol::LessThan => Some(6),
-            Symbol::GreaterThan => Some(7),
-            Symbol::Comma => Some(8),
-            Symbol::Equals => Some(9),
+            Symbol::Subtract => Some(UnaryOperator::Negate),
+            Symbol::Add => Some(UnaryOperator::Plus),
+            Symbol::Negate => Some(UnaryOperator::Not),
             _ => None,
         }
     }
 
-    fn compound_op_prec(symbols: (Symbol, Symbol)) -> Option<u8> {
-        match symbols {
-            (Symbol::LessThan, Symbol::Equals) => Some(10),
-            (Symbol::GreaterThan, Symbol::Equals) => Some(11),
-            (Symbol::Equals, Symbol::Equals) => Some(12),
-            (Symbol::Negate, Symbol::Equals) => Some(13),
-            (Symbol::Ampersand, Symbol::Ampersand) => Some(14),
-            (Symbol::Pipe, Symbol::Pipe) => Some(15),
+    // the operator a symbol stands for, if it's one that goes between two operands
+    fn binary_operator(symbol: &Symbol) -> Option<Operator> {
+        match symbol {
+            Symbol::ToPower => Some(Operator::Power),
+            Symbol::Multiply => Some(Operator::Multiply),
+            Symbol::Divide => Some(Operator::Divide),
+            Symbol::Remainder => Some(Operator::Remainder),
+            Symbol::Add => Some(Operator::Add),
+            Symbol::Subtract => Some(Operator::Subtract),
+            Symbol::LessThan => Some(Operator::Less),
+            Symbol::GreaterThan => Some(Operator::Greater),
+            Symbol::Compound(first, second) => match (first.as_ref(), second.as_ref()) {
+                (Symbol::LessThan, Symbol::Equals) => Some(Operator::LessEqual),
+                (Symbol::GreaterThan, Symbol::Equals) => Some(Operator::GreaterEqual),
+                (Symbol::Equals, Symbol::Equals) => Some(Operator::Equal),
+                (Symbol::Negate, Symbol::Equals) => Some(Operator::NotEqual),
+                (Symbol::Ampersand, Symbol::Ampersand) => Some(Operator::And),
+                (Symbol::Pipe, Symbol::Pipe) => Some(Operator::Or),
+                _ => None,
+            },
             _ => None,
         }
     }
@@ -256,7 +822,6 @@ impl Parser {
 
     fn next_token(&mut self) -> Result<LocatedToken> {
         if self.index >= self.tokens.len() {
-            self.add_to_stack(EofError::basic(Location::internal()).into());
             EofError::while_initializing()
         } else {
             let next = self.tokens[self.index].clone();
@@ -282,64 +847,150 @@ impl Parser {
         }
     }
 
-    fn trace_return_type(&mut self, expr: &LocatedExpr) -> Result<ReturnType> {
-        match expr.get_expr() {
-            Expr::NumberLiteral(floating, _, _) => Ok(if floating {
-                ReturnType::Float
-            } else {
-                ReturnType::Int
-            }),
-            Expr::BinaryOperation(_, left, right) => {
-                let left_type = self.trace_return_type(&left)?;
-                let right_type = self.trace_return_type(&right)?;
-                if left_type == right_type {
-                    Ok(left_type)
-                } else {
-                    UnbalancedBinaryExpressionError::while_parsing(right.get_location())
-                }
-            }
-            Expr::StringLiteral(_) => Ok(ReturnType::String),
-            Expr::FunctionDefinition(_, _, body, _) => self.trace_return_type(&body),
-            Expr::ChainExpression(links) => {
-                let mut last_link_type = None;
-                for link in links {
-                    let link_type = self.trace_return_type(&link)?;
-                    if last_link_type != None && last_link_type != Some(link_type.clone()) {
-                        return UnbalancedChainExpressionError::while_parsing(link.get_location());
-                    } else {
-                        last_link_type = Some(link_type);
-                    }
-                }
-                Ok(last_link_type.unwrap())
-            }
-            Expr::WhenExpression(_, result) => self.trace_return_type(&result),
-            Expr::FunctionCall(name, _) => {
-                Ok(self.registry.get(&name).unwrap().clone())
-            },
-            Expr::VariableRef(_) => {
-                
-            },
-        }
-    }
-
     fn parse_definition(&mut self) -> Result<LocatedExpr> {
         let identifier = self.expect_identifier()?;
+        let name_location = self.last()?.loc();
         let next = self.next_token()?;
         match next.tok() {
             Token::Grouping(args) => {
                 let parsed_args = Parser::parse_def_args(args)?;
-                let body = self.parse_expr_or_err()?.into();
-                let return_type = self.trace_return_type(&body)?;
-                self.registry.insert(identifier.clone(), return_type.clone());
+                let declared = self.parse_return_annotation()?;
+                let body = self.parse_expr_or_err()?;
+
+                // the header covers the return annotation too, if there is one
+                let end = match &declared {
+                    Some((_, location)) => location.get_end(),
+                    None => next.loc().get_end(),
+                };
                 Ok(LocatedExpr::new(
-                    Expr::FunctionDefinition(identifier, parsed_args, body.into(), return_type),
-                    next.loc(),
+                    Expr::FunctionDefinition(
+                        identifier,
+                        parsed_args,
+                        body.into(),
+                        declared.map(|(return_type, _)| return_type),
+                        None,
+                    ),
+                    name_location.with_end(end),
                 ))
             }
             _ => DeclarationError::while_parsing(next.loc()),
         }
     }
 
+    // import "other.fx", where the path is relative to the importing file
+    fn parse_import(&mut self) -> Result<()> {
+        let location = self.last()?.loc();
+        let next = self.next_token()?;
+        let Token::String(path) = next.tok() else {
+            return Err(ImportError::diagnostic(next.loc(), "parse")
+                .with_note("the file to import goes in quotes, like `import \"other.fx\"`")
+                .into());
+        };
+        self.imports
+            .push((path, location.with_end(next.loc().get_end())));
+        Ok(())
+    }
+
+    // extern sin(x: Float): Float, with every type spelled out since there's
+    // no body to infer them from
+    fn parse_extern(&mut self) -> Result<LocatedExpr> {
+        let identifier = self.expect_identifier()?;
+        let name_location = self.last()?.loc();
+        let next = self.next_token()?;
+        let Token::Grouping(args) = next.tok() else {
+            return ExternError::while_parsing(next.loc());
+        };
+        let params = Parser::parse_def_args(args)?;
+        for param in &params {
+            match &param.ty {
+                Some(ParamType::Plain(_)) => {}
+                Some(ParamType::Range(..)) => {
+                    return Err(RangeError::diagnostic(next.loc(), "parse")
+                        .with_note("ranges can't be checked on extern parameters")
+                        .into())
+                }
+                None => {
+                    let note = format!(
+                        "'{}' needs a type, like `{}: Float`",
+                        param.name, param.name
+                    );
+                    return Err(ExternError::diagnostic(next.loc(), "parse")
+                        .with_note(&note)
+                        .into());
+                }
+            }
+        }
+
+        let header = name_location.with_end(next.loc().get_end());
+        let Some((return_type, location)) = self.parse_return_annotation()? else {
+            return Err(ExternError::diagnostic(header.clone(), "parse")
+                .with_note("externs need a return type, like `: Float`")
+                .into());
+        };
+        Ok(LocatedExpr::new(
+            Expr::ExternDeclaration(identifier, params, return_type),
+            header.with_end(location.get_end()),
+        ))
+    }
+
+    // the `: Int` in `let f(n): Int = ...`, if there is one
+    fn parse_return_annotation(&mut self) -> Result<Option<(ReturnType, Location)>> {
+        let Ok(next) = self.next_token() else {
+            return Ok(None);
+        };
+        let Token::Symbol(Symbol::Colon) = next.tok() else {
+            self.back();
+            return Ok(None);
+        };
+        match self.parse_annotation()? {
+            (ParamType::Plain(return_type), location) => Ok(Some((return_type, location))),
+            (ParamType::Range(..), location) => Err(RangeError::diagnostic(location, "parse")
+                .with_note("ranges can only be used on parameters")
+                .into()),
+        }
+    }
+
+    // whatever comes after a colon, which is one token unless it's a function
+    // type like `(Int, Float) -> Int`; the location is the last token's
+    fn parse_annotation(&mut self) -> Result<(ParamType, Location)> {
+        let token = self.next_token()?;
+        let group = match token.tok() {
+            Token::Grouping(group)
+                if group[0].tok() == Token::Bracket(Bracket::Parens(Is::Open)) =>
+            {
+                group
+            }
+            _ => return Ok((Parser::parse_param_type(token.clone())?, token.loc())),
+        };
+        let arrow = self.next_token()?;
+        if !Parser::is_arrow(&arrow) {
+            return Err(UnknownTypeError::diagnostic(token.loc(), "parse")
+                .with_note("a function type needs its return type, like `(Int) -> Int`")
+                .into());
+        }
+
+        let inner = &group[1..group.len() - 1];
+        let mut args = vec![];
+        if !inner.is_empty() {
+            for arg in inner.split(|t| t.tok() == Token::Symbol(Symbol::Comma)) {
+                let mut p = Parser::from_tree(arg.to_vec());
+                let (ty, location) = p.parse_annotation()?;
+                if p.index < p.tokens.len() {
+                    return UnknownTypeError::while_parsing(location);
+                }
+                args.push(ty.base());
+            }
+        }
+        let (ret, location) = self.parse_annotation()?;
+        let function = ReturnType::Function(args, ret.base().into());
+        Ok((ParamType::Plain(function), location))
+    }
+
+    fn is_arrow(token: &LocatedToken) -> bool {
+        let arrow = Symbol::Compound(Symbol::Subtract.into(), Symbol::GreaterThan.into());
+        token.tok() == Token::Symbol(arrow)
+    }
+
     fn look_ahead(&mut self) -> Result<LocatedToken> {
         loop {
             let token = self.next_token()?;
@@ -414,6 +1065,8 @@ impl Parser {
         loop {
             let Ok(token) = self.next_token() else { break };
             match token.tok() {
+                // nothing read yet, like after a trailing `in`, so it starts on the next line
+                Token::Newline if tokens.is_empty() => {}
                 Token::Newline => {
                     let Ok(next) = self.next_token() else { break };
                     if let Token::Let = next.tok() {
@@ -432,15 +1085,20 @@ impl Parser {
                 Token::Symbol(_)
                 | Token::Identifier(_)
                 | Token::Number(_, _)
+                | Token::Bool(_)
                 | Token::Grouping(_)
                 | Token::When
                 | Token::String(_)
-                | Token::FunctionCall(_, _) => {
+                | Token::FunctionCall(_, _)
+                | Token::Index(_, _) => {
                     if tokens.len() > 0 || !matches!(token.tok(), Token::Symbol(Symbol::Equals)) {
                         tokens.push(token);
                     }
                 }
+                // anything else ends the expression and is put back, like the `in`
+                // after a local binding's value
                 _ => {
+                    self.back();
                     break;
                 }
             }
@@ -448,12 +1106,92 @@ impl Parser {
         return tokens;
     }
 
-    fn parse_grouping(tokens: Vec<LocatedToken>, with_brackets: bool) -> Option<LocatedExpr> {
+    // only an unindented `let` (or `extern`, `import`) at the start of a line begins a new
+    // top-level definition, any other `let` is a local binding inside a body
+    fn starts_definition(&self, index: usize) -> bool {
+        let Some(token) = self.tokens.get(index) else {
+            return false;
+        };
+        matches!(token.tok(), Token::Let | Token::Extern | Token::Import)
+            && token.loc().get_column() == 0
+            && (index == 0 || self.tokens[index - 1].tok() == Token::Newline)
+    }
+
+    // looks past the `=` a body starts with, and only moves on if a local binding is next
+    fn at_local_binding(&mut self) -> bool {
+        let start = self.index;
+        let mut next = self.look_ahead();
+        if next
+            .as_ref()
+            .is_ok_and(|t| t.tok() == Token::Symbol(Symbol::Equals))
+        {
+            next = self.look_ahead();
+        }
+        let found =
+            next.is_ok_and(|t| t.tok() == Token::Let) && !self.starts_definition(self.index - 1);
+        if !found {
+            self.index = start;
+        }
+        found
+    }
+
+    fn parse_local_binding(&mut self) -> Option<LocatedExpr> {
+        let location = self.last().ok()?.loc();
+        let errors_before = self.stack.len();
+        match self.parse_binding(location) {
+            Ok(binding) => Some(binding),
+            Err(e) => {
+                // whatever went wrong inside the value or the body is more useful
+                if self.stack.len() == errors_before {
+                    self.add_to_stack(e.into());
+                }
+                None
+            }
+        }
+    }
+
+    // `let y = x * 2 in y + y`, where the `in` can be left off if the body
+    // starts on the next line
+    fn parse_binding(&mut self, location: Location) -> Result<LocatedExpr> {
+        let name = self.expect_identifier()?;
+        let equals = self.next_token()?;
+        if equals.tok() != Token::Symbol(Symbol::Equals) {
+            return BindingError::while_parsing(equals.loc());
+        }
+        let Some(value) = self.parse_expression() else {
+            return Err(BindingError::diagnostic(location, "parse")
+                .with_note("the value is missing")
+                .into());
+        };
+
+        let before_in = self.index;
+        if !self.look_ahead().is_ok_and(|t| t.tok() == Token::In) {
+            self.index = before_in;
+        }
+        let Some(body) = self.parse_expression() else {
+            let note = format!("nothing comes after '{}', so it can never be used", name);
+            return Err(BindingError::diagnostic(location, "parse")
+                .with_note(&note)
+                .into());
+        };
+        Ok(LocatedExpr::new(
+            Expr::Let(name, value.into(), body.into()),
+            location,
+        ))
+    }
+
+    fn parse_grouping(
+        &mut self,
+        tokens: Vec<LocatedToken>,
+        with_brackets: bool,
+    ) -> Option<LocatedExpr> {
         let change = with_brackets as usize;
 
         if tokens.len() > 2 * change {
             let mut parser = Parser::from_tree(tokens[change..tokens.len() - change].to_vec());
-            Some(parser.parse_expression()?)
+            let parsed = parser.parse_expression();
+            self.stack.append(&mut parser.stack);
+            parsed
         } else {
             None
         }
@@ -469,76 +1207,270 @@ impl Parser {
                 symbol.loc(),
             )),
             Token::String(s) => Some(LocatedExpr::new(Expr::StringLiteral(s), symbol.loc())),
-            Token::Grouping(tokens) => Parser::parse_grouping(tokens, true),
-            // fix
+            Token::Bool(value) => Some(LocatedExpr::new(Expr::BoolLiteral(value), symbol.loc())),
+            Token::Grouping(tokens)
+                if tokens[0].tok() == Token::Bracket(Bracket::Square(Is::Open)) =>
+            {
+                let inner = tokens[1..tokens.len() - 1].to_vec();
+                if inner.iter().any(|t| t.tok() == Token::For) {
+                    return self.parse_comprehension(ForKind::Collect, inner, symbol.loc());
+                }
+                let elements = self.parse_list(inner)?;
+                Some(LocatedExpr::new(
+                    Expr::ArrayLiteral(elements, None),
+                    symbol.loc(),
+                ))
+            }
+            Token::Grouping(tokens) => self.parse_grouping(tokens, true),
+            Token::Index(target, tokens) => {
+                let array = self.parse_non_symbol(*target)?;
+                let index = self.parse_grouping(tokens, false)?;
+                Some(LocatedExpr::new(
+                    Expr::Index(array.into(), index.into()),
+                    symbol.loc(),
+                ))
+            }
+            Token::FunctionCall(name, tokens)
+                if name == "sum" && tokens.iter().any(|t| t.tok() == Token::For) =>
+            {
+                self.parse_comprehension(ForKind::Sum, tokens, symbol.loc())
+            }
             Token::FunctionCall(name, tokens) => {
-                let call = if let Ok(args) = Parser::parse_chain(tokens, false) {
-                    Expr::FunctionCall(name, args)
-                } else {
-                    Expr::FunctionCall(name, vec![])
-                };
-                Some(LocatedExpr::new(call, symbol.loc()))
+                let args = self.parse_list(tokens)?;
+                // `len` is built in, it works on any array
+                if name == "len" {
+                    let [array]: [LocatedExpr; 1] = args.try_into().ok().or_else(|| {
+                        self.add_to_stack(ArgumentCountError::basic(Some(symbol.loc())).into());
+                        None
+                    })?;
+                    return Some(LocatedExpr::new(Expr::Length(array.into()), symbol.loc()));
+                }
+                Some(LocatedExpr::new(
+                    Expr::FunctionCall(name, args),
+                    symbol.loc(),
+                ))
             }
             _ => None,
         }
     }
 
-    fn branch(
+    // <body> for <name> in <iterable>, where the iterable is an array or `start..end`
+    fn parse_comprehension(
         &mut self,
+        kind: ForKind,
         tokens: Vec<LocatedToken>,
-        mut indexes: Vec<(usize, u8)>,
+        location: Location,
     ) -> Option<LocatedExpr> {
-        let mut last_index = 0;
-        let mut tree = None;
-        indexes.sort_by(|a, b| a.1.cmp(&b.1));
+        let for_index = tokens.iter().position(|t| t.tok() == Token::For)?;
+        let (body, rest) = tokens.split_at(for_index);
+        let parsed = match rest {
+            [_, variable, keyword, iterable @ ..] if keyword.tok() == Token::In => {
+                match variable.tok() {
+                    Token::Identifier(name) => self
+                        .parse_iterable(iterable.to_vec())
+                        .zip(self.parse_grouping(body.to_vec(), false))
+                        .map(|(iterable, body)| (name, iterable, body)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        let Some((variable, iterable, body)) = parsed else {
+            self.add_to_stack(ComprehensionError::basic(Some(location)).into());
+            return None;
+        };
+        Some(LocatedExpr::new(
+            Expr::For(kind, variable, iterable.into(), body.into(), None),
+            location,
+        ))
+    }
+
+    fn parse_iterable(&mut self, tokens: Vec<LocatedToken>) -> Option<LocatedExpr> {
+        let range = Token::Symbol(Symbol::Compound(Symbol::Dot.into(), Symbol::Dot.into()));
+        let Some(dots) = tokens.iter().position(|t| t.tok() == range) else {
+            return self.parse_grouping(tokens, false);
+        };
+        let start = self.parse_grouping(tokens[..dots].to_vec(), false)?;
+        let end = self.parse_grouping(tokens[dots + 1..].to_vec(), false)?;
+        Some(LocatedExpr::new(
+            Expr::Range(start.into(), end.into()),
+            tokens[dots].loc(),
+        ))
+    }
 
-        for (index, prec) in indexes {
-            let max = tokens.len();
-            if index >= max - 1 {
+    // comma separated arguments or array elements, which can be empty
+    fn parse_list(&mut self, tokens: Vec<LocatedToken>) -> Option<Vec<LocatedExpr>> {
+        if tokens.is_empty() {
+            return Some(vec![]);
+        }
+        match self.parse_chain(tokens, false) {
+            Ok(list) => Some(list),
+            Err(e) => {
+                self.add_to_stack(e.into());
+                None
+            }
+        }
+    }
+
+    // precedence climbing: `tokens` should go operand, operator, operand... and
+    // every operator takes everything after it that binds tighter as its right side
+    fn parse_binary(&mut self, tokens: &[LocatedToken]) -> Option<LocatedExpr> {
+        let mut position = 0;
+        self.climb(tokens, &mut position, 0)
+    }
+
+    fn climb(
+        &mut self,
+        tokens: &[LocatedToken],
+        position: &mut usize,
+        min_precedence: u8,
+    ) -> Option<LocatedExpr> {
+        let mut left = self.parse_operand(tokens, position)?;
+        while let Some(token) = tokens.get(*position) {
+            let Token::Symbol(symbol) = token.tok() else {
                 self.add_to_stack(
-                    UnbalancedBinaryExpressionError::basic(Some(tokens[index].loc())).into(),
+                    UnbalancedBinaryExpressionError::diagnostic(token.loc(), "parse")
+                        .with_note("there's no operator between this and what's before it"),
                 );
                 return None;
+            };
+            let Some(op) = Parser::binary_operator(&symbol) else {
+                self.add_to_stack(UnknownOperatorError::basic(Some(token.loc())).into());
+                return None;
+            };
+            if op.precedence() < min_precedence {
+                break;
             }
+            *position += 1;
 
-            match tree {
-                None => {
-                    let branch = Expr::BinaryOperation(
-                        prec,
-                        self.parse_non_symbol(tokens[index - 1].clone())?.into(),
-                        self.parse_non_symbol(tokens[index + 1].clone())?.into(),
-                    );
-                    tree = Some(LocatedExpr::new(branch, tokens[index].loc()));
-                    last_index = index;
+            // a left associative operator stops the right side at the next one like it
+            let next_min = match op.right_associative() {
+                true => op.precedence(),
+                false => op.precedence() + 1,
+            };
+            let right = self.climb(tokens, position, next_min)?;
+            left = LocatedExpr::new(
+                Expr::BinaryOperation(op, left.into(), right.into()),
+                token.loc(),
+            );
+        }
+        Some(left)
+    }
+
+    fn parse_operand(
+        &mut self,
+        tokens: &[LocatedToken],
+        position: &mut usize,
+    ) -> Option<LocatedExpr> {
+        if let Some(token) = tokens.get(*position) {
+            if let Token::Symbol(symbol) = token.tok() {
+                if symbol == Symbol::Backslash {
+                    *position += 1;
+                    return self.parse_lambda(tokens, position, token.loc());
                 }
-                Some(t) => {
-                    let branch;
-                    if last_index < index {
-                        branch = Expr::BinaryOperation(
-                            prec,
-                            t.into(),
-                            self.parse_non_symbol(tokens[index + 1].clone())?.into(),
-                        );
-                    } else {
-                        branch = Expr::BinaryOperation(
-                            prec,
-                            self.parse_non_symbol(tokens[index - 1].clone())?.into(),
-                            t.into(),
-                        );
-                    }
-                    tree = Some(LocatedExpr::new(branch, tokens[index].loc()));
-                    last_index = index;
+                if let Some(op) = Parser::unary_operator(&symbol) {
+                    *position += 1;
+                    let operand = self.climb(tokens, position, Operator::Power.precedence())?;
+                    return Some(Parser::prefixed(op, operand, token.loc()));
                 }
             }
         }
 
-        match tree {
-            None => Some(self.parse_non_symbol(tokens[0].clone())?),
-            t => t,
+        let Some(token) = tokens
+            .get(*position)
+            .filter(|t| !matches!(t.tok(), Token::Symbol(_)))
+        else {
+            // an operator with nothing after it, or two operators in a row
+            let at = tokens
+                .get(*position)
+                .or(tokens.get(position.wrapping_sub(1)));
+            let location = at.map(|t| t.loc());
+            self.add_to_stack(UnbalancedBinaryExpressionError::basic(location).into());
+            return None;
+        };
+        *position += 1;
+        let mut operand = self.parse_non_symbol(token.clone())?;
+
+        // brackets straight after something call it, like `adder(1)(2)`
+        while let Some(call) = tokens.get(*position) {
+            let Token::Grouping(group) = call.tok() else {
+                break;
+            };
+            if group[0].tok() != Token::Bracket(Bracket::Parens(Is::Open)) {
+                break;
+            }
+            *position += 1;
+            let args = self.parse_list(group[1..group.len() - 1].to_vec())?;
+            operand = LocatedExpr::new(Expr::Apply(operand.into(), args, None), call.loc());
+        }
+        Some(operand)
+    }
+
+    // `\x -> x * 2` or `\(x, y: Int) -> x * y`, where the body takes everything after
+    // the arrow, so a lambda in the middle of something has to go in brackets
+    fn parse_lambda(
+        &mut self,
+        tokens: &[LocatedToken],
+        position: &mut usize,
+        location: Location,
+    ) -> Option<LocatedExpr> {
+        let params = match tokens.get(*position).map(|t| t.tok()) {
+            Some(Token::Identifier(name)) => Ok(vec![Param { name, ty: None }]),
+            Some(Token::Grouping(group))
+                if group[0].tok() == Token::Bracket(Bracket::Parens(Is::Open)) =>
+            {
+                Parser::parse_def_args(group)
+            }
+            _ => LambdaError::while_parsing(location.clone()),
+        };
+        let params = match params {
+            Ok(params) => params,
+            Err(e) => {
+                self.add_to_stack(e.into());
+                return None;
+            }
+        };
+        *position += 1;
+
+        if !tokens.get(*position).is_some_and(Parser::is_arrow) {
+            self.add_to_stack(
+                LambdaError::diagnostic(location, "parse")
+                    .with_note("the parameters are followed by `->` and then the body"),
+            );
+            return None;
+        }
+        *position += 1;
+        if *position == tokens.len() {
+            self.add_to_stack(
+                LambdaError::diagnostic(location, "parse").with_note("the body is missing"),
+            );
+            return None;
+        }
+
+        let body = self.climb(tokens, position, 0)?;
+        Some(LocatedExpr::new(
+            Expr::Lambda(params, body.into(), None, vec![]),
+            location,
+        ))
+    }
+
+    // a minus right in front of a number is part of it, so `-1` is just a literal
+    fn prefixed(op: UnaryOperator, operand: LocatedExpr, location: Location) -> LocatedExpr {
+        match (op, operand.get_expr()) {
+            (UnaryOperator::Negate, Expr::NumberLiteral(floating, int, float)) => {
+                let literal = Expr::NumberLiteral(floating, -int, -float);
+                let end = operand.get_location().get_end();
+                LocatedExpr::new(literal, location.with_end(end))
+            }
+            _ => LocatedExpr::new(Expr::UnaryOperation(op, operand.into()), location),
         }
     }
 
     fn parse_expression(&mut self) -> Option<LocatedExpr> {
+        if self.at_local_binding() {
+            return self.parse_local_binding();
+        }
         let tokens = self.read_expr_tokens();
         if tokens.len() == 0 {
             return None;
@@ -546,10 +1478,10 @@ impl Parser {
 
         for token in &tokens {
             if token.tok() == Token::Symbol(Symbol::Comma) {
-                let expressions = Parser::parse_chain(tokens.clone(), false);
+                let expressions = self.parse_chain(tokens.clone(), false);
                 return match expressions {
                     Err(e) => {
-                        self.add_to_stack(e);
+                        self.add_to_stack(e.into());
                         None
                     }
                     Ok(expr) => Some(LocatedExpr::new(Expr::ChainExpression(expr), token.loc())),
@@ -557,91 +1489,134 @@ impl Parser {
             }
         }
 
-        let mut reader = Parser::from_tree(tokens.clone());
-        let mut op_indexes = vec![];
-        let mut when_index = 0;
-
-        loop {
-            let Ok(next) = reader.next_token() else { break };
-            match next.tok() {
-                Token::When => {
-                    when_index = reader.index - 1;
-                }
-                Token::Symbol(Symbol::Compound(first, second)) => {
-                    op_indexes.push((
-                        reader.index - 1,
-                        Parser::compound_op_prec((*first, *second))?,
-                    ));
-                }
-                // auto unwrap because otherwise it would not have even been added to token list
-                Token::Symbol(op) => {
-                    op_indexes.push((reader.index - 1, Parser::basic_op_prec(op)?))
-                }
-                _ => {}
-            }
-        }
-
-        if when_index > 0 {
-            let left: Vec<(usize, u8)> = op_indexes
-                .clone()
-                .into_iter()
-                .filter(|(index, _)| index < &when_index)
-                .collect();
-            let right: Vec<(usize, u8)> = op_indexes
-                .into_iter()
-                .filter(|(index, _)| index > &when_index)
-                .map(|(index, prec)| (index - when_index - 1, prec))
-                .collect();
-
-            return Some(LocatedExpr::new(
+        // `result when predicate`, the predicate being everything after the last `when`
+        match tokens.iter().rposition(|t| t.tok() == Token::When) {
+            Some(when_index) if when_index > 0 => Some(LocatedExpr::new(
                 Expr::WhenExpression(
-                    self.branch(tokens[when_index + 1..].to_vec(), right)?
-                        .into(),
-                    self.branch(tokens[..when_index].to_vec(), left)?.into(),
+                    self.parse_binary(&tokens[when_index + 1..])?.into(),
+                    self.parse_binary(&tokens[..when_index])?.into(),
                 ),
                 tokens[when_index].loc(),
-            ));
-        } else {
-            self.branch(tokens, op_indexes)
+            )),
+            _ => self.parse_binary(&tokens),
         }
     }
 
-    fn parse_def_args(args: Vec<LocatedToken>) -> Result<Vec<String>> {
+    fn parse_def_args(args: Vec<LocatedToken>) -> Result<Vec<Param>> {
         let mut arg_tree = vec![];
-        let mut p = Parser::from_tree(args.clone());
+        let mut p = Parser::from_tree(args);
 
         p.forward();
-        while let Ok(identifier) = p.expect_identifier() {
-            if let Ok(next) = p.next_token() {
-                match next.tok() {
-                    Token::Symbol(Symbol::Comma) => {
-                        arg_tree.push(identifier);
-                    }
-                    Token::Bracket(Bracket::Parens(Is::Closed)) => {
-                        arg_tree.push(identifier);
-                        break;
-                    }
-                    // type annotations go here
-                    // Token::Operation(Symbol::Colon) => {}
-                    // n: [0,]
-                    _ => {
-                        break;
+        while let Ok(name) = p.expect_identifier() {
+            let mut ty = None;
+            let mut next = p.next_token()?;
+            // n: Int, n: [0,]
+            if let Token::Symbol(Symbol::Colon) = next.tok() {
+                ty = Some(p.parse_annotation()?.0);
+                next = p.next_token()?;
+            }
+            arg_tree.push(Param { name, ty });
+            match next.tok() {
+                Token::Symbol(Symbol::Comma) => {}
+                Token::Bracket(Bracket::Parens(Is::Closed)) => break,
+                _ => return DeclarationError::while_parsing(next.loc()),
+            }
+        }
+        Ok(arg_tree)
+    }
+
+    fn parse_param_type(token: LocatedToken) -> Result<ParamType> {
+        match token.tok() {
+            Token::Identifier(name) => match name.as_str() {
+                "Int" => Ok(ParamType::Plain(ReturnType::Int)),
+                "Float" => Ok(ParamType::Plain(ReturnType::Float)),
+                "String" => Ok(ParamType::Plain(ReturnType::String)),
+                "Bool" => Ok(ParamType::Plain(ReturnType::Bool)),
+                _ => UnknownTypeError::while_parsing(token.loc()),
+            },
+            // `[Int]` is an array type, anything else in square brackets is a range
+            Token::Grouping(group) => match group.as_slice() {
+                [open, element, _]
+                    if open.tok() == Token::Bracket(Bracket::Square(Is::Open))
+                        && matches!(element.tok(), Token::Identifier(_) | Token::Grouping(_)) =>
+                {
+                    match Parser::parse_param_type(element.clone())? {
+                        ParamType::Plain(element) => {
+                            Ok(ParamType::Plain(ReturnType::Array(element.into())))
+                        }
+                        ParamType::Range(..) => Err(RangeError::diagnostic(element.loc(), "parse")
+                            .with_note("arrays of ranges aren't supported")
+                            .into()),
                     }
                 }
+                _ => Parser::parse_range(group, token.loc()),
+            },
+            _ => UnknownTypeError::while_parsing(token.loc()),
+        }
+    }
+
+    // [low, high] where either side can be empty, but not both
+    fn parse_range(group: Vec<LocatedToken>, location: Location) -> Result<ParamType> {
+        let inner = match group.as_slice() {
+            [open, inner @ .., _] if open.tok() == Token::Bracket(Bracket::Square(Is::Open)) => {
+                inner
+            }
+            _ => return RangeError::while_parsing(location),
+        };
+        let mut sides = inner.split(|t| t.tok() == Token::Symbol(Symbol::Comma));
+        let (Some(low), Some(high), None) = (sides.next(), sides.next(), sides.next()) else {
+            return RangeError::while_parsing(location);
+        };
+
+        let mut floating = false;
+        let mut bound = |side: &[LocatedToken]| -> Option<Option<f64>> {
+            let (negative, number) = match side {
+                [] => return Some(None),
+                [sign, number] if sign.tok() == Token::Symbol(Symbol::Subtract) => (true, number),
+                [number] => (false, number),
+                _ => return None,
+            };
+            let value = match number.tok() {
+                Token::Number(Some(int), _) => int as f64,
+                Token::Number(None, Some(float)) => {
+                    floating = true;
+                    float
+                }
+                _ => return None,
+            };
+            Some(Some(if negative { -value } else { value }))
+        };
+
+        match (bound(low), bound(high)) {
+            (Some(None), Some(None)) | (None, _) | (_, None) => RangeError::while_parsing(location),
+            (Some(Some(l)), Some(Some(h))) if l > h => RangeError::while_parsing(location),
+            (Some(low), Some(high)) => {
+                let base = if floating {
+                    ReturnType::Float
+                } else {
+                    ReturnType::Int
+                };
+                Ok(ParamType::Range(base, low, high))
             }
         }
-        return Ok(arg_tree);
     }
 
-    fn parse_one_of_chain(current_group: Vec<LocatedToken>) -> Result<LocatedExpr> {
-        if let Some(parsed) = Parser::parse_grouping(current_group.clone(), false) {
+    fn parse_one_of_chain(
+        &mut self,
+        current_group: Vec<LocatedToken>,
+        end: Location,
+    ) -> Result<LocatedExpr> {
+        if let Some(parsed) = self.parse_grouping(current_group.clone(), false) {
             Ok(parsed)
         } else {
-            BadCommaError::while_parsing(current_group.last().unwrap().loc())
+            BadCommaError::while_parsing(current_group.last().map_or(end, |t| t.loc()))
         }
     }
 
-    fn parse_chain(args: Vec<LocatedToken>, is_arg: bool) -> Result<Vec<LocatedExpr>> {
+    fn parse_chain(&mut self, args: Vec<LocatedToken>, is_arg: bool) -> Result<Vec<LocatedExpr>> {
+        let end = args
+            .last()
+            .map_or(Location::internal().unwrap(), |t| t.loc());
         let mut arg_tree = vec![];
         let mut p = Parser::from_tree(args);
         let mut current_group = vec![];
@@ -652,7 +1627,7 @@ impl Parser {
         while let Ok(next) = p.next_token() {
             match next.tok() {
                 Token::Symbol(Symbol::Comma) | Token::Bracket(Bracket::Parens(Is::Closed)) => {
-                    let parsed = Parser::parse_one_of_chain(current_group)?;
+                    let parsed = self.parse_one_of_chain(current_group, next.loc())?;
                     arg_tree.push(parsed);
                     current_group = vec![];
 
@@ -669,44 +1644,78 @@ impl Parser {
         }
 
         if is_arg {
-            BadCommaError::while_parsing(current_group.last().unwrap().loc())
+            BadCommaError::while_parsing(current_group.last().map_or(end, |t| t.loc()))
         } else {
-            arg_tree.push(Parser::parse_one_of_chain(current_group)?);
+            arg_tree.push(self.parse_one_of_chain(current_group, end)?);
             Ok(arg_tree)
         }
     }
 
-    pub fn run(&mut self) -> Vec<LocatedExpr> {
+    // skip ahead to the next top-level definition so one bad one doesn't hide the rest
+    fn synchronize(&mut self) {
+        while self.index < self.tokens.len() && !self.starts_definition(self.index) {
+            self.forward();
+        }
+    }
+
+    pub fn run(&mut self) -> std::result::Result<Vec<LocatedExpr>, Vec<Diagnostic>> {
         let mut tree: Vec<LocatedExpr> = vec![];
 
         loop {
             let Ok(token) = self.next_token() else { break };
+            let errors_before = self.stack.len();
 
-            if let Token::Let = token.tok() {
-                match self.parse_definition() {
-                    Ok(def) => {
-                        tree.push(def);
-                    }
-                    Err(e) => {
-                        self.print_error();
-                        panic!("{}", e);
-                    }
+            let parsed = match token.tok() {
+                Token::Let => self.parse_definition(),
+                Token::Extern => self.parse_extern(),
+                Token::Import => match self.parse_import() {
+                    Ok(()) => continue,
+                    Err(e) => Err(e),
+                },
+                Token::Identifier(_)
+                | Token::Grouping(_)
+                | Token::FunctionCall(_, _)
+                | Token::Index(_, _) => {
+                    self.back();
+                    self.parse_expr_or_err()
                 }
-            } else {
-                match token.tok() {
-                    Token::Identifier(_)
-                    | Token::Extern
-                    | Token::Grouping(_)
-                    | Token::FunctionCall(_, _) => {
-                        self.back();
-                        let expr = self.parse_expr_or_err();
-                        tree.push(self.or_dump(expr));
+                _ => continue,
+            };
+
+            match parsed {
+                Ok(expr) => tree.push(expr),
+                Err(e) => {
+                    // whatever was found further down is more useful than the generic error
+                    if self.stack.len() == errors_before {
+                        self.add_to_stack(e.into());
                     }
-                    _ => {}
+                    self.synchronize();
                 }
             }
         }
 
-        return tree;
+        // taken so that errors only get reported by the `run` that found them
+        if self.stack.is_empty() {
+            Ok(tree)
+        } else {
+            Err(std::mem::take(&mut self.stack))
+        }
+    }
+
+    // a single expression on its own, rather than a file of definitions
+    pub fn expression(&mut self) -> std::result::Result<LocatedExpr, Vec<Diagnostic>> {
+        let errors_before = self.stack.len();
+        let parsed = self.parse_expr_or_err();
+        self.synchronize();
+        match parsed {
+            Ok(expr) if self.stack.is_empty() => Ok(expr),
+            Ok(_) => Err(std::mem::take(&mut self.stack)),
+            Err(e) => {
+                if self.stack.len() == errors_before {
+                    self.add_to_stack(e.into());
+                }
+                Err(std::mem::take(&mut self.stack))
+            }
+        }
     }
 }
@@ -0,0 +1,424 @@
+use crate::{
+    ast::{Expr, LocatedExpr},
+    dump::{render, DumpFormat},
+    errors::*,
+    eval::Evaluator,
+    lexer::{Interpreter, LocatedToken, Token},
+    loader::Loader,
+    parser::Parser,
+    typeck::TypeChecker,
+};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+const HELP: &str = "\
+let ...              define (or redefine) a function
+<expr>               evaluate an expression
+import \"file\"        bring in the definitions from a file
+:type [name | expr]  the type of a function or an expression, or of everything defined
+:ast <name | expr>   the ast of a definition (typed) or an expression
+:tokens <text>       what the lexer makes of some text
+:help                print this
+:quit                leave, so does ctrl-d
+
+a line ending in `=`, `,`, `in`, an operator or an open bracket carries on until a blank line
+";
+
+// what an expression gets wrapped in to be checked and run, which can't clash
+// with anything typed in since it isn't an identifier
+const IT: &str = "<it>";
+
+pub enum Reply {
+    // what to print, which might be nothing (like for a blank line)
+    Done(String),
+    // the input isn't finished yet, so there's another line to wait for
+    More,
+    Quit,
+}
+
+// `fx repl`: definitions are kept for the whole session and re-checked together
+// every time one is added, expressions are checked against them and then run
+// through the evaluator
+pub struct Repl {
+    // one parser for the whole session, each input gets appended to it
+    parser: Parser,
+    // how many of the parser's imports have been followed
+    imported: usize,
+    include_paths: Vec<String>,
+    // everything defined so far, untyped, with anything redefined replaced
+    definitions: Vec<LocatedExpr>,
+    // from the last time `definitions` checked, for `:type`
+    checker: TypeChecker,
+    // every input so far, `<repl:1>` being the first, so errors can show where they are
+    inputs: Vec<String>,
+    // the lines of an input that isn't finished yet
+    pending: String,
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl {
+            parser: Parser::from_tree(vec![]),
+            imported: 0,
+            include_paths: vec![],
+            definitions: vec![],
+            checker: TypeChecker::new(),
+            inputs: vec![],
+            pending: String::new(),
+        }
+    }
+
+    pub fn with_include_paths(mut self, paths: &[String]) -> Repl {
+        self.include_paths.extend(paths.iter().cloned());
+        self
+    }
+
+    pub fn run(mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "fx> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let prompt = match self.line(&line?) {
+                Reply::Done(text) => {
+                    write!(output, "{}", text)?;
+                    "fx> "
+                }
+                Reply::More => "... ",
+                Reply::Quit => return Ok(()),
+            };
+            write!(output, "{}", prompt)?;
+            output.flush()?;
+        }
+        // running out of input part way through still finishes what was typed
+        let pending = std::mem::take(&mut self.pending);
+        write!(output, "\n{}", self.submit(&pending))
+    }
+
+    pub fn line(&mut self, line: &str) -> Reply {
+        if !self.pending.is_empty() {
+            if line.trim().is_empty() {
+                let input = std::mem::take(&mut self.pending);
+                return Reply::Done(self.submit(&input));
+            }
+            self.pending.push_str(line);
+            self.pending.push('\n');
+            return Reply::More;
+        }
+        match line.trim() {
+            ":quit" | ":q" => Reply::Quit,
+            trimmed if Repl::continues(trimmed) => {
+                self.pending = format!("{}\n", line);
+                Reply::More
+            }
+            _ => Reply::Done(self.submit(line)),
+        }
+    }
+
+    // whether a line obviously isn't the end of what's being typed
+    fn continues(line: &str) -> bool {
+        let open = line.matches(['(', '[']).count();
+        let closed = line.matches([')', ']']).count();
+        open > closed
+            || line == "in"
+            || line.ends_with(" in")
+            || line.ends_with(['=', ',', '+', '-', '*', '/', '%', '^', '<', '>', '&', '|'])
+    }
+
+    fn submit(&mut self, input: &str) -> String {
+        let input = input.trim();
+        if input.is_empty() {
+            return String::new();
+        }
+        if let Some(command) = input.strip_prefix(':') {
+            return self.command(command);
+        }
+        let tokens = match self.lex(input) {
+            Ok(tokens) => tokens,
+            Err(errors) => return self.report(&errors),
+        };
+        let definition = matches!(
+            tokens.first().map(LocatedToken::tok),
+            Some(Token::Let | Token::Extern | Token::Import)
+        );
+        self.parser.extend(tokens);
+        let result = match definition {
+            true => self.define(),
+            false => self.evaluate(),
+        };
+        result.unwrap_or_else(|errors| self.report(&errors))
+    }
+
+    fn command(&mut self, command: &str) -> String {
+        let (name, rest) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let rest = rest.trim();
+        let result = match name {
+            "help" | "h" => Ok(HELP.to_string()),
+            "type" | "t" => self.type_of(rest),
+            "ast" => self.ast(rest),
+            "tokens" => self
+                .lex(rest)
+                .map(|tokens| render(&tokens, DumpFormat::Text)),
+            _ => Ok(format!("unknown command ':{}', try :help\n", name)),
+        };
+        result.unwrap_or_else(|errors| self.report(&errors))
+    }
+
+    fn define(&mut self) -> std::result::Result<String, Vec<Diagnostic>> {
+        let own = self.parser.run();
+        // counted even if the input didn't parse, so they aren't followed next time instead
+        let imports = self.parser.imports()[self.imported..].to_vec();
+        self.imported += imports.len();
+        let own = own?;
+
+        // imports are relative to wherever the repl was started
+        let mut added = vec![];
+        let mut loader = Loader::new().with_include_paths(&self.include_paths);
+        for (import, location) in imports {
+            let path = loader.resolve(Path::new(""), &import);
+            match loader.load(&path.to_string_lossy()) {
+                Ok(tree) => added.extend(tree),
+                Err(errors) => {
+                    let note = format!("'{}': {}", import, errors[0].get_message());
+                    return Err(vec![
+                        ImportError::diagnostic(location, "parse").with_note(&note)
+                    ]);
+                }
+            }
+        }
+        added.extend(own);
+
+        let mut definitions = self.definitions.clone();
+        for def in &added {
+            let name = Repl::name(def);
+            match definitions.iter().position(|old| Repl::name(old) == name) {
+                Some(index) => definitions[index] = def.clone(),
+                None => definitions.push(def.clone()),
+            }
+        }
+        let mut checker = TypeChecker::new();
+        checker.check(definitions.clone())?;
+        self.definitions = definitions;
+        self.checker = checker;
+
+        let mut out = String::new();
+        for def in &added {
+            let name = Repl::name(def);
+            if let Some(signature) = self.checker.signature(&name) {
+                out.push_str(&format!("{}: {}\n", name, signature));
+            }
+        }
+        Ok(out)
+    }
+
+    fn evaluate(&mut self) -> std::result::Result<String, Vec<Diagnostic>> {
+        let expr = self.parser.expression()?;
+        let location = expr.get_location();
+        let (_, tree) = self.check_with(expr)?;
+        match Evaluator::new(&tree).call(IT, vec![], location) {
+            Ok(value) => Ok(format!("{}\n", value)),
+            Err(e) => Err(vec![Diagnostic::from(e)]),
+        }
+    }
+
+    fn type_of(&mut self, input: &str) -> std::result::Result<String, Vec<Diagnostic>> {
+        if input.is_empty() {
+            let mut out = String::new();
+            for def in &self.definitions {
+                let name = Repl::name(def);
+                if let Some(signature) = self.checker.signature(&name) {
+                    out.push_str(&format!("{}: {}\n", name, signature));
+                }
+            }
+            return Ok(out);
+        }
+        if let Some(signature) = self.checker.signature(input) {
+            if self.defined(input) {
+                return Ok(format!("{}: {}\n", input, signature));
+            }
+        }
+        let expr = self.scratch(input)?.expression()?;
+        let (checker, _) = self.check_with(expr)?;
+        Ok(format!("{}\n", checker.signature(IT).unwrap().ret))
+    }
+
+    fn ast(&mut self, input: &str) -> std::result::Result<String, Vec<Diagnostic>> {
+        if self.defined(input) {
+            let tree = TypeChecker::new().check(self.definitions.clone())?;
+            let def: Vec<LocatedExpr> = tree
+                .into_iter()
+                .filter(|def| Repl::name(def) == input)
+                .collect();
+            return Ok(render(&def, DumpFormat::Text));
+        }
+        let mut parser = self.scratch(input)?;
+        let tree = match input.starts_with("let ") || input.starts_with("extern ") {
+            true => parser.run()?,
+            false => vec![parser.expression()?],
+        };
+        Ok(render(&tree, DumpFormat::Text))
+    }
+
+    // definitions plus `expr` as the body of `IT`, checked
+    fn check_with(
+        &self,
+        expr: LocatedExpr,
+    ) -> std::result::Result<(TypeChecker, Vec<LocatedExpr>), Vec<Diagnostic>> {
+        let location = expr.get_location();
+        let it = Expr::FunctionDefinition(IT.to_string(), vec![], Box::new(expr), None, None);
+        let mut tree = self.definitions.clone();
+        tree.push(LocatedExpr::new(it, location));
+        let mut checker = TypeChecker::new();
+        let tree = checker.check(tree)?;
+        Ok((checker, tree))
+    }
+
+    // a parser of its own, for things that are only being looked at
+    fn scratch(&mut self, input: &str) -> std::result::Result<Parser, Vec<Diagnostic>> {
+        self.lex(input).map(Parser::from_tree)
+    }
+
+    fn lex(&mut self, input: &str) -> std::result::Result<Vec<LocatedToken>, Vec<Diagnostic>> {
+        self.inputs.push(input.to_string());
+        let filename = format!("<repl:{}>", self.inputs.len());
+        Interpreter::from_str(input, filename)
+            .pull()
+            .map_err(|e| vec![Diagnostic::from(e)])
+    }
+
+    fn defined(&self, name: &str) -> bool {
+        self.definitions.iter().any(|def| Repl::name(def) == name)
+    }
+
+    fn name(def: &LocatedExpr) -> String {
+        match def.get_expr() {
+            Expr::FunctionDefinition(name, ..) | Expr::ExternDeclaration(name, ..) => name,
+            _ => String::new(),
+        }
+    }
+
+    fn report(&self, errors: &[Diagnostic]) -> String {
+        let source_of = |filename: &str| match filename
+            .strip_prefix("<repl:")
+            .and_then(|rest| rest.strip_suffix('>'))
+        {
+            Some(number) => self
+                .inputs
+                .get(number.parse::<usize>().ok()?.checked_sub(1)?)
+                .cloned(),
+            None => fs::read_to_string(filename).ok(),
+        };
+        errors
+            .iter()
+            .map(|error| error.render(&source_of))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn done(repl: &mut Repl, line: &str) -> String {
+        match repl.line(line) {
+            Reply::Done(text) => text,
+            Reply::More => panic!("'{}' wasn't finished", line),
+            Reply::Quit => panic!("'{}' quit", line),
+        }
+    }
+
+    #[test]
+    fn session() {
+        let mut repl = Repl::new();
+        assert_eq!(
+            done(&mut repl, "let twice(n) = n * 2"),
+            "twice: (Int) -> Int\n"
+        );
+        assert_eq!(done(&mut repl, "twice(4) + 1"), "9\n");
+        assert_eq!(done(&mut repl, "[1, 2, 3][twice(1)]"), "3\n");
+
+        // a definition over more than one line waits for a blank one
+        assert!(matches!(repl.line("let fib(n) ="), Reply::More));
+        assert!(matches!(repl.line("    n when n < 2,"), Reply::More));
+        assert!(matches!(
+            repl.line("    fib(n - 1) + fib(n - 2)"),
+            Reply::More
+        ));
+        assert_eq!(done(&mut repl, ""), "fib: (Int) -> Int\n");
+        assert_eq!(done(&mut repl, "fib(10)"), "55\n");
+
+        // defining something again replaces it
+        done(&mut repl, "let twice(n) = n + n");
+        assert_eq!(done(&mut repl, "twice(5)"), "10\n");
+        assert!(matches!(repl.line(":q"), Reply::Quit));
+    }
+
+    #[test]
+    fn errors() {
+        let mut repl = Repl::new();
+        done(&mut repl, "let one() = 1");
+        let error = done(&mut repl, "let bad() = nope(1)");
+        assert!(error.contains("unknown function!"));
+        assert!(error.contains(" 1 | let bad() = nope(1)"));
+
+        // nothing from an input that failed is kept
+        assert!(done(&mut repl, "bad()").contains("unknown function!"));
+        assert!(done(&mut repl, "1 / 0").contains("division by zero!"));
+        assert!(done(&mut repl, "let = 2").contains("@<repl:5>:1:5"));
+        assert_eq!(done(&mut repl, "one()"), "1\n");
+    }
+
+    #[test]
+    fn inspect() {
+        let mut repl = Repl::new();
+        done(&mut repl, "let twice(n) = n * 2");
+        done(&mut repl, "let four() = twice(2)");
+        assert_eq!(done(&mut repl, ":type twice"), "twice: (Int) -> Int\n");
+        assert_eq!(done(&mut repl, ":type twice(2) < 3"), "Bool\n");
+        assert_eq!(
+            done(&mut repl, ":type"),
+            "twice: (Int) -> Int\nfour: () -> Int\n"
+        );
+
+        let ast = done(&mut repl, ":ast twice");
+        assert!(ast.starts_with("FunctionDefinition name=\"twice\" params=[\"n\"] signature="));
+        let ast = done(&mut repl, ":ast 1 + 2");
+        assert!(ast.starts_with("BinaryOperation op=\"+\" @<repl:"));
+        let tokens = done(&mut repl, ":tokens let x");
+        assert_eq!(tokens.lines().next(), Some("Let @<repl:5>:1:1"));
+        assert!(done(&mut repl, ":nope").starts_with("unknown command ':nope'"));
+    }
+
+    #[test]
+    fn imports() {
+        let mut repl = Repl::new().with_include_paths(&["src/tests/imports/lib".to_string()]);
+        // nothing calls `cube` yet, so it's whatever typeck falls back on
+        assert_eq!(
+            done(&mut repl, "import \"helpers.txt\""),
+            "cube: (Float) -> Float\n"
+        );
+        assert_eq!(done(&mut repl, "cube(3)"), "27\n");
+        assert!(done(&mut repl, "import \"nowhere.txt\"").contains("couldn't import file!"));
+    }
+
+    #[test]
+    fn run() {
+        // running out of input finishes off what was being typed
+        let input = "let three() = 3\nthree() *\n  2\n";
+        let mut output = vec![];
+        Repl::new().run(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "fx> three: () -> Int\nfx> ... ... \n6\n"
+        );
+    }
+}
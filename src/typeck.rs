@@ -0,0 +1,1114 @@
+use crate::{ast::*, errors::*};
+use std::collections::HashMap;
+
+// a type as the checker sees it, variables get bound by `unify` as constraints show up
+#[derive(Debug, Clone, PartialEq)]
+enum Ty {
+    Var(usize),
+    Con(ReturnType),
+    Array(Box<Ty>),
+    // parameter types, return type
+    Fn(Vec<Ty>, Box<Ty>),
+}
+
+// parameter types, return type and where the function was declared
+type FunctionType = (Vec<Ty>, Ty, Location);
+
+type Scope = HashMap<String, Ty>;
+
+// annotations are always concrete, arrays get split out so they can unify element-wise
+impl From<ReturnType> for Ty {
+    fn from(return_type: ReturnType) -> Ty {
+        match return_type {
+            ReturnType::Array(element) => Ty::Array(Ty::from(*element).into()),
+            ReturnType::Function(args, ret) => Ty::Fn(
+                args.into_iter().map(Ty::from).collect(),
+                Ty::from(*ret).into(),
+            ),
+            other => Ty::Con(other),
+        }
+    }
+}
+
+// runs between `Parser::run` and the ffi, and fills in the `Signature` of every
+// definition so codegen never has to guess. inference is unification like
+// Hindley-Milner, but monomorphic on purpose: codegen makes one function per
+// definition, so nothing gets generalized and `let id(x) = x` can't be used at
+// both Int and Float. whatever no use pins down ends up a Float (see `concrete`)
+pub struct TypeChecker {
+    // what each type variable has been bound to so far
+    bindings: Vec<Option<Ty>>,
+    functions: HashMap<String, FunctionType>,
+    // element type of every array literal and body type of every `for`,
+    // written back into the tree by `annotate`
+    element_types: HashMap<Location, Ty>,
+    // return type of every call through a variable rather than a definition's name
+    call_types: HashMap<Location, Ty>,
+    // the type of every lambda, and what it captures from around it
+    lambdas: HashMap<Location, (Ty, Vec<(String, Ty)>)>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> TypeChecker {
+        TypeChecker::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> TypeChecker {
+        TypeChecker {
+            bindings: vec![],
+            functions: HashMap::new(),
+            element_types: HashMap::new(),
+            call_types: HashMap::new(),
+            lambdas: HashMap::new(),
+        }
+    }
+
+    // every definition is declared before any body is checked, so calls can
+    // refer to anything in the tree (themselves included) in any order
+    pub fn check(
+        &mut self,
+        tree: Vec<LocatedExpr>,
+    ) -> std::result::Result<Vec<LocatedExpr>, Vec<Diagnostic>> {
+        let mut errors = vec![];
+        let mut declared = vec![];
+        for def in &tree {
+            match self.declare(def) {
+                Ok(()) => declared.push(def),
+                Err(e) => errors.push(Diagnostic::from(e)),
+            }
+        }
+        for def in declared {
+            if let Err(e) = self.check_definition(def) {
+                errors.push(Diagnostic::from(e));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(tree.into_iter().map(|def| self.annotate(def)).collect())
+    }
+
+    pub fn signature(&self, name: &str) -> Option<Signature> {
+        let (args, ret, _) = self.functions.get(name)?;
+        Some(Signature {
+            args: args.iter().map(|arg| self.concrete(arg)).collect(),
+            ret: self.concrete(ret),
+        })
+    }
+
+    // names are global, so this is also where two files defining the same thing get caught
+    fn declare(&mut self, def: &LocatedExpr) -> Result<()> {
+        let (name, params, declared) = match def.get_expr() {
+            Expr::FunctionDefinition(name, params, _, declared, _) => (name, params, declared),
+            Expr::ExternDeclaration(name, params, ret) => (name, params, Some(ret)),
+            _ => return Ok(()),
+        };
+        if let Some((_, _, first)) = self.functions.get(&name) {
+            return Err(
+                DuplicateFunctionError::diagnostic(def.get_location(), "type")
+                    .with_label(first.clone(), "first defined here")
+                    .into(),
+            );
+        }
+        let args = params
+            .iter()
+            .map(|param| match &param.ty {
+                Some(ty) => Ty::from(ty.base()),
+                None => self.fresh(),
+            })
+            .collect();
+        let ret = match declared {
+            Some(return_type) => Ty::from(return_type),
+            None => self.fresh(),
+        };
+        self.functions.insert(name, (args, ret, def.get_location()));
+        Ok(())
+    }
+
+    // what `--entry` names has to exist and take nothing, and since it gets
+    // exported as `entry` it can't sit next to a different `entry`
+    pub fn check_entry(&self, name: &str) -> Result<()> {
+        let Some((args, _, location)) = self.functions.get(name) else {
+            let note = format!("there's no function called '{}'", name);
+            return Err(EntryError::unlocated("type").with_note(&note).into());
+        };
+        if !args.is_empty() {
+            return Err(EntryError::diagnostic(location.clone(), "type")
+                .with_note("the entry point can't take any arguments")
+                .into());
+        }
+        match self.functions.get("entry") {
+            Some((_, _, other)) if name != "entry" => {
+                let note = format!(
+                    "'{}' gets exported as `entry`, which is already taken",
+                    name
+                );
+                Err(EntryError::diagnostic(other.clone(), "type")
+                    .with_note(&note)
+                    .into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_definition(&mut self, def: &LocatedExpr) -> Result<()> {
+        let (name, params, body, declared) = match def.get_expr() {
+            Expr::FunctionDefinition(name, params, body, declared, _) => {
+                (name, params, body, declared)
+            }
+            // nothing to check without a body, the declared types are taken as given
+            Expr::ExternDeclaration(..) => return Ok(()),
+            _ => return DeclarationError::while_checking(def.get_location()),
+        };
+        let (args, ret, _) = self.functions[&name].clone();
+        let scope: Scope = params.into_iter().map(|p| p.name).zip(args).collect();
+
+        let body_type = self.infer(&body, &scope)?;
+        if !self.unify(&ret, &body_type) {
+            let note = format!("the body is {}", self.describe(&body_type));
+            let mut error =
+                ReturnTypeError::diagnostic(body.get_location(), "type").with_note(&note);
+            if declared.is_some() {
+                let label = format!("declared as {} here", self.describe(&ret));
+                error = error.with_label(def.get_location(), &label);
+            }
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    fn infer(&mut self, expr: &LocatedExpr, scope: &Scope) -> Result<Ty> {
+        let location = expr.get_location();
+        match expr.get_expr() {
+            Expr::NumberLiteral(floating, _, _) => Ok(Ty::Con(if floating {
+                ReturnType::Float
+            } else {
+                ReturnType::Int
+            })),
+            Expr::StringLiteral(_) => Ok(Ty::Con(ReturnType::String)),
+            Expr::BoolLiteral(_) => Ok(Ty::Con(ReturnType::Bool)),
+            // a definition's name on its own is a function value
+            Expr::VariableRef(name) => match (scope.get(&name), self.functions.get(&name)) {
+                (Some(ty), _) => Ok(ty.clone()),
+                (None, Some((args, ret, _))) => Ok(Ty::Fn(args.clone(), ret.clone().into())),
+                (None, None) => UnknownVariableError::while_checking(location),
+            },
+            Expr::BinaryOperation(op @ (Operator::And | Operator::Or), left, right) => {
+                let used_by = format!("'{}'", op.symbol());
+                self.expect_bool(&left, scope, &used_by)?;
+                self.expect_bool(&right, scope, &used_by)?;
+                Ok(Ty::Con(ReturnType::Bool))
+            }
+            Expr::BinaryOperation(op, left, right) => {
+                let left_type = self.infer(&left, scope)?;
+                let right_type = self.infer(&right, scope)?;
+                if !self.unify(&left_type, &right_type) {
+                    let note = format!(
+                        "the left side is {}, the right side is {}",
+                        self.describe(&left_type),
+                        self.describe(&right_type)
+                    );
+                    return Err(UnbalancedBinaryExpressionError::diagnostic(
+                        right.get_location(),
+                        "type",
+                    )
+                    .with_note(&note)
+                    .into());
+                }
+                match self.resolve(&left_type) {
+                    Ty::Array(_) => {
+                        return Err(UnknownOperatorError::diagnostic(location, "type")
+                            .with_note("arrays don't support any operators")
+                            .into())
+                    }
+                    Ty::Fn(..) => {
+                        return Err(UnknownOperatorError::diagnostic(location, "type")
+                            .with_note("functions don't support any operators")
+                            .into())
+                    }
+                    Ty::Con(ReturnType::Bool)
+                        if !matches!(op, Operator::Equal | Operator::NotEqual) =>
+                    {
+                        return Err(UnknownOperatorError::diagnostic(location, "type")
+                            .with_note("Bools can only be compared with '==' and '!='")
+                            .into())
+                    }
+                    Ty::Con(ReturnType::String) if op != Operator::Add && !op.compares() => {
+                        return Err(UnknownOperatorError::diagnostic(location, "type")
+                            .with_note("Strings can only be joined with '+' and compared")
+                            .into())
+                    }
+                    _ => {}
+                }
+                match op.compares() {
+                    true => Ok(Ty::Con(ReturnType::Bool)),
+                    false => Ok(left_type),
+                }
+            }
+            Expr::UnaryOperation(UnaryOperator::Not, operand) => {
+                self.expect_bool(&operand, scope, "'!'")?;
+                Ok(Ty::Con(ReturnType::Bool))
+            }
+            Expr::UnaryOperation(op, operand) => {
+                let operand_type = self.infer(&operand, scope)?;
+                if let Ty::Con(ReturnType::String | ReturnType::Bool) | Ty::Array(_) | Ty::Fn(..) =
+                    self.resolve(&operand_type)
+                {
+                    let note = format!(
+                        "'{}' only works on Ints and Floats, this is {}",
+                        op.symbol(),
+                        self.describe(&operand_type)
+                    );
+                    return Err(UnknownOperatorError::diagnostic(location, "type")
+                        .with_note(&note)
+                        .into());
+                }
+                Ok(operand_type)
+            }
+            Expr::ChainExpression(links) => {
+                let chain_type = self.fresh();
+                // the first link that pinned the type down, for the error label
+                let mut first_known: Option<Location> = None;
+                for link in links {
+                    let link_type = self.infer(&link, scope)?;
+                    let location = match link.get_expr() {
+                        Expr::WhenExpression(_, result) => result.get_location(),
+                        _ => link.get_location(),
+                    };
+                    if !self.unify(&chain_type, &link_type) {
+                        let label = format!("this link is {}", self.describe(&chain_type));
+                        let mut error =
+                            UnbalancedChainExpressionError::diagnostic(location, "type");
+                        if let Some(first) = first_known {
+                            error = error.with_label(first, &label);
+                        }
+                        return Err(error.into());
+                    }
+                    if first_known.is_none() && matches!(self.resolve(&chain_type), Ty::Con(_)) {
+                        first_known = Some(location);
+                    }
+                }
+                Ok(chain_type)
+            }
+            Expr::WhenExpression(predicate, result) => {
+                self.expect_bool(&predicate, scope, "'when'")?;
+                self.infer(&result, scope)
+            }
+            // locals come first, so a parameter can shadow a definition
+            Expr::FunctionCall(name, args) if scope.contains_key(&name) => {
+                let called = format!("'{}'", name);
+                let ret = self.apply(&scope[&name], &args, scope, &location, &called, None)?;
+                self.call_types.insert(location, ret.clone());
+                Ok(ret)
+            }
+            Expr::FunctionCall(name, args) => {
+                let Some((params, ret, declared)) = self.functions.get(&name).cloned() else {
+                    return UnknownFunctionError::while_checking(location);
+                };
+                let function = Ty::Fn(params, ret.into());
+                let called = format!("'{}'", name);
+                self.apply(&function, &args, scope, &location, &called, Some(declared))
+            }
+            Expr::Apply(function, args, _) => {
+                let function_type = self.infer(&function, scope)?;
+                let ret = self.apply(
+                    &function_type,
+                    &args,
+                    scope,
+                    &location,
+                    "the function",
+                    None,
+                )?;
+                self.call_types.insert(location, ret.clone());
+                Ok(ret)
+            }
+            // anything the body uses from around it gets captured, at whatever type it has here
+            Expr::Lambda(params, body, _, _) => {
+                let mut inner = scope.clone();
+                let mut args = vec![];
+                for param in &params {
+                    let ty = match &param.ty {
+                        Some(ty) => Ty::from(ty.base()),
+                        None => self.fresh(),
+                    };
+                    inner.insert(param.name.clone(), ty.clone());
+                    args.push(ty);
+                }
+                let ret = self.infer(&body, &inner)?;
+
+                let bound: Vec<String> = params.into_iter().map(|p| p.name).collect();
+                let mut free = vec![];
+                free_variables(&body, &bound, &mut free);
+                let captures = free
+                    .into_iter()
+                    .filter_map(|name| scope.get(&name).map(|ty| (name, ty.clone())))
+                    .collect();
+
+                let function = Ty::Fn(args, ret.into());
+                self.lambdas.insert(location, (function.clone(), captures));
+                Ok(function)
+            }
+            Expr::ArrayLiteral(elements, _) => {
+                let element_type = self.fresh();
+                for element in &elements {
+                    let this_type = self.infer(element, scope)?;
+                    if !self.unify(&element_type, &this_type) {
+                        let label = format!("this element is {}", self.describe(&element_type));
+                        return Err(
+                            ArrayElementError::diagnostic(element.get_location(), "type")
+                                .with_label(elements[0].get_location(), &label)
+                                .into(),
+                        );
+                    }
+                }
+                self.element_types.insert(location, element_type.clone());
+                Ok(Ty::Array(element_type.into()))
+            }
+            Expr::Index(array, index) => {
+                let element_type = self.expect_array(&array, scope)?;
+                let index_type = self.infer(&index, scope)?;
+                if !self.unify(&index_type, &Ty::Con(ReturnType::Int)) {
+                    let note = format!("the index is {}", self.describe(&index_type));
+                    return Err(IndexTypeError::diagnostic(index.get_location(), "type")
+                        .with_note(&note)
+                        .into());
+                }
+                Ok(element_type)
+            }
+            Expr::Length(array) => {
+                self.expect_array(&array, scope)?;
+                Ok(Ty::Con(ReturnType::Int))
+            }
+            Expr::Range(start, end) => {
+                for bound in [start, end] {
+                    let bound_type = self.infer(&bound, scope)?;
+                    if !self.unify(&bound_type, &Ty::Con(ReturnType::Int)) {
+                        let note = format!("this is {}", self.describe(&bound_type));
+                        return Err(RangeBoundError::diagnostic(bound.get_location(), "type")
+                            .with_note(&note)
+                            .into());
+                    }
+                }
+                Ok(Ty::Array(Ty::Con(ReturnType::Int).into()))
+            }
+            Expr::For(kind, variable, iterable, body, _) => {
+                let element_type = self.expect_array(&iterable, scope)?;
+                let mut inner = scope.clone();
+                inner.insert(variable, element_type);
+                let body_type = self.infer(&body, &inner)?;
+                self.element_types.insert(location, body_type.clone());
+                match kind {
+                    ForKind::Collect => Ok(Ty::Array(body_type.into())),
+                    ForKind::Sum => match self.resolve(&body_type) {
+                        Ty::Con(ReturnType::String | ReturnType::Bool)
+                        | Ty::Array(_)
+                        | Ty::Fn(..) => {
+                            let note = format!("the body is {}", self.describe(&body_type));
+                            Err(SumTypeError::diagnostic(body.get_location(), "type")
+                                .with_note(&note)
+                                .into())
+                        }
+                        _ => Ok(body_type),
+                    },
+                }
+            }
+            // the value can't see its own name, only the body can
+            Expr::Let(name, value, body) => {
+                let value_type = self.infer(&value, scope)?;
+                let mut inner = scope.clone();
+                inner.insert(name, value_type);
+                self.infer(&body, &inner)
+            }
+            Expr::FunctionDefinition(..) | Expr::ExternDeclaration(..) => {
+                DeclarationError::while_checking(location)
+            }
+            // only closure conversion makes these, after everything's been checked
+            Expr::Closure(..) => DeclarationError::while_checking(location),
+        }
+    }
+
+    // calls `function` with `args`, where `called` is how the notes refer to it and
+    // `declared` is where it was defined, if it has a name
+    fn apply(
+        &mut self,
+        function: &Ty,
+        args: &[LocatedExpr],
+        scope: &Scope,
+        location: &Location,
+        called: &str,
+        declared: Option<Location>,
+    ) -> Result<Ty> {
+        // nothing's known about it yet, so it's whatever these arguments make it
+        if let Ty::Var(_) = self.resolve(function) {
+            let params = args.iter().map(|_| self.fresh()).collect();
+            let ret = self.fresh();
+            self.unify(function, &Ty::Fn(params, Box::new(ret)));
+        }
+        let with_label = |error: Diagnostic| match &declared {
+            Some(declared) => error.with_label(declared.clone(), "function declared here"),
+            None => error,
+        };
+        let Ty::Fn(params, ret) = self.resolve(function) else {
+            let note = format!("{} is {}", called, self.describe(function));
+            return Err(NotFunctionError::diagnostic(location.clone(), "type")
+                .with_note(&note)
+                .into());
+        };
+        if params.len() != args.len() {
+            let note = format!(
+                "{} takes {} argument(s), got {}",
+                called,
+                params.len(),
+                args.len()
+            );
+            let error = ArgumentCountError::diagnostic(location.clone(), "type").with_note(&note);
+            return Err(with_label(error).into());
+        }
+        for (arg, param) in args.iter().zip(&params) {
+            let arg_type = self.infer(arg, scope)?;
+            if !self.unify(param, &arg_type) {
+                let note = format!(
+                    "{} expects {} here, got {}",
+                    called,
+                    self.describe(param),
+                    self.describe(&arg_type)
+                );
+                let error =
+                    ArgumentTypeError::diagnostic(arg.get_location(), "type").with_note(&note);
+                return Err(with_label(error).into());
+            }
+        }
+        Ok(*ret)
+    }
+
+    // `when` predicates and what `&&`, `||` and `!` work on
+    fn expect_bool(&mut self, condition: &LocatedExpr, scope: &Scope, used_by: &str) -> Result<()> {
+        let condition_type = self.infer(condition, scope)?;
+        if !self.unify(&condition_type, &Ty::Con(ReturnType::Bool)) {
+            let note = format!(
+                "{} needs a Bool, this is {}",
+                used_by,
+                self.describe(&condition_type)
+            );
+            return Err(
+                ConditionTypeError::diagnostic(condition.get_location(), "type")
+                    .with_note(&note)
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    // infers `array` and makes sure it is one, giving back the element type
+    fn expect_array(&mut self, array: &LocatedExpr, scope: &Scope) -> Result<Ty> {
+        let array_type = self.infer(array, scope)?;
+        let element_type = self.fresh();
+        if !self.unify(&array_type, &Ty::Array(element_type.clone().into())) {
+            let note = format!("this is {}", self.describe(&array_type));
+            return Err(NotArrayError::diagnostic(array.get_location(), "type")
+                .with_note(&note)
+                .into());
+        }
+        Ok(element_type)
+    }
+
+    fn annotate(&self, def: LocatedExpr) -> LocatedExpr {
+        match def.get_expr() {
+            Expr::FunctionDefinition(name, params, body, declared, _) => {
+                let signature = self.signature(&name);
+                let body = self.annotate_expr(*body);
+                LocatedExpr::new(
+                    Expr::FunctionDefinition(name, params, body.into(), declared, signature),
+                    def.get_location(),
+                )
+            }
+            _ => def,
+        }
+    }
+
+    // fills in the element and body types `infer` recorded for everything under `expr`
+    fn annotate_expr(&self, expr: LocatedExpr) -> LocatedExpr {
+        let location = expr.get_location();
+        let boxed = |e: Box<LocatedExpr>| Box::new(self.annotate_expr(*e));
+        let all = |es: Vec<LocatedExpr>| es.into_iter().map(|e| self.annotate_expr(e)).collect();
+        let annotated = match expr.get_expr() {
+            Expr::ArrayLiteral(elements, _) => {
+                let element_type = self
+                    .element_types
+                    .get(&location)
+                    .map(|ty| self.concrete(ty));
+                Expr::ArrayLiteral(all(elements), element_type)
+            }
+            Expr::For(kind, variable, iterable, body, _) => {
+                let body_type = self
+                    .element_types
+                    .get(&location)
+                    .map(|ty| self.concrete(ty));
+                Expr::For(kind, variable, boxed(iterable), boxed(body), body_type)
+            }
+            Expr::Range(start, end) => Expr::Range(boxed(start), boxed(end)),
+            Expr::ChainExpression(links) => Expr::ChainExpression(all(links)),
+            Expr::BinaryOperation(op, left, right) => {
+                Expr::BinaryOperation(op, boxed(left), boxed(right))
+            }
+            Expr::UnaryOperation(op, operand) => Expr::UnaryOperation(op, boxed(operand)),
+            Expr::WhenExpression(predicate, result) => {
+                Expr::WhenExpression(boxed(predicate), boxed(result))
+            }
+            // a call through a variable is an `Apply` from here on
+            Expr::FunctionCall(name, args) => match self.call_types.get(&location) {
+                Some(ret) => {
+                    let function = LocatedExpr::new(Expr::VariableRef(name), location.clone());
+                    Expr::Apply(function.into(), all(args), Some(self.concrete(ret)))
+                }
+                None => Expr::FunctionCall(name, all(args)),
+            },
+            Expr::Apply(function, args, _) => {
+                let ret = self.call_types.get(&location).map(|ty| self.concrete(ty));
+                Expr::Apply(boxed(function), all(args), ret)
+            }
+            Expr::Lambda(params, body, _, _) => match self.lambdas.get(&location) {
+                Some((function, captures)) => {
+                    let ReturnType::Function(args, ret) = self.concrete(function) else {
+                        unreachable!("lambdas are always inferred as functions")
+                    };
+                    let captures = captures
+                        .iter()
+                        .map(|(name, ty)| (name.clone(), self.concrete(ty)))
+                        .collect();
+                    let signature = Signature { args, ret: *ret };
+                    Expr::Lambda(params, boxed(body), Some(signature), captures)
+                }
+                None => Expr::Lambda(params, boxed(body), None, vec![]),
+            },
+            Expr::Index(array, index) => Expr::Index(boxed(array), boxed(index)),
+            Expr::Length(array) => Expr::Length(boxed(array)),
+            Expr::Let(name, value, body) => Expr::Let(name, boxed(value), boxed(body)),
+            other => other,
+        };
+        LocatedExpr::new(annotated, location)
+    }
+
+    fn fresh(&mut self) -> Ty {
+        self.bindings.push(None);
+        Ty::Var(self.bindings.len() - 1)
+    }
+
+    fn resolve(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(var) => match &self.bindings[*var] {
+                Some(bound) => self.resolve(bound),
+                None => ty.clone(),
+            },
+            Ty::Con(_) | Ty::Array(_) | Ty::Fn(..) => ty.clone(),
+        }
+    }
+
+    // false if the two can't be the same type
+    fn unify(&mut self, a: &Ty, b: &Ty) -> bool {
+        match (self.resolve(a), self.resolve(b)) {
+            (Ty::Var(x), Ty::Var(y)) if x == y => true,
+            (Ty::Var(var), other) | (other, Ty::Var(var)) => {
+                // `x` can't be bound to `[x]`
+                if self.occurs(var, &other) {
+                    return false;
+                }
+                self.bindings[var] = Some(other);
+                true
+            }
+            (Ty::Array(x), Ty::Array(y)) => self.unify(&x, &y),
+            (Ty::Fn(x_args, x_ret), Ty::Fn(y_args, y_ret)) => {
+                x_args.len() == y_args.len()
+                    && x_args.iter().zip(&y_args).all(|(x, y)| self.unify(x, y))
+                    && self.unify(&x_ret, &y_ret)
+            }
+            (Ty::Con(x), Ty::Con(y)) => x == y,
+            _ => false,
+        }
+    }
+
+    fn occurs(&self, var: usize, ty: &Ty) -> bool {
+        match self.resolve(ty) {
+            Ty::Var(other) => other == var,
+            Ty::Array(element) => self.occurs(var, &element),
+            Ty::Fn(args, ret) => {
+                args.iter().any(|arg| self.occurs(var, arg)) || self.occurs(var, &ret)
+            }
+            Ty::Con(_) => false,
+        }
+    }
+
+    // anything that can't be inferred falls back to a float
+    fn concrete(&self, ty: &Ty) -> ReturnType {
+        match self.resolve(ty) {
+            Ty::Con(return_type) => return_type,
+            Ty::Array(element) => ReturnType::Array(self.concrete(&element).into()),
+            Ty::Fn(args, ret) => ReturnType::Function(
+                args.iter().map(|arg| self.concrete(arg)).collect(),
+                self.concrete(&ret).into(),
+            ),
+            Ty::Var(_) => ReturnType::Float,
+        }
+    }
+
+    fn describe(&self, ty: &Ty) -> String {
+        match self.resolve(ty) {
+            Ty::Var(_) => "unknown".to_string(),
+            Ty::Array(element) => format!("[{}]", self.describe(&element)),
+            Ty::Fn(args, ret) => {
+                let args: Vec<String> = args.iter().map(|arg| self.describe(arg)).collect();
+                format!("({}) -> {}", args.join(", "), self.describe(&ret))
+            }
+            Ty::Con(return_type) => return_type.to_string(),
+        }
+    }
+}
+
+// names `expr` uses that it doesn't bind itself and that aren't in `bound`, in the
+// order they first show up; calls count too, since the name might be a local function
+fn free_variables(expr: &LocatedExpr, bound: &[String], found: &mut Vec<String>) {
+    let note = |name: String, found: &mut Vec<String>| {
+        if !bound.contains(&name) && !found.contains(&name) {
+            found.push(name);
+        }
+    };
+    let within = |name: &str| -> Vec<String> {
+        let mut inner = bound.to_vec();
+        inner.push(name.to_string());
+        inner
+    };
+    match expr.get_expr() {
+        Expr::VariableRef(name) => note(name, found),
+        Expr::FunctionCall(name, args) => {
+            note(name, found);
+            args.iter()
+                .for_each(|arg| free_variables(arg, bound, found));
+        }
+        Expr::Let(name, value, body) => {
+            free_variables(&value, bound, found);
+            free_variables(&body, &within(&name), found);
+        }
+        Expr::For(_, variable, iterable, body, _) => {
+            free_variables(&iterable, bound, found);
+            free_variables(&body, &within(&variable), found);
+        }
+        Expr::Lambda(params, body, ..) => {
+            let mut inner = bound.to_vec();
+            inner.extend(params.into_iter().map(|p| p.name));
+            free_variables(&body, &inner, found);
+        }
+        Expr::Apply(function, args, _) => {
+            free_variables(&function, bound, found);
+            args.iter()
+                .for_each(|arg| free_variables(arg, bound, found));
+        }
+        Expr::ChainExpression(all) | Expr::ArrayLiteral(all, _) => {
+            all.iter().for_each(|e| free_variables(e, bound, found));
+        }
+        Expr::BinaryOperation(_, first, second)
+        | Expr::WhenExpression(first, second)
+        | Expr::Index(first, second)
+        | Expr::Range(first, second) => {
+            free_variables(&first, bound, found);
+            free_variables(&second, bound, found);
+        }
+        Expr::UnaryOperation(_, inner) | Expr::Length(inner) => {
+            free_variables(&inner, bound, found)
+        }
+        Expr::Closure(_, captures) => captures.into_iter().for_each(|name| note(name, found)),
+        Expr::NumberLiteral(..)
+        | Expr::StringLiteral(_)
+        | Expr::BoolLiteral(_)
+        | Expr::FunctionDefinition(..)
+        | Expr::ExternDeclaration(..) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn check(
+        file: &str,
+    ) -> (
+        TypeChecker,
+        std::result::Result<Vec<LocatedExpr>, Vec<Diagnostic>>,
+    ) {
+        let tree = Parser::new(&format!("src/tests/{}.txt", file))
+            .unwrap()
+            .run()
+            .unwrap();
+        let mut checker = TypeChecker::new();
+        let result = checker.check(tree);
+        (checker, result)
+    }
+
+    fn error_lines(
+        result: std::result::Result<Vec<LocatedExpr>, Vec<Diagnostic>>,
+    ) -> Vec<(usize, String)> {
+        result
+            .unwrap_err()
+            .iter()
+            .map(|e| (e.get_location().unwrap().get_line(), e.get_message()))
+            .collect()
+    }
+
+    #[test]
+    fn signatures() {
+        let signatures: Vec<Signature> = ["fib", "arithmetic"]
+            .iter()
+            .flat_map(|file| check(file).1.unwrap())
+            .filter_map(|def| match def.get_expr() {
+                Expr::FunctionDefinition(_, _, _, _, signature) => signature,
+                _ => None,
+            })
+            .collect();
+        let signature = |args, ret| Signature { args, ret };
+        assert_eq!(
+            signatures,
+            vec![
+                signature(vec![ReturnType::Int], ReturnType::Int),
+                signature(vec![], ReturnType::Int),
+                signature(vec![ReturnType::Float], ReturnType::Float),
+                signature(vec![], ReturnType::Float),
+            ]
+        );
+    }
+
+    #[test]
+    fn recursion() {
+        let (checker, result) = check("recursion");
+        assert!(result.is_ok());
+        let signature = |name| checker.signature(name).unwrap();
+        // both are used before they're defined, and call each other
+        assert_eq!(signature("even").args, vec![ReturnType::Int]);
+        assert_eq!(signature("even").ret, ReturnType::Int);
+        assert_eq!(signature("odd").ret, ReturnType::Int);
+        assert_eq!(signature("entry").ret, ReturnType::Int);
+    }
+
+    #[test]
+    fn monomorphic() {
+        let (checker, result) = check("monomorphic");
+        let signature = |name| checker.signature(name).unwrap();
+        assert_eq!(signature("id").to_string(), "(Int) -> Int");
+        assert_eq!(signature("unused").to_string(), "(Float) -> Float");
+        assert_eq!(
+            error_lines(result),
+            vec![(5, "mismatched argument type!".to_string())]
+        );
+    }
+
+    #[test]
+    fn return_annotations() {
+        let (checker, result) = check("returns");
+        assert_eq!(
+            error_lines(result),
+            vec![(8, "return type doesn't match the declaration!".to_string())]
+        );
+        let ret = |name| checker.signature(name).unwrap().ret;
+        assert_eq!(ret("fib"), ReturnType::Int);
+        assert_eq!(ret("forever"), ReturnType::String);
+        assert_eq!(ret("half"), ReturnType::Float);
+        assert_eq!(ret("entry"), ReturnType::Int);
+    }
+
+    #[test]
+    fn arrays() {
+        let (checker, result) = check("arrays");
+        let int_array = ReturnType::Array(ReturnType::Int.into());
+        assert_eq!(checker.signature("sum").unwrap().args[0], int_array);
+        assert_eq!(
+            checker.signature("corners").unwrap().args[0],
+            ReturnType::Array(int_array.clone().into())
+        );
+
+        // every literal knows its element type afterwards, `[]` falls back to floats
+        let mut element_types = vec![];
+        fn collect(expr: &LocatedExpr, found: &mut Vec<Option<ReturnType>>) {
+            match expr.get_expr() {
+                Expr::ArrayLiteral(elements, element_type) => {
+                    found.push(element_type);
+                    elements.iter().for_each(|e| collect(e, found));
+                }
+                Expr::FunctionDefinition(_, _, body, ..) => collect(&body, found),
+                Expr::BinaryOperation(_, left, right) => {
+                    collect(&left, found);
+                    collect(&right, found);
+                }
+                Expr::FunctionCall(_, args) => args.iter().for_each(|a| collect(a, found)),
+                Expr::Length(array) => collect(&array, found),
+                _ => {}
+            }
+        }
+        collect(&result.unwrap()[2], &mut element_types);
+        assert_eq!(
+            element_types,
+            vec![
+                Some(ReturnType::Int),
+                Some(int_array),
+                Some(ReturnType::Int),
+                Some(ReturnType::Int),
+                Some(ReturnType::Float),
+            ]
+        );
+    }
+
+    #[test]
+    fn array_errors() {
+        assert_eq!(
+            error_lines(check("array_errors").1),
+            vec![
+                (
+                    0,
+                    "array elements don't all have the same type!".to_string()
+                ),
+                (2, "arrays can only be indexed with an Int!".to_string()),
+                (4, "expected an array!".to_string()),
+                (6, "unknown operator!".to_string()),
+                (8, "expected an array!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unary() {
+        let (checker, result) = check("unary");
+        assert!(result.is_ok());
+        assert_eq!(checker.signature("power").unwrap().ret, ReturnType::Int);
+        assert_eq!(
+            checker.signature("offset").unwrap().args,
+            vec![ReturnType::Int]
+        );
+
+        assert_eq!(
+            error_lines(check("unary_errors").1),
+            vec![
+                (0, "unknown operator!".to_string()),
+                (2, "conditions have to be Bools!".to_string()),
+                (4, "unknown operator!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bools() {
+        let (checker, result) = check("bools");
+        assert!(result.is_ok());
+        let signature = |name| checker.signature(name).unwrap();
+        assert_eq!(
+            signature("positive"),
+            Signature {
+                args: vec![ReturnType::Int],
+                ret: ReturnType::Bool,
+            }
+        );
+        assert_eq!(signature("flipped").args, vec![ReturnType::Bool]);
+        assert_eq!(signature("pick").args[0], ReturnType::Bool);
+    }
+
+    #[test]
+    fn strings() {
+        let (checker, result) = check("string_errors");
+        assert_eq!(checker.signature("joined").unwrap().ret, ReturnType::String);
+        assert_eq!(checker.signature("compared").unwrap().ret, ReturnType::Bool);
+        assert_eq!(
+            error_lines(result),
+            vec![
+                (4, "unknown operator!".to_string()),
+                (6, "unknown operator!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn logic() {
+        let (checker, result) = check("logic");
+        assert!(result.is_ok());
+        let signature = |name| checker.signature(name).unwrap();
+        assert_eq!(signature("safe").ret, ReturnType::Bool);
+        assert_eq!(
+            signature("mixed"),
+            Signature {
+                args: vec![ReturnType::Float],
+                ret: ReturnType::Bool,
+            }
+        );
+        assert_eq!(signature("count").args, vec![ReturnType::Bool]);
+
+        assert_eq!(
+            error_lines(check("logic_errors").1),
+            vec![
+                (0, "conditions have to be Bools!".to_string()),
+                (2, "conditions have to be Bools!".to_string()),
+                (4, "conditions have to be Bools!".to_string()),
+                (6, "unknown operator!".to_string()),
+                (8, "only Ints and Floats can be summed!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lambdas() {
+        let (checker, result) = check("lambdas");
+        let int_to_int = ReturnType::Function(vec![ReturnType::Int], ReturnType::Int.into());
+        let signature = |name| checker.signature(name).unwrap();
+        assert_eq!(
+            signature("twice").args,
+            vec![int_to_int.clone(), ReturnType::Int]
+        );
+        assert_eq!(signature("adder").ret, int_to_int);
+        assert_eq!(
+            signature("curried").to_string(),
+            "() -> (Int, Int) -> (Int) -> Int"
+        );
+
+        // every lambda knows its own signature and what it took from around it
+        let tree = result.unwrap();
+        let body_of = |name: &str| {
+            tree.iter()
+                .find_map(|def| match def.get_expr() {
+                    Expr::FunctionDefinition(n, _, body, ..) if n == name => Some(body),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let Expr::Let(_, lambda, rest) = body_of("scaled").get_expr() else {
+            panic!("expected a binding");
+        };
+        let Expr::Lambda(_, _, Some(signature), captures) = lambda.get_expr() else {
+            panic!("expected a typed lambda");
+        };
+        assert_eq!(signature.to_string(), "(Int) -> Int");
+        assert_eq!(captures, vec![("factor".to_string(), ReturnType::Int)]);
+
+        // and calling a parameter or a local goes through it as a value
+        let Expr::For(_, _, iterable, ..) = rest.get_expr() else {
+            panic!("expected a loop");
+        };
+        let Expr::FunctionCall(_, args) = iterable.get_expr() else {
+            panic!("expected a call");
+        };
+        assert!(matches!(args[0].get_expr(), Expr::VariableRef(_)));
+        assert!(matches!(
+            body_of("twice").get_expr(),
+            Expr::Apply(_, _, Some(ReturnType::Int))
+        ));
+
+        assert_eq!(
+            error_lines(check("lambda_errors").1),
+            vec![
+                (0, "only functions can be called!".to_string()),
+                (2, "mismatched argument count!".to_string()),
+                (4, "unknown operator!".to_string()),
+                (8, "mismatched argument type!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn loops() {
+        let (checker, result) = check("loops");
+        assert!(result.is_ok());
+        let signature = |name| checker.signature(name).unwrap();
+        assert_eq!(
+            signature("squares"),
+            Signature {
+                args: vec![ReturnType::Int],
+                ret: ReturnType::Array(ReturnType::Int.into()),
+            }
+        );
+        assert_eq!(signature("total").ret, ReturnType::Float);
+        assert_eq!(signature("nested").ret, ReturnType::Int);
+
+        assert_eq!(
+            error_lines(check("loop_errors").1),
+            vec![
+                (0, "only Ints and Floats can be summed!".to_string()),
+                (2, "range bounds have to be Ints!".to_string()),
+                (4, "expected an array!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bindings() {
+        let (checker, result) = check("bindings");
+        assert!(result.is_ok());
+        assert_eq!(
+            checker.signature("area").unwrap(),
+            Signature {
+                args: vec![ReturnType::Int, ReturnType::Int],
+                ret: ReturnType::Int,
+            }
+        );
+
+        // a binding isn't visible in its own value, or anywhere past its body
+        assert_eq!(
+            error_lines(check("binding_errors").1),
+            vec![
+                (0, "unknown variable!".to_string()),
+                (2, "unknown variable!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn call_errors() {
+        assert_eq!(
+            error_lines(check("calls").1),
+            vec![
+                (2, "mismatched argument count!".to_string()),
+                (4, "mismatched argument type!".to_string()),
+                (6, "unknown function!".to_string()),
+                (8, "unknown variable!".to_string()),
+                (10, "chain does not have consistent types!".to_string()),
+                (14, "mismatched argument type!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let (checker, result) = check("duplicates");
+        assert_eq!(
+            error_lines(result),
+            vec![(2, "function defined more than once!".to_string())]
+        );
+        // the first definition is the one that sticks
+        assert_eq!(checker.signature("twice").unwrap().ret, ReturnType::Int);
+    }
+
+    #[test]
+    fn entry_points() {
+        let (checker, result) = check("entries");
+        assert!(result.is_ok());
+        assert!(checker.check_entry("entry").is_ok());
+
+        let line = |name| {
+            let error = Diagnostic::from(checker.check_entry(name).unwrap_err());
+            assert_eq!(error.get_message(), "bad entry point!");
+            error.get_location().map(|l| l.get_line())
+        };
+        // `start` would be exported over the `entry` that's already there
+        assert_eq!(line("start"), Some(2));
+        assert_eq!(line("twice"), Some(4));
+        assert_eq!(line("nowhere"), None);
+    }
+
+    #[test]
+    fn externs() {
+        let (checker, result) = check("externs");
+        assert!(result.is_ok());
+        let float = |args: usize| Signature {
+            args: vec![ReturnType::Float; args],
+            ret: ReturnType::Float,
+        };
+        assert_eq!(checker.signature("pow").unwrap(), float(2));
+        // `t` is only known through the call to `sin`
+        assert_eq!(checker.signature("wave").unwrap(), float(1));
+    }
+}
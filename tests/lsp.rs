@@ -0,0 +1,253 @@
+use rfx::{
+    json::Json,
+    lsp::{read_message, write_message},
+};
+use std::{
+    io::BufReader,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+// drives a real `fx-lsp` over its stdin and stdout, like an editor would
+struct Client {
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    next_id: i64,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_fx-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Client {
+            child,
+            input,
+            output,
+            next_id: 0,
+        };
+        let initialized = client.request("initialize", Json::object(vec![]));
+        assert!(initialized.get("capabilities").is_some());
+        client.notify("initialized", Json::object(vec![]));
+        client
+    }
+
+    fn send(&mut self, message: Json) {
+        write_message(&mut self.input, &message).unwrap();
+    }
+
+    fn receive(&mut self) -> Json {
+        let body = read_message(&mut self.output).unwrap().unwrap();
+        Json::parse(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Json) {
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]));
+    }
+
+    // the whole response, errors and all
+    fn call(&mut self, method: &str, params: Json) -> Json {
+        self.next_id += 1;
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", Json::Int(self.next_id)),
+            ("method", method.into()),
+            ("params", params),
+        ]));
+        let response = self.receive();
+        assert_eq!(response.get("id"), Some(&Json::Int(self.next_id)));
+        response
+    }
+
+    fn request(&mut self, method: &str, params: Json) -> Json {
+        self.call(method, params).get("result").unwrap().clone()
+    }
+
+    // opens (or replaces) a file and waits for its diagnostics
+    fn open(&mut self, uri: &str, text: &str) -> Vec<Json> {
+        self.notify(
+            "textDocument/didOpen",
+            Json::object(vec![(
+                "textDocument",
+                Json::object(vec![
+                    ("uri", uri.into()),
+                    ("languageId", "fx".into()),
+                    ("version", Json::Int(1)),
+                    ("text", text.into()),
+                ]),
+            )]),
+        );
+        self.diagnostics(uri)
+    }
+
+    fn change(&mut self, uri: &str, text: &str) -> Vec<Json> {
+        self.notify(
+            "textDocument/didChange",
+            Json::object(vec![
+                (
+                    "textDocument",
+                    Json::object(vec![("uri", uri.into()), ("version", Json::Int(2))]),
+                ),
+                (
+                    "contentChanges",
+                    Json::Array(vec![Json::object(vec![("text", text.into())])]),
+                ),
+            ]),
+        );
+        self.diagnostics(uri)
+    }
+
+    fn diagnostics(&mut self, uri: &str) -> Vec<Json> {
+        let notification = self.receive();
+        assert_eq!(
+            notification.get("method").and_then(Json::as_str),
+            Some("textDocument/publishDiagnostics")
+        );
+        let params = notification.get("params").unwrap();
+        assert_eq!(params.get("uri").and_then(Json::as_str), Some(uri));
+        params
+            .get("diagnostics")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .to_vec()
+    }
+
+    fn at(uri: &str, line: usize, character: usize) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", uri.into())])),
+            (
+                "position",
+                Json::object(vec![("line", line.into()), ("character", character.into())]),
+            ),
+        ])
+    }
+
+    fn stop(mut self) -> bool {
+        assert_eq!(self.request("shutdown", Json::Null), Json::Null);
+        self.notify("exit", Json::Null);
+        self.child.wait().unwrap().success()
+    }
+}
+
+fn uri(file: &str) -> String {
+    format!("file://{}/src/tests/{}", env!("CARGO_MANIFEST_DIR"), file)
+}
+
+// (line, character) of a range's start
+fn start(range: &Json) -> (i64, i64) {
+    let start = range.get("start").unwrap();
+    (
+        start.get("line").and_then(Json::as_int).unwrap(),
+        start.get("character").and_then(Json::as_int).unwrap(),
+    )
+}
+
+fn message(diagnostic: &Json) -> &str {
+    diagnostic.get("message").and_then(Json::as_str).unwrap()
+}
+
+#[test]
+fn diagnostics() {
+    let mut client = Client::start();
+    let file = uri("scratch.txt");
+
+    let errors = client.open(&file, "let good(n) = n + 1\n\nlet bad = 1\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(message(&errors[0]), "expected function declaration!");
+    assert_eq!(start(errors[0].get("range").unwrap()), (2, 8));
+
+    // type errors show up once it parses, with their notes
+    let errors = client.change(&file, "let good(n) = n + 1\n\nlet bad() = good(\"one\")\n");
+    assert_eq!(errors.len(), 1);
+    assert!(message(&errors[0]).starts_with("mismatched argument type!"));
+    assert_eq!(start(errors[0].get("range").unwrap()).0, 2);
+
+    assert!(client.change(&file, "let good(n) = n + 1\n").is_empty());
+    assert!(client.stop());
+}
+
+#[test]
+fn navigation() {
+    let mut client = Client::start();
+    let file = uri("scratch.txt");
+    let text = "let square(x) = x * x\n\nlet entry() = square(3) + sq\n";
+    assert!(!client.open(&file, text).is_empty());
+
+    // `sq` doesn't check, but everything else can still be found
+    let found = client.request("textDocument/definition", Client::at(&file, 2, 16));
+    assert_eq!(found.get("uri").and_then(Json::as_str), Some(file.as_str()));
+    assert_eq!(start(found.get("range").unwrap()), (0, 4));
+
+    let hover = client.request("textDocument/hover", Client::at(&file, 2, 14));
+    let contents = hover.get("contents").unwrap();
+    assert_eq!(
+        contents.get("value").and_then(Json::as_str),
+        Some("```fx\nlet square(x: Int): Int\n```")
+    );
+    // nothing's called `x` at the top level
+    assert_eq!(
+        client.request("textDocument/hover", Client::at(&file, 0, 16)),
+        Json::Null
+    );
+
+    let completions = client.request("textDocument/completion", Client::at(&file, 2, 28));
+    let labels: Vec<&str> = completions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item.get("label").and_then(Json::as_str).unwrap())
+        .collect();
+    assert_eq!(labels, vec!["square", "entry"]);
+    assert!(client.stop());
+}
+
+#[test]
+fn imports() {
+    let mut client = Client::start();
+    let file = uri("imports/main.txt");
+    let text = std::fs::read_to_string(&file["file://".len()..]).unwrap();
+    assert!(client.open(&file, &text).is_empty());
+
+    // `square` lives in math.txt, next to main.txt
+    let found = client.request("textDocument/definition", Client::at(&file, 3, 15));
+    assert_eq!(
+        found.get("uri").and_then(Json::as_str),
+        Some(uri("imports/math.txt").as_str())
+    );
+    let hover = client.request("textDocument/hover", Client::at(&file, 3, 28));
+    let value = hover.get("contents").and_then(|c| c.get("value")).unwrap();
+    assert!(value.as_str().unwrap().contains("let area("));
+
+    let errors = client.change(&file, "import \"nowhere.txt\"\n\nlet entry() = 1\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        message(&errors[0]).lines().next(),
+        Some("couldn't import file!")
+    );
+    assert!(client.stop());
+}
+
+#[test]
+fn protocol() {
+    let mut client = Client::start();
+    let response = client.call("workspace/symbol", Json::object(vec![]));
+    let error = response.get("error").unwrap();
+    assert_eq!(error.get("code").and_then(Json::as_int), Some(-32601));
+
+    // asking about a file that was never opened is fine
+    let nothing = client.request("textDocument/hover", Client::at(&uri("nope.txt"), 0, 0));
+    assert_eq!(nothing, Json::Null);
+
+    // exiting without shutting down first is an error
+    client.notify("exit", Json::Null);
+    assert!(!client.child.wait().unwrap().success());
+}